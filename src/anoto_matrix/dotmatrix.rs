@@ -1,20 +1,5 @@
 use ndarray::{Array3, s};
-use std::error::Error;
-use std::fmt;
-
-// Custom error type for decoding errors
-#[derive(Debug)]
-pub struct DecodingError {
-    message: String,
-}
-
-impl fmt::Display for DecodingError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Decoding error: {}", self.message)
-    }
-}
-
-impl Error for DecodingError {}
+use crate::error::AnotoError;
 
 // Anoto codec structure
 #[derive(Debug, Clone)]
@@ -188,15 +173,16 @@ impl AnotoCodec {
     }
 
     pub fn decode_position(&self, section: &Array3<i8>) -> Option<(i64, i64)> {
-        if section.dim() != (6, 6, 2) {
+        let order = section.dim().0;
+        if order == 0 || section.dim() != (order, order, 2) {
             return None;
         }
 
         // Extract the x-direction pattern from the first row
-        let x_pattern: Vec<i32> = (0..6).map(|i| section[[0, i, 0]] as i32).collect();
+        let x_pattern: Vec<i32> = (0..order).map(|i| section[[0, i, 0]] as i32).collect();
         
         // Extract the y-direction pattern from the first column  
-        let y_pattern: Vec<i32> = (0..6).map(|i| section[[i, 0, 1]] as i32).collect();
+        let y_pattern: Vec<i32> = (0..order).map(|i| section[[i, 0, 1]] as i32).collect();
 
         // Find the roll value for x-direction
         let x_roll = self.find_roll(&x_pattern)?;
@@ -215,7 +201,7 @@ impl AnotoCodec {
         // Try all possible roll values
         for roll in 0..mns_len {
             let rolled_mns = self.roll_mns(roll);
-            let rolled_pattern: Vec<i32> = rolled_mns.iter().take(6).map(|&x| x as i32).collect();
+            let rolled_pattern: Vec<i32> = rolled_mns.iter().take(pattern.len()).map(|&x| x as i32).collect();
             
             if rolled_pattern == pattern {
                 return Some(roll);
@@ -319,19 +305,23 @@ fn make_cyclic(seq: &[i8], order: usize) -> Vec<i8> {
     cyclic
 }
 
-pub fn gen_matrix(height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let bitmatrix = generate_matrix_only(height, width, sect_u, sect_v)?;
+pub fn gen_matrix(codec: &crate::codec::AnotoCodec, height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<(), AnotoError> {
+    let bitmatrix = generate_matrix_only(codec, height, width, sect_u, sect_v)?;
     save_generated_matrix(&bitmatrix, height, width, sect_u, sect_v)?;
     Ok(())
 }
 
-pub fn generate_matrix_only(height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<Array3<i32>, Box<dyn std::error::Error>> {
-    let codec = anoto_6x6_a4_fixed();
-    let bitmatrix = codec.encode_bitmatrix((height, width), (sect_u, sect_v));
-    Ok(bitmatrix)
+pub fn generate_matrix_only(codec: &crate::codec::AnotoCodec, height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<Array3<i32>, AnotoError> {
+    generate_matrix_with_codec(codec, height, width, sect_u, sect_v)
 }
 
-pub fn save_generated_matrix(bitmatrix: &Array3<i32>, height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<(), Box<dyn std::error::Error>> {
+/// Bits of the `codec` pattern of `height` x `width` dots at the origin of section (sect_u, sect_v),
+/// with a codec picked at runtime (see `codec::resolve_codec`).
+pub fn generate_matrix_with_codec(codec: &crate::codec::AnotoCodec, height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<Array3<i32>, AnotoError> {
+    Ok(codec.encode_bitmatrix((height, width), (sect_u, sect_v))?.mapv(|x| x as i32))
+}
+
+pub fn save_generated_matrix(bitmatrix: &Array3<i32>, height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<(), AnotoError> {
     let base_filename = format!("G__{}__{}__{}__{}", height, width, sect_u, sect_v);
 
     // Create output directory
@@ -352,12 +342,12 @@ pub fn save_generated_matrix(bitmatrix: &Array3<i32>, height: usize, width: usiz
     Ok(())
 }
 
-pub fn load_matrix_from_json(json_path: &str) -> std::result::Result<Array3<i32>, Box<dyn std::error::Error>> {
+pub fn load_matrix_from_json(json_path: &str) -> std::result::Result<Array3<i32>, AnotoError> {
     let bitmatrix = crate::persist_json::load_array3_from_json(json_path)?;
     Ok(bitmatrix)
 }
 
-pub fn load_matrix_from_txt(txt_path: &str) -> std::result::Result<Array3<i32>, Box<dyn std::error::Error>> {
+pub fn load_matrix_from_txt(txt_path: &str) -> std::result::Result<Array3<i32>, AnotoError> {
     use std::fs::File;
     use std::io::Read;
 
@@ -397,7 +387,7 @@ pub fn load_matrix_from_txt(txt_path: &str) -> std::result::Result<Array3<i32>,
     }
 
     if rows.is_empty() {
-        return Err("No data found in txt file".into());
+        return Err(AnotoError::parse(format!("No [x y] pairs found in {}", txt_path), 0, 0));
     }
 
     let height = rows.len();
@@ -413,7 +403,7 @@ pub fn load_matrix_from_txt(txt_path: &str) -> std::result::Result<Array3<i32>,
     Ok(bitmatrix)
 }
 
-pub fn save_matrix_from_json(bitmatrix: &Array3<i32>, json_path: &str) -> std::result::Result<(), Box<dyn std::error::Error>> {
+pub fn save_matrix_from_json(bitmatrix: &Array3<i32>, json_path: &str) -> std::result::Result<(), AnotoError> {
     let (height, width, _) = bitmatrix.dim();
     
     // Try to extract sect_u and sect_v from the filename if it follows G__ pattern
@@ -447,24 +437,206 @@ pub fn save_matrix_from_json(bitmatrix: &Array3<i32>, json_path: &str) -> std::r
     Ok(())
 }
 
-pub fn extract_6x6_section(bitmatrix: &Array3<i32>, pos: (i32, i32)) -> std::result::Result<(), Box<dyn std::error::Error>> {
+// Rows encoded per band when streaming a generated pattern to disk
+const STREAM_BAND_ROWS: usize = 64;
+// Above this many dots the PNG preview (which needs the whole matrix) is skipped
+const PREVIEW_MAX_DOTS: usize = 4_000_000;
+
+/// Encodes and writes a `height` x `width` pattern band by band, like `save_generated_matrix`
+/// but without ever holding the full matrix.
+pub fn save_generated_pattern(codec: &crate::codec::AnotoCodec, height: usize, width: usize, sect_u: i32, sect_v: i32) -> std::result::Result<(), AnotoError> {
+    let base_filename = format!("G__{}__{}__{}__{}", height, width, sect_u, sect_v);
+    let bands = || codec.row_bands((sect_u, sect_v), (0, 0), (width, height), STREAM_BAND_ROWS);
+
+    std::fs::create_dir_all("output")?;
+
+    crate::persist_json::save_tiles_as_json(bands()?, &base_filename)?;
+    crate::persist_json::save_tiles_as_txt(bands()?, &base_filename)?;
+
+    if height.saturating_mul(width) <= PREVIEW_MAX_DOTS {
+        let bitmatrix = codec.encode_patch((0, 0), (width, height), (sect_u % codec.mns_length as i32, sect_v % codec.mns_length as i32))?;
+        crate::make_plots::draw_dots(&bitmatrix, 1.0, &base_filename)?;
+    } else {
+        println!("Skipping PNG preview for {} dots (limit {})", height * width, PREVIEW_MAX_DOTS);
+    }
+
+    let pattern = crate::pdf_dotpaper::metadata::PatternInfo::generated(codec, (sect_u, sect_v), (0, 0));
+    crate::pdf_dotpaper::gen_pdf::gen_pdf_from_tiles(bands()?, (width, height), &format!("{}.pdf", base_filename), &crate::pdf_dotpaper::gen_pdf::PdfConfig::default(), &pattern)?;
+
+    Ok(())
+}
+
+/// Calibration sheet of `spec` with sections counting up from (sect_u, sect_v), saved as
+/// CAL__{sect_u}__{sect_v}.pdf.
+pub fn save_calibration_sheet(
+    codec: &crate::codec::AnotoCodec,
+    sect_u: i32,
+    sect_v: i32,
+    spec: &crate::pdf_dotpaper::gen_calibration::CalibrationSpec,
+) -> std::result::Result<Vec<crate::pdf_dotpaper::gen_calibration::CalibrationSwatch>, AnotoError> {
+    let filename = format!("CAL__{}__{}.pdf", sect_u, sect_v);
+    crate::pdf_dotpaper::gen_calibration::gen_calibration_pdf(codec, (sect_u, sect_v), spec, &filename)
+}
+
+/// Poster of `spec` in section (sect_u, sect_v) from pattern origin (0, 0), saved as
+/// P__{width}__{height}__{sect_u}__{sect_v}.pdf with the size in dots.
+pub fn save_poster(
+    codec: &crate::codec::AnotoCodec,
+    sect_u: i32,
+    sect_v: i32,
+    spec: &crate::pdf_dotpaper::gen_poster::PosterSpec,
+) -> std::result::Result<(String, crate::pdf_dotpaper::gen_poster::PosterLayout), AnotoError> {
+    let (width, height) = crate::pdf_dotpaper::gen_poster::poster_layout(spec, (0, 0))?.size;
+    let filename = format!("P__{}__{}__{}__{}.pdf", width, height, sect_u, sect_v);
+    let poster = crate::pdf_dotpaper::gen_poster::gen_poster_pdf(codec, (sect_u, sect_v), (0, 0), spec, &filename)?;
+    Ok((filename, poster))
+}
+
+/// Vector copy of a `save_generated_pattern` page for layout tools: `format` is "svg" or "eps".
+pub fn save_generated_vector(codec: &crate::codec::AnotoCodec, height: usize, width: usize, sect_u: i32, sect_v: i32, format: &str) -> std::result::Result<(), AnotoError> {
+    let filename = format!("G__{}__{}__{}__{}.{}", height, width, sect_u, sect_v, format);
+    let bands = codec.row_bands((sect_u, sect_v), (0, 0), (width, height), STREAM_BAND_ROWS)?;
+    let config = crate::pdf_dotpaper::gen_pdf::PdfConfig::default();
+    match format {
+        "svg" => crate::pdf_dotpaper::gen_svg::gen_svg_from_tiles(bands, (width, height), &filename, &config),
+        "eps" => crate::pdf_dotpaper::gen_eps::gen_eps_from_tiles(bands, (width, height), &filename, &config),
+        _ => Err(format!("Unknown vector format '{}', expected svg or eps", format).into()),
+    }
+}
+
+/// Writes `G__h__w__su__sv.{format}` (tiff or png) at the raster settings, streaming the pattern in bands.
+pub fn save_generated_raster(
+    codec: &crate::codec::AnotoCodec,
+    height: usize,
+    width: usize,
+    sect_u: i32,
+    sect_v: i32,
+    format: &str,
+    raster: &crate::pdf_dotpaper::gen_raster::RasterConfig,
+) -> std::result::Result<(), AnotoError> {
+    let filename = format!("G__{}__{}__{}__{}.{}", height, width, sect_u, sect_v, format);
+    let bands = codec.row_bands((sect_u, sect_v), (0, 0), (width, height), STREAM_BAND_ROWS)?;
+    let config = crate::pdf_dotpaper::gen_pdf::PdfConfig::default();
+    crate::pdf_dotpaper::gen_raster::gen_raster_from_tiles(bands, (width, height), &filename, &config, raster)
+}
+
+/// PDF of the page at `address` in `space`, saved as PAGE__{address}.pdf. Returns the page's section
+/// and pattern origin.
+pub fn save_page_pattern(
+    codec: &crate::codec::AnotoCodec,
+    space: &crate::codec::AddressSpace,
+    address: &crate::codec::PageAddress,
+) -> std::result::Result<crate::codec::SectionPosition, AnotoError> {
+    let (section, origin) = space.page_origin(address)?;
+    let filename = format!("PAGE__{}.pdf", address);
+    let bands = codec.row_bands(section, origin, space.page_size, STREAM_BAND_ROWS)?;
+    let pattern = crate::pdf_dotpaper::metadata::PatternInfo {
+        address: Some(*address),
+        ..crate::pdf_dotpaper::metadata::PatternInfo::generated(codec, section, origin)
+    };
+    crate::pdf_dotpaper::gen_pdf::gen_pdf_from_tiles(bands, space.page_size, &filename, &crate::pdf_dotpaper::gen_pdf::PdfConfig::default(), &pattern)?;
+    Ok((section, origin))
+}
+
+/// Checks a PDF written by `save_generated_pattern` (or reworked since) against a fresh encoding of
+/// the same pattern, page by page.
+pub fn verify_generated_pdf(
+    codec: &crate::codec::AnotoCodec,
+    path: &str,
+    height: usize,
+    width: usize,
+    sect_u: i32,
+    sect_v: i32,
+) -> std::result::Result<Vec<crate::pdf_dotpaper::read_pdf::PdfVerification>, AnotoError> {
+    let expected = generate_matrix_with_codec(codec, height, width, sect_u, sect_v)?;
+    crate::pdf_dotpaper::read_pdf::verify_pdf(path, &[expected], &crate::pdf_dotpaper::gen_pdf::PdfConfig::default())
+}
+
+/// `verify_generated_pdf` for a PDF carrying its pattern metadata: section, page origins, sizes and
+/// geometry all come from the file. Calibration sheets are checked swatch by swatch, each against
+/// its own section and settings. Refuses PDFs generated with a different codec than `codec`.
+pub fn verify_pdf_from_metadata(
+    codec: &crate::codec::AnotoCodec,
+    path: &str,
+) -> std::result::Result<(crate::pdf_dotpaper::metadata::PatternMetadata, Vec<crate::pdf_dotpaper::read_pdf::PdfVerification>), AnotoError> {
+    let metadata = crate::pdf_dotpaper::metadata::read_pattern_metadata(path)?.ok_or_else(|| format!("{} carries no pattern metadata", path))?;
+    if let Some(name) = metadata.pattern.codec.as_ref().filter(|name| name.as_str() != codec.name()) {
+        return Err(format!("{} was generated with codec '{}', not '{}'", path, name, codec.name()).into());
+    }
+    let encode = |section, origin, size| -> std::result::Result<Array3<i32>, AnotoError> {
+        let tile = codec.tiles(section, origin, size, size)?.next().ok_or("Page pattern could not be encoded")?;
+        Ok(tile.bits.mapv(|x| x as i32))
+    };
+
+    if !metadata.swatches.is_empty() {
+        let swatches = metadata.swatches.iter()
+            .map(|swatch| Ok(crate::pdf_dotpaper::read_pdf::PlacedPattern {
+                page: 0,
+                expected: encode(swatch.section, (0, 0), swatch.size)?,
+                config: swatch.config(&metadata.config),
+                centre: swatch.centre,
+            }))
+            .collect::<std::result::Result<Vec<_>, AnotoError>>()?;
+        let pages = crate::pdf_dotpaper::read_pdf::verify_placed_patterns(path, &swatches)?;
+        return Ok((metadata, pages));
+    }
+
+    let section = metadata.pattern.section.ok_or_else(|| format!("The metadata of {} does not name a section", path))?;
+    let mut expected = Vec::new();
+    for (page, &size) in metadata.page_sizes.iter().enumerate() {
+        let origin = metadata.origin(page).ok_or_else(|| format!("The metadata of {} has no page origin", path))?;
+        expected.push(encode(section, origin, size)?);
+    }
+    let pages = crate::pdf_dotpaper::read_pdf::verify_pdf(path, &expected, &metadata.config)?;
+    Ok((metadata, pages))
+}
+
+pub fn extract_6x6_section(bitmatrix: &Array3<i32>, pos: (i32, i32)) -> std::result::Result<(), AnotoError> {
+    extract_section(bitmatrix, pos, 6)
+}
+
+/// Saves the `order` x `order` section at `pos` as section_{row}_{col}.json/.txt, zeroed when out of bounds.
+pub fn extract_section(bitmatrix: &Array3<i32>, pos: (i32, i32), order: usize) -> std::result::Result<(), AnotoError> {
     let (rows, cols, _) = bitmatrix.dim();
-    let (row, col) = (pos.0 as usize, pos.1 as usize);
+    save_section((rows, cols), pos, order, |row, col| Ok(bitmatrix.slice(s![row..row + order, col..col + order, ..]).to_owned()))
+}
+
+/// `extract_section` for a pattern generated with `save_generated_pattern`, encoding only the window.
+pub fn extract_generated_section(codec: &crate::codec::AnotoCodec, height: usize, width: usize, section: (i32, i32), pos: (i32, i32)) -> std::result::Result<(), AnotoError> {
+    let order = codec.mns_order();
+    save_section((height, width), pos, order, |row, col| {
+        let mut tiles = codec.tiles(section, (col as i64, row as i64), (order, order), (order, order))?;
+        let tile = tiles.next().ok_or("Section window could not be encoded")?;
+        Ok(tile.bits.mapv(|x| x as i32))
+    })
+}
+
+fn save_section(
+    dims: (usize, usize),
+    pos: (i32, i32),
+    order: usize,
+    window: impl FnOnce(usize, usize) -> std::result::Result<Array3<i32>, AnotoError>,
+) -> std::result::Result<(), AnotoError> {
+    let (rows, cols) = dims;
+    let (row, col) = match (usize::try_from(pos.0), usize::try_from(pos.1)) {
+        (Ok(row), Ok(col)) => (row, col),
+        _ => return Err(format!("Section position ({}, {}) must not be negative", pos.0, pos.1).into()),
+    };
     
     println!("Matrix size [{}, {}]", rows, cols);
     println!("Requested position ({}, {})", row, col);
     
-    let max_row = rows.saturating_sub(6);
-    let max_col = cols.saturating_sub(6);
-    println!("Maximum 6x6 position for this matrix is ({}, {})", max_row, max_col);
+    let max_row = rows.saturating_sub(order);
+    let max_col = cols.saturating_sub(order);
+    println!("Maximum {}x{} position for this matrix is ({}, {})", order, order, max_row, max_col);
     
     // Create output directory
     std::fs::create_dir_all("output")?;
     
     if row > max_row || col > max_col {
-        println!("Position out of bounds, returning zeroed 6x6 section");
-        // Create a zeroed 6x6 section
-        let zeroed_section = Array3::<i32>::zeros((6, 6, 2));
+        println!("Position out of bounds, returning zeroed {}x{} section", order, order);
+        // Create a zeroed section
+        let zeroed_section = Array3::<i32>::zeros((order, order, 2));
         
         // Save the zeroed section
         let filename = format!("section_{}_{}", row, col);
@@ -474,13 +646,8 @@ pub fn extract_6x6_section(bitmatrix: &Array3<i32>, pos: (i32, i32)) -> std::res
         return Ok(());
     }
     
-    // Extract the 6x6 section
-    let section = bitmatrix.slice(s![
-        row..row + 6,
-        col..col + 6,
-        ..
-    ]);
-    let section = section.to_owned();
+    // Extract the section
+    let section = window(row, col)?;
     
     // Save the section
     let filename = format!("section_{}_{}", row, col);
@@ -490,7 +657,7 @@ pub fn extract_6x6_section(bitmatrix: &Array3<i32>, pos: (i32, i32)) -> std::res
     Ok(())
 }
 
-pub fn gen_matrix_from_json(json_path: &str) -> std::result::Result<(), Box<dyn std::error::Error>> {
+pub fn gen_matrix_from_json(json_path: &str) -> std::result::Result<(), AnotoError> {
     let bitmatrix = crate::persist_json::load_array3_from_json(json_path)?;
     let (height, width, _) = bitmatrix.dim();
     
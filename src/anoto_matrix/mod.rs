@@ -2,9 +2,20 @@ pub mod dotmatrix;
 pub use dotmatrix::gen_matrix;
 pub use dotmatrix::gen_matrix_from_json;
 pub use dotmatrix::generate_matrix_only;
+pub use dotmatrix::generate_matrix_with_codec;
 pub use dotmatrix::save_generated_matrix;
+pub use dotmatrix::save_generated_pattern;
+pub use dotmatrix::save_generated_vector;
+pub use dotmatrix::save_generated_raster;
+pub use dotmatrix::save_calibration_sheet;
+pub use dotmatrix::save_poster;
+pub use dotmatrix::save_page_pattern;
+pub use dotmatrix::verify_generated_pdf;
+pub use dotmatrix::verify_pdf_from_metadata;
 pub use dotmatrix::load_matrix_from_json;
 pub use dotmatrix::load_matrix_from_txt;
 pub use dotmatrix::save_matrix_from_json;
 pub use dotmatrix::extract_6x6_section;
+pub use dotmatrix::extract_section;
+pub use dotmatrix::extract_generated_section;
 pub use dotmatrix::anoto_6x6_a4_fixed;
\ No newline at end of file
@@ -1,5 +1,5 @@
 
-use serde_json::Value;
+use anoto_pdf::decode_utils::DirectionGrid;
 
 fn main() {
     let input = r#"[
@@ -106,8 +106,8 @@ fn main() {
 "#;
 
     println!("Attempting to parse input...");
-    match serde_json::from_str::<Value>(input) {
-        Ok(_) => println!("Success!"),
+    match DirectionGrid::parse(input) {
+        Ok(grid) => println!("Success! {}x{} grid", grid.width(), grid.height()),
         Err(e) => println!("Error: {}", e),
     }
 }
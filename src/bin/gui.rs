@@ -1,26 +1,33 @@
-use iced::widget::{button, column, container, row, scrollable, slider, text, text_editor, text_input, vertical_space, canvas};
+use iced::widget::{button, checkbox, column, container, progress_bar, row, scrollable, slider, text, text_editor, text_input, vertical_space, canvas};
 use iced::widget::image;
 use iced::{Element, Length, Task, Border, Color, Shadow, Point, Vector, Rectangle, Renderer, Theme, mouse};
 use iced::event;
 use iced_aw::spinner::Spinner;
-use anoto_pdf::pdf_dotpaper::gen_pdf::{PdfConfig, gen_pdf_from_matrix_data};
-use anoto_pdf::anoto_matrix::generate_matrix_only;
-use anoto_pdf::make_plots::{draw_preview_image, draw_dot_on_file, draw_dots_on_file};
+use anoto_pdf::pdf_dotpaper::gen_pdf::{PdfConfig, gen_pdf_bytes, gen_pdf_from_tiles_with_progress};
+use anoto_pdf::pdf_dotpaper::metadata::PatternInfo;
+use anoto_pdf::pdf_dotpaper::gen_eps::gen_eps_bytes;
+use anoto_pdf::pdf_dotpaper::gen_svg::gen_svg_bytes;
+use anoto_pdf::anoto_matrix::generate_matrix_with_codec;
+use anoto_pdf::make_plots::{draw_preview_png_bytes, draw_preview_image_with_progress, draw_dot_on_file, draw_dots_on_file};
+use anoto_pdf::progress::JobProgress;
 use anoto_pdf::controls::{anoto_control, page_layout_control, section_control};
-use tokio::sync::{oneshot, mpsc, Mutex};
+use tokio::sync::{broadcast, oneshot, mpsc, Mutex};
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use anoto_pdf::codec::anoto_6x6_a4_fixed;
+use anoto_pdf::AnotoError;
+use anoto_pdf::decode_utils::{DecodeReport, DirectionGrid, direction_arrow};
+use anoto_pdf::codec::{AnotoCodec, DEFAULT_CODEC, PatternTile, resolve_codec};
+use anoto_pdf::registry::{Allocation, REGISTRY_FILE, Registry};
+use serde::Serialize;
 use serde_json::Value;
 
-const JB_MONO_BYTES: &[u8] = include_bytes!("../assets/fonts/ttf/JetBrainsMonoNL-Medium.ttf");
-
 pub fn main() -> iced::Result {
     iced::application("Anoto PDF Generator", Gui::update, Gui::view)
         .window_size((800.0, 600.0))
         .centered()
         .scale_factor(|s| s.ui_scale as f64)
-        .font(JB_MONO_BYTES)
+        .font(anoto_pdf::fonts::JB_MONO_TTF)
         .run()
 }
 
@@ -38,6 +45,12 @@ struct Gui {
     control_state: anoto_control::State,
     page_layout_state: page_layout_control::State,
     is_generating: bool,
+    // Generate even when another document holds the pattern
+    reuse_pattern: bool,
+    // Progress and cancellation of the running generation
+    generation: Option<Arc<JobProgress>>,
+    generation_stage: String,
+    generation_fraction: f32,
     server_port: String,
     server_shutdown_tx: Option<oneshot::Sender<()>>,
     server_status_text: String,
@@ -60,6 +73,19 @@ struct Gui {
     scrollable_id: scrollable::Id,
     points_input: text_editor::Content,
     points_status: String,
+    live_rx: Option<Arc<Mutex<broadcast::Receiver<LiveEvent>>>>,
+    live_strokes: Vec<LiveStroke>,
+    live_status: String,
+    codec: SharedCodec,
+    codec_spec: String,
+    codec_status: String,
+}
+
+// Codec picked in the GUI; the REST server reads the same one.
+type SharedCodec = Arc<std::sync::RwLock<Arc<AnotoCodec>>>;
+
+fn current_codec(shared: &SharedCodec) -> Arc<AnotoCodec> {
+    shared.read().unwrap_or_else(|e| e.into_inner()).clone()
 }
 
 #[derive(Debug, Clone)]
@@ -74,7 +100,10 @@ enum Message {
     AutodetectChanged(bool),
     SectUChanged(i32),
     SectVChanged(i32),
+    ReusePatternChanged(bool),
     GeneratePressed,
+    GenerationProgress(String, f32),
+    CancelGeneration,
     GenerationFinished(Result<(image::Handle, String, u32, u32), String>),
     ToggleUpPicker(bool),
     ToggleDownPicker(bool),
@@ -106,6 +135,10 @@ enum Message {
     PointsInputChanged(text_editor::Action),
     PlotAllPoints,
     PlotAllPointsFinished(Result<image::Handle, String>),
+    LiveEventReceived(Option<LiveEvent>),
+    ClearLiveInk,
+    CodecSpecChanged(String),
+    LoadCodec,
 }
 
 impl Default for Gui {
@@ -124,6 +157,10 @@ impl Default for Gui {
             control_state: anoto_control::State::default(),
             page_layout_state: page_layout_control::State::default(),
             is_generating: false,
+            reuse_pattern: false,
+            generation: None,
+            generation_stage: String::new(),
+            generation_fraction: 0.0,
             server_port: "8080".to_string(),
             server_shutdown_tx: None,
             server_status_text: "Server Stopped".to_string(),
@@ -146,35 +183,73 @@ impl Default for Gui {
             scrollable_id: scrollable::Id::unique(),
             points_input: text_editor::Content::new(),
             points_status: "Ready".to_string(),
+            live_rx: None,
+            live_strokes: Vec::new(),
+            live_status: "No live ink".to_string(),
+            codec: Arc::new(std::sync::RwLock::new(Arc::new(
+                resolve_codec(DEFAULT_CODEC).expect("built-in codec is valid"),
+            ))),
+            codec_spec: DEFAULT_CODEC.to_string(),
+            codec_status: format!("Using {}", DEFAULT_CODEC),
         }
     }
 }
 
 #[derive(Clone)]
 struct GenerationParams {
+    codec: Arc<AnotoCodec>,
     height: usize,
     width: usize,
     sect_u: i32,
     sect_v: i32,
     config: PdfConfig,
+    reuse: bool,
 }
 
+#[derive(Default)]
 struct ImageViewerState {
     is_dragging: bool,
     last_cursor_pos: Option<Point>,
 }
 
-impl Default for ImageViewerState {
-    fn default() -> Self {
-        Self {
-            is_dragging: false,
-            last_cursor_pos: None,
-        }
-    }
-}
-
 struct ImageViewer<'a> {
     handle: &'a image::Handle,
+    ink: &'a [LiveStroke],
+    ink_layout: InkLayout,
+}
+
+/// What is needed to map pattern coordinates onto the preview image, mirroring `draw_dots_on_file`.
+#[derive(Clone, Copy)]
+struct InkLayout {
+    matrix_height: usize,
+    matrix_width: usize,
+    grid_spacing: f64,
+    dpi: f64,
+    image_width: u32,
+    image_height: u32,
+}
+
+impl InkLayout {
+    fn project(&self, x: f64, y: f64, bounds: Rectangle) -> Point {
+        // A4 dimensions in points
+        let a4_width_pts = 595.276;
+        let a4_height_pts = 841.89;
+        let scale = self.dpi / 72.0;
+
+        let grid_width = (self.matrix_width as f64 - 1.0) * self.grid_spacing;
+        let grid_height = (self.matrix_height as f64 - 1.0) * self.grid_spacing;
+        let margin_x = (a4_width_pts - grid_width) / 2.0;
+        let margin_y = (a4_height_pts - grid_height) / 2.0;
+
+        let x_px = (margin_x + x * self.grid_spacing) * scale;
+        let y_px = (a4_height_pts - (margin_y + y * self.grid_spacing)) * scale;
+
+        // The canvas is the image scaled by the preview zoom
+        Point::new(
+            (x_px * bounds.width as f64 / self.image_width.max(1) as f64) as f32,
+            (y_px * bounds.height as f64 / self.image_height.max(1) as f64) as f32,
+        )
+    }
 }
 
 impl<'a> canvas::Program<Message> for ImageViewer<'a> {
@@ -200,7 +275,7 @@ impl<'a> canvas::Program<Message> for ImageViewer<'a> {
                 }
             }
             canvas::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
-                if let Some(_) = cursor.position_in(bounds) {
+                if cursor.position_in(bounds).is_some() {
                     state.is_dragging = true;
                     state.last_cursor_pos = cursor.position();
                     (event::Status::Captured, None)
@@ -218,14 +293,13 @@ impl<'a> canvas::Program<Message> for ImageViewer<'a> {
                 }
             }
             canvas::Event::Mouse(mouse::Event::CursorMoved { .. }) => {
-                if state.is_dragging {
-                    if let Some(current_pos) = cursor.position() {
-                        if let Some(last_pos) = state.last_cursor_pos {
-                            let delta = current_pos - last_pos;
-                            state.last_cursor_pos = Some(current_pos);
-                            return (event::Status::Captured, Some(Message::PreviewPanned(delta)));
-                        }
-                    }
+                if state.is_dragging
+                    && let Some(current_pos) = cursor.position()
+                    && let Some(last_pos) = state.last_cursor_pos
+                {
+                    let delta = current_pos - last_pos;
+                    state.last_cursor_pos = Some(current_pos);
+                    return (event::Status::Captured, Some(Message::PreviewPanned(delta)));
                 }
                 (event::Status::Ignored, None)
             }
@@ -247,6 +321,24 @@ impl<'a> canvas::Program<Message> for ImageViewer<'a> {
             bounds,
             canvas::Image::new(self.handle.clone())
         );
+
+        let ink_color = Color::from_rgb(1.0, 0.0, 0.0);
+        for stroke in self.ink {
+            let points: Vec<Point> = stroke.points.iter()
+                .map(|&(x, y)| self.ink_layout.project(x, y, bounds))
+                .collect();
+            if let [single] = points.as_slice() {
+                frame.fill(&canvas::Path::circle(*single, 3.0), ink_color);
+            } else if let Some((first, rest)) = points.split_first() {
+                let path = canvas::Path::new(|builder| {
+                    builder.move_to(*first);
+                    for p in rest {
+                        builder.line_to(*p);
+                    }
+                });
+                frame.stroke(&path, canvas::Stroke::default().with_color(ink_color).with_width(2.0));
+            }
+        }
         
         vec![frame.into_geometry()]
     }
@@ -322,21 +414,27 @@ impl Gui {
                     }
                     self.server_status_text = "Server Stopped".to_string();
                     self.server_rx = None;
+                    self.live_rx = None;
                 } else {
                     // Start server
                     let port_str = self.server_port.clone();
                     let (tx, rx) = oneshot::channel();
                     let (msg_tx, msg_rx) = mpsc::channel(100);
+                    let (live_tx, live_rx) = broadcast::channel(LIVE_CHANNEL_CAPACITY);
                     self.server_shutdown_tx = Some(tx);
                     let rx_arc = Arc::new(Mutex::new(msg_rx));
                     self.server_rx = Some(rx_arc.clone());
+                    let live_rx_arc = Arc::new(Mutex::new(live_rx));
+                    self.live_rx = Some(live_rx_arc.clone());
                     self.server_status_text = "Starting Server...".to_string();
+                    let codec = self.codec.clone();
 
                     return Task::batch(vec![
                         Task::perform(async move {
-                            start_server_task(port_str, rx, msg_tx).await
+                            start_server_task(port_str, rx, msg_tx, live_tx, codec).await
                         }, Message::ServerStarted),
-                        listen_for_post(rx_arc)
+                        listen_for_post(rx_arc),
+                        listen_for_live(live_rx_arc),
                     ]);
                 }
             }
@@ -360,10 +458,10 @@ impl Gui {
                                     if i < arr.len() - 1 {
                                         s.push_str(",\n");
                                     } else {
-                                        s.push_str("\n");
+                                        s.push('\n');
                                     }
                                 }
-                                s.push_str("]");
+                                s.push(']');
                                 s
                             } else {
                                 serde_json::to_string_pretty(&val).unwrap_or(json_candidate.to_string())
@@ -382,7 +480,7 @@ impl Gui {
                     self.rest_post_content = text_editor::Content::with_text(&formatted_content);
 
                     // Attempt to decode and add to points list
-                    let decode_res = decode_json_input(json_candidate);
+                    let decode_res = decode_json_input(&current_codec(&self.codec), json_candidate);
                     
                     if decode_res.contains("Position: ") {
                         println!("Decode success");
@@ -431,7 +529,7 @@ impl Gui {
                 self.json_input.perform(action);
             }
             Message::DecodeJson => {
-                self.decoded_result = decode_json_input(&self.json_input.text());
+                self.decoded_result = decode_json_input(&current_codec(&self.codec), &self.json_input.text());
             }
             Message::LookupSectUChanged(val) => {
                 if val.chars().all(|c| c.is_numeric()) {
@@ -457,28 +555,56 @@ impl Gui {
                 self.lookup_result.perform(action);
             }
             Message::PerformLookup => {
-                let res = perform_pattern_lookup(&self.lookup_sect_u, &self.lookup_sect_v, &self.lookup_x, &self.lookup_y);
+                let res = perform_pattern_lookup(&current_codec(&self.codec), &self.lookup_sect_u, &self.lookup_sect_v, &self.lookup_x, &self.lookup_y);
                 self.lookup_result = text_editor::Content::with_text(&res);
             }
+            Message::ReusePatternChanged(val) => {
+                self.reuse_pattern = val;
+            }
             Message::GeneratePressed => {
                 if !self.is_generating {
                     self.is_generating = true;
                     self.status_message = "Generating PDF...".to_string();
+                    self.generation_stage = "Encoding".to_string();
+                    self.generation_fraction = 0.0;
                     let params = GenerationParams {
+                        codec: current_codec(&self.codec),
                         height: self.height,
                         width: self.width,
                         sect_u: self.sect_u,
                         sect_v: self.sect_v,
                         config: self.config.clone(),
+                        reuse: self.reuse_pattern,
                     };
-                    return Task::perform(async move {
-                        generate_and_save(params).await
-                    }, Message::GenerationFinished);
+                    let (tx, rx) = iced::futures::channel::mpsc::unbounded();
+                    let progress_tx = tx.clone();
+                    let progress = Arc::new(JobProgress::with_observer(move |stage, fraction| {
+                        let _ = progress_tx.unbounded_send(Message::GenerationProgress(stage.to_string(), fraction));
+                    }));
+                    self.generation = Some(progress.clone());
+                    // Off the UI thread; the stream ends once the job and its progress are dropped
+                    std::thread::spawn(move || {
+                        let result = generate_and_save(params, &progress);
+                        let _ = tx.unbounded_send(Message::GenerationFinished(result));
+                    });
+                    return Task::run(rx, |message| message);
                 }
             },
+            Message::GenerationProgress(stage, fraction) => {
+                self.generation_stage = stage;
+                self.generation_fraction = fraction;
+            }
+            Message::CancelGeneration => {
+                if let Some(progress) = &self.generation {
+                    progress.cancel();
+                    self.status_message = "Cancelling...".to_string();
+                }
+            }
             Message::GenerationFinished(result) => {
                 self.is_generating = false;
+                let cancelled = self.generation.take().is_some_and(|p| p.is_cancelled());
                 match result {
+                    Err(_) if cancelled => self.status_message = "Generation cancelled".to_string(),
                     Ok((handle, path, w, h)) => {
                         self.status_message = "PDF Generated Successfully!".to_string();
                         self.generated_image_handle = Some(handle);
@@ -579,9 +705,48 @@ impl Gui {
                     Err(e) => self.points_status = format!("Error: {}", e),
                 }
             }
+            Message::LiveEventReceived(event) => {
+                let Some(event) = event else {
+                    // Channel closed: the server was stopped
+                    return Task::none();
+                };
+                match event {
+                    LiveEvent::Position { pen, stroke, x, y, .. } => {
+                        match self.live_strokes.iter_mut().rev().find(|s| s.pen == pen && s.stroke == stroke) {
+                            Some(s) => s.points.push((x, y)),
+                            None => self.live_strokes.push(LiveStroke { pen, stroke, points: vec![(x, y)] }),
+                        }
+                        let total: usize = self.live_strokes.iter().map(|s| s.points.len()).sum();
+                        self.live_status = format!("Live: {} points in {} strokes", total, self.live_strokes.len());
+                    }
+                    LiveEvent::StrokeEnd { pen, stroke } => {
+                        self.live_status = format!("Pen {} finished stroke {}", pen, stroke);
+                    }
+                    LiveEvent::FrameError { pen, error, .. } => {
+                        self.live_status = format!("Pen {}: {}", pen, error);
+                    }
+                }
+                if let Some(rx) = &self.live_rx {
+                    return listen_for_live(rx.clone());
+                }
+            }
+            Message::ClearLiveInk => {
+                self.live_strokes.clear();
+                self.live_status = "No live ink".to_string();
+            }
+            Message::CodecSpecChanged(spec) => self.codec_spec = spec,
+            Message::LoadCodec => {
+                match resolve_codec(&self.codec_spec) {
+                    Ok(codec) => {
+                        *self.codec.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(codec);
+                        self.codec_status = format!("Using {}", self.codec_spec.trim());
+                    }
+                    Err(e) => self.codec_status = e.to_string(),
+                }
+            }
             Message::PreviewZoomed(delta, cursor) => {
                 let old_zoom = self.preview_zoom;
-                let new_zoom = (old_zoom * (1.0 + delta * 0.1)).max(0.1).min(20.0);
+                let new_zoom = (old_zoom * (1.0 + delta * 0.1)).clamp(0.1, 20.0);
                 
                 // cursor is relative to the canvas (which is scaled)
                 // We want to scroll such that the point under cursor remains under cursor.
@@ -690,21 +855,40 @@ impl Gui {
             Message::SectVChanged,
         );
 
+        let codec_picker = column![
+            text("Codec (built-in name, JSON file or codecs/ name)"),
+            row![
+                text_input(DEFAULT_CODEC, &self.codec_spec)
+                    .on_input(Message::CodecSpecChanged)
+                    .on_submit(Message::LoadCodec)
+                    .padding(5)
+                    .width(Length::Fill),
+                button("Load").on_press(Message::LoadCodec),
+            ].spacing(10),
+            text(&self.codec_status),
+        ].spacing(5);
+
         let matrix_inputs = column![
             text("Matrix Settings:"),
+            codec_picker,
             page_layout,
             section_ctrl,
         ].spacing(10);
 
         let generate_btn = if self.is_generating {
             row![
-                button("Generating...").width(Length::Fill),
+                column![
+                    progress_bar(0.0..=1.0, self.generation_fraction).height(Length::Fixed(20.0)),
+                    text(format!("{} {:.0}%", self.generation_stage, self.generation_fraction * 100.0)),
+                ].spacing(5).width(Length::Fill),
                 Spinner::new().width(Length::Fixed(20.0)).height(Length::Fixed(20.0)),
+                button("Cancel").on_press(Message::CancelGeneration),
             ].spacing(10)
         } else {
             row![
-                button("Generate PDF").on_press(Message::GeneratePressed).width(Length::Fill)
-            ]
+                button("Generate PDF").on_press(Message::GeneratePressed).width(Length::Fill),
+                checkbox("Allow allocated pattern", self.reuse_pattern).on_toggle(Message::ReusePatternChanged),
+            ].spacing(10)
         };
 
         let controls = column![
@@ -732,6 +916,15 @@ impl Gui {
                     scrollable(
                         canvas::Canvas::new(ImageViewer {
                             handle,
+                            ink: &self.live_strokes,
+                            ink_layout: InkLayout {
+                                matrix_height: self.height,
+                                matrix_width: self.width,
+                                grid_spacing: self.config.grid_spacing as f64,
+                                dpi: self.config.dpi as f64,
+                                image_width: self.image_width,
+                                image_height: self.image_height,
+                            },
                         })
                         .width(Length::Fixed(self.image_width as f32 * self.preview_zoom))
                         .height(Length::Fixed(self.image_height as f32 * self.preview_zoom))
@@ -914,7 +1107,7 @@ impl Gui {
         .width(Length::Fixed(200.0));
 
         let rest_post_controls = container(column![
-            text("JSON 6x6+ REST Listener").size(20),
+            text("JSON Grid REST Listener").size(20),
             vertical_space().height(10),
             text_editor(&self.rest_post_content)
                 .on_action(Message::RestPostContentChanged)
@@ -934,6 +1127,13 @@ impl Gui {
                 .padding(10)
                 .width(Length::Fill),
             text(&self.points_status).size(14),
+            vertical_space().height(20),
+            text("Live Ink").size(20),
+            text(&self.live_status).size(14),
+            button("Clear Live Ink")
+                .on_press(Message::ClearLiveInk)
+                .padding(10)
+                .width(Length::Fill),
         ]
         .spacing(10))
         .padding(20)
@@ -991,10 +1191,10 @@ fn parse_points(input: &str) -> Vec<(f64, f64)> {
             } else {
                 // Maybe space separated?
                 let coords: Vec<&str> = content.split_whitespace().collect();
-                if coords.len() >= 2 {
-                     if let (Ok(x), Ok(y)) = (coords[0].parse::<f64>(), coords[1].parse::<f64>()) {
-                        points.push((x, y));
-                    }
+                if coords.len() >= 2
+                    && let (Ok(x), Ok(y)) = (coords[0].parse::<f64>(), coords[1].parse::<f64>())
+                {
+                    points.push((x, y));
                 }
             }
         }
@@ -1002,194 +1202,60 @@ fn parse_points(input: &str) -> Vec<(f64, f64)> {
     points
 }
 
-fn decode_json_input(input: &str) -> String {
-    let mut parsed: Value = match serde_json::from_str(input) {
-        Ok(v) => v,
-        Err(e) => return format!("JSON Parse Error: {}", e),
-    };
-
-    // If parsed is a string, try to parse it as JSON again (handles cmd.exe sending "[[...]]")
-    if let Some(s) = parsed.as_str() {
-        if let Ok(v) = serde_json::from_str(s) {
-            parsed = v;
-        }
-    }
-
-    // Helper to map direction to bits
-    let map_direction = |dir: &str| -> Option<(i8, i8)> {
-        match dir {
-            "↑" | "Up" | "up" => Some((0, 0)),
-            "←" | "Left" | "left" => Some((1, 0)),
-            "→" | "Right" | "right" => Some((0, 1)),
-            "↓" | "Down" | "down" => Some((1, 1)),
-            _ => None,
-        }
-    };
-
-    let map_coords = |x: i64, y: i64| -> Option<(i8, i8)> {
-        match (x, y) {
-            (0, 0) => Some((0, 0)), // Up
-            (1, 0) => Some((1, 0)), // Left
-            (0, 1) => Some((0, 1)), // Right
-            (1, 1) => Some((1, 1)), // Down
-            _ => None,
-        }
-    };
-
-    let map_cell = |cell: &Value| -> Option<(i8, i8)> {
-        if let Some(cell_arr) = cell.as_array() {
-            // Case: [0,0]
-            if cell_arr.len() == 2 {
-                let x = cell_arr[0].as_i64();
-                let y = cell_arr[1].as_i64();
-                if let (Some(x), Some(y)) = (x, y) {
-                    return map_coords(x, y);
-                } else {
-                    // Maybe it's ["↑"]
-                    if let Some(s) = cell_arr[0].as_str() {
-                         return map_direction(s);
-                    }
-                }
-            } else if cell_arr.len() == 1 {
-                 // Case: ["↑"]
-                 if let Some(s) = cell_arr[0].as_str() {
-                     return map_direction(s);
-                 }
-            }
-        } else if let Some(s) = cell.as_str() {
-            // Case: "↑"
-            return map_direction(s);
-        }
-        None
-    };
-
-    // Find the matrix
-    let mut matrix_val = &parsed;
-    while let Some(arr) = matrix_val.as_array() {
-        if arr.is_empty() { return "Empty array".to_string(); }
-        
-        if let Some(first_row) = arr[0].as_array() {
-             if first_row.is_empty() { return "Empty row".to_string(); }
-             
-             let is_cell = |v: &Value| {
-                 if v.is_string() { true }
-                 else if let Some(a) = v.as_array() {
-                     // It's a cell if it's [num, num] or ["str"]
-                     // It's NOT a cell if it's [[...]] (array of arrays)
-                     !a.is_empty() && !a[0].is_array()
-                 } else {
-                     false
-                 }
-             };
-             
-             if is_cell(&first_row[0]) {
-                 // Found the matrix!
-                 break;
-             } else {
-                 // Go deeper
-                 matrix_val = &arr[0];
-             }
-        } else {
-            return "Invalid structure".to_string();
-        }
-    }
-
-    let rows = match matrix_val.as_array() {
-        Some(a) => a,
-        None => return "Could not find matrix array".to_string(),
+fn decode_json_input(codec: &AnotoCodec, input: &str) -> String {
+    let report = match DirectionGrid::parse(input).and_then(|grid| grid.decode(codec)) {
+        Ok(r) => r,
+        Err(e) => return e.to_string(),
     };
 
-    let mut grid = Vec::new();
-    for (r_idx, row_val) in rows.iter().enumerate() {
-        let row_arr = match row_val.as_array() {
-            Some(a) => a,
-            None => return format!("Row {} is not an array", r_idx),
-        };
-        let mut row_bits = Vec::new();
-        for (c_idx, cell_val) in row_arr.iter().enumerate() {
-            match map_cell(cell_val) {
-                Some(bits) => row_bits.push(bits),
-                None => return format!("Invalid cell at [{}, {}]", r_idx, c_idx),
-            }
-        }
-        grid.push(row_bits);
-    }
-
-    let height = grid.len();
-    if height < 6 { return "Matrix too small (height < 6)".to_string(); }
-    let width = grid[0].len();
-    if width < 6 { return "Matrix too small (width < 6)".to_string(); }
-
-    // Check all rows have same width
-    if grid.iter().any(|r| r.len() != width) {
-        return "Matrix rows have inconsistent lengths".to_string();
+    if report.decoded.is_empty() {
+        return "No valid positions found".to_string();
     }
 
-    let mut results = String::new();
-    let codec = anoto_6x6_a4_fixed();
-
-    for r in 0..=(height - 6) {
-        for c in 0..=(width - 6) {
-            // Extract 6x6
-            let mut bits = ndarray::Array3::<i8>::zeros((6, 6, 2));
-            for i in 0..6 {
-                for j in 0..6 {
-                    let (b0, b1) = grid[r+i][c+j];
-                    bits[[i, j, 0]] = b0;
-                    bits[[i, j, 1]] = b1;
-                }
-            }
-
-            if let Ok((x, y)) = codec.decode_position(&bits) {
-                if !results.is_empty() { results.push('\n'); }
-                results.push_str(&format!("Position: ({}, {})", x, y));
-            }
-        }
-    }
+    report.decoded.iter()
+        .map(|d| format!("Position: ({}, {})", d.position.0, d.position.1))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-    if results.is_empty() {
-        "No valid positions found".to_string()
-    } else {
-        results
-    }
+fn perform_pattern_lookup(codec: &AnotoCodec, sect_u_str: &str, sect_v_str: &str, x_str: &str, y_str: &str) -> String {
+    lookup_window(codec, sect_u_str, sect_v_str, x_str, y_str).unwrap_or_else(|e| e.to_string())
 }
 
-fn perform_pattern_lookup(sect_u_str: &str, sect_v_str: &str, x_str: &str, y_str: &str) -> String {
-    let sect_u = match sect_u_str.parse::<i32>() { Ok(v) => v, Err(_) => return "Invalid Sect U".to_string() };
-    let sect_v = match sect_v_str.parse::<i32>() { Ok(v) => v, Err(_) => return "Invalid Sect V".to_string() };
-    let x = match x_str.parse::<i32>() { Ok(v) => v, Err(_) => return "Invalid X".to_string() };
-    let y = match y_str.parse::<i32>() { Ok(v) => v, Err(_) => return "Invalid Y".to_string() };
+fn lookup_window(codec: &AnotoCodec, sect_u_str: &str, sect_v_str: &str, x_str: &str, y_str: &str) -> Result<String, AnotoError> {
+    let field = |name: &'static str| move |e: std::num::ParseIntError| AnotoError::parse(format!("{}: {}", name, e), 0, 0);
+    let sect_u = sect_u_str.trim().parse::<i32>().map_err(field("Sect U"))?;
+    let sect_v = sect_v_str.trim().parse::<i32>().map_err(field("Sect V"))?;
+    let x = x_str.trim().parse::<i64>().map_err(field("X"))?;
+    let y = y_str.trim().parse::<i64>().map_err(field("Y"))?;
 
-    let codec = anoto_6x6_a4_fixed();
-    
-    let start_roll_x = sect_u % codec.mns_length as i32;
-    let start_roll_y = sect_v % codec.mns_length as i32;
-    
-    let bitmatrix = codec.encode_patch((x, y), (6, 6), (start_roll_x, start_roll_y));
+    let order = codec.mns_order();
+    let bitmatrix = encode_window(codec, (sect_u, sect_v), (x, y), (order, order))?;
     
     let mut result = String::new();
     result.push_str("[\n");
-    for r in 0..6 {
+    for r in 0..order {
         result.push_str("  [");
-        for c in 0..6 {
-            let b0 = bitmatrix[[r, c, 0]];
-            let b1 = bitmatrix[[r, c, 1]];
-            let arrow = match (b0, b1) {
-                (0, 0) => "\"↑\"",
-                (1, 0) => "\"←\"",
-                (0, 1) => "\"→\"",
-                (1, 1) => "\"↓\"",
-                _ => "\"?\"",
-            };
-            result.push_str(arrow);
-            if c < 5 { result.push_str(", "); }
+        for c in 0..order {
+            result.push('"');
+            result.push_str(direction_arrow((bitmatrix[[r, c, 0]], bitmatrix[[r, c, 1]])));
+            result.push('"');
+            if c + 1 < order { result.push_str(", "); }
         }
-        result.push_str("]");
-        if r < 5 { result.push_str(",\n"); }
+        result.push(']');
+        if r + 1 < order { result.push_str(",\n"); }
     }
     result.push_str("\n]");
     
-    result
+    Ok(result)
+}
+
+/// Encodes the `(width, height)` window whose top-left dot sits at `pos` in the given section.
+fn encode_window(codec: &AnotoCodec, section: (i32, i32), pos: (i64, i64), size: (usize, usize)) -> Result<ndarray::Array3<i8>, AnotoError> {
+    let start_roll_x = section.0 % codec.mns_length as i32;
+    let start_roll_y = section.1 % codec.mns_length as i32;
+    
+    codec.encode_patch(pos, size, (start_roll_x, start_roll_y))
 }
 
 fn listen_for_post(rx: Arc<Mutex<mpsc::Receiver<String>>>) -> Task<Message> {
@@ -1199,7 +1265,21 @@ fn listen_for_post(rx: Arc<Mutex<mpsc::Receiver<String>>>) -> Task<Message> {
     }, Message::RestPostReceived)
 }
 
-async fn start_server_task(port_str: String, rx: oneshot::Receiver<()>, msg_tx: mpsc::Sender<String>) -> Result<(), String> {
+fn listen_for_live(rx: Arc<Mutex<broadcast::Receiver<LiveEvent>>>) -> Task<Message> {
+    Task::perform(async move {
+        let mut lock = rx.lock().await;
+        loop {
+            match lock.recv().await {
+                Ok(event) => return Some(event),
+                // Dropping old ink is fine, keep drawing the newest
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }, Message::LiveEventReceived)
+}
+
+async fn start_server_task(port_str: String, rx: oneshot::Receiver<()>, msg_tx: mpsc::Sender<String>, live_tx: broadcast::Sender<LiveEvent>, codec: SharedCodec) -> Result<(), String> {
     let port = port_str.parse::<u16>().map_err(|_| "Invalid port")?;
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
 
@@ -1233,7 +1313,16 @@ async fn start_server_task(port_str: String, rx: oneshot::Receiver<()>, msg_tx:
             let app = axum::Router::new()
                 .route("/", axum::routing::get(index_handler))
                 .route("/decode", axum::routing::post(decode_handler))
-                .layer(axum::Extension(msg_tx));
+                .route("/encode", axum::routing::post(encode_handler))
+                .route("/generate", axum::routing::post(generate_handler))
+                .route("/verify", axum::routing::post(verify_handler))
+                .route("/openapi.json", axum::routing::get(openapi_handler))
+                .route("/live", axum::routing::get(live_stream_handler))
+                .route("/live/frames", axum::routing::post(live_frame_handler))
+                .layer(axum::Extension(msg_tx))
+                .layer(axum::Extension(live_tx))
+                .layer(axum::Extension(codec))
+                .layer(axum::Extension(Arc::new(std::sync::Mutex::new(HashMap::<String, PenState>::new()))));
 
             if let Err(e) = axum::serve(listener, app)
                 .with_graceful_shutdown(async { rx.await.ok(); })
@@ -1256,12 +1345,738 @@ async fn index_handler() -> axum::response::Html<&'static str> {
 
 async fn decode_handler(
     axum::Extension(msg_tx): axum::Extension<mpsc::Sender<String>>,
+    axum::Extension(codec): axum::Extension<SharedCodec>,
     body: String
-) -> impl axum::response::IntoResponse {
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let codec = current_codec(&codec);
+    let input = body.clone();
+    let result = tokio::task::spawn_blocking(move || -> Result<DecodeReport, AnotoError> {
+        let grid = DirectionGrid::parse(&input)?;
+        if grid.height() > MAX_WINDOW_DOTS || grid.width() > MAX_WINDOW_DOTS {
+            return Err(AnotoError::shape(format!("a grid of at most {0}x{0}", MAX_WINDOW_DOTS), format!("{}x{}", grid.width(), grid.height())));
+        }
+        grid.decode(&codec)
+    }).await;
+    let response = match result {
+        Ok(Ok(report)) => (axum::http::StatusCode::OK, axum::Json(report)).into_response(),
+        Ok(Err(e)) => bad_request(e),
+        Err(e) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({ "error": e.to_string() })),
+        ).into_response(),
+    };
+
+    // Let the GUI know about the capture as well; a closed channel is not an error for the client.
     let _ = msg_tx.send(body).await;
-    axum::http::StatusCode::OK
+    response
+}
+
+// Largest window /encode and /verify will build, and /decode will read, in one request.
+const MAX_WINDOW_DOTS: usize = 1000;
+// Same bounds as the GUI page layout sliders.
+const MAX_PAGE_DOTS: usize = 200;
+
+fn bad_request(error: impl Into<AnotoError>) -> axum::response::Response {
+    error_response(axum::http::StatusCode::BAD_REQUEST, &error.into())
+}
+
+/// `{ "error": message, "kind": variant }`, so clients can react to the specific failure.
+fn error_response(status: axum::http::StatusCode, error: &AnotoError) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    (
+        status,
+        axum::Json(serde_json::json!({ "error": error.to_string(), "kind": error.kind() })),
+    ).into_response()
+}
+
+fn default_sect_u() -> i32 { 10 }
+fn default_sect_v() -> i32 { 2 }
+
+#[derive(Debug, serde::Deserialize)]
+struct EncodeRequest {
+    x: i64,
+    y: i64,
+    #[serde(default = "default_sect_u")]
+    sect_u: i32,
+    #[serde(default = "default_sect_v")]
+    sect_v: i32,
+    // Both default to the codec's window order
+    width: Option<usize>,
+    height: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct EncodeResponse {
+    x: i64,
+    y: i64,
+    sect_u: i32,
+    sect_v: i32,
+    bits: Vec<Vec<[i8; 2]>>,
+    arrows: Vec<Vec<&'static str>>,
+}
+
+async fn encode_handler(
+    axum::Extension(codec): axum::Extension<SharedCodec>,
+    axum::Json(req): axum::Json<EncodeRequest>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let codec = current_codec(&codec);
+    let width = req.width.unwrap_or(codec.mns_order());
+    let height = req.height.unwrap_or(codec.mns_order());
+    if width == 0 || height == 0 || width > MAX_WINDOW_DOTS || height > MAX_WINDOW_DOTS {
+        return bad_request(format!("width and height must be between 1 and {}", MAX_WINDOW_DOTS));
+    }
+
+    let result = run_blocking(move || -> Result<EncodeResponse, AnotoError> {
+        let window = encode_window(&codec, (req.sect_u, req.sect_v), (req.x, req.y), (width, height))?;
+        let bits = window.outer_iter()
+            .map(|row| row.outer_iter().map(|cell| [cell[0], cell[1]]).collect())
+            .collect();
+        let arrows = window.outer_iter()
+            .map(|row| row.outer_iter().map(|cell| direction_arrow((cell[0], cell[1]))).collect())
+            .collect();
+        Ok(EncodeResponse {
+            x: req.x,
+            y: req.y,
+            sect_u: req.sect_u,
+            sect_v: req.sect_v,
+            bits,
+            arrows,
+        })
+    }).await;
+
+    match result {
+        Ok(Ok(response)) => axum::Json(response).into_response(),
+        Ok(Err(e)) => bad_request(e),
+        Err(response) => response,
+    }
+}
+
+/// Runs `work` on the blocking pool, so large grids do not stall the server; a panic becomes a 500.
+async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Result<T, axum::response::Response> {
+    use axum::response::IntoResponse;
+
+    tokio::task::spawn_blocking(work).await.map_err(|e| (
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        axum::Json(serde_json::json!({ "error": e.to_string() })),
+    ).into_response())
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    #[default]
+    Pdf,
+    Png,
+    Svg,
+    Eps,
+}
+
+fn default_height() -> usize { 9 }
+fn default_width() -> usize { 16 }
+
+#[derive(Debug, serde::Deserialize)]
+struct GenerateRequest {
+    #[serde(default = "default_height")]
+    height: usize,
+    #[serde(default = "default_width")]
+    width: usize,
+    #[serde(default = "default_sect_u")]
+    sect_u: i32,
+    #[serde(default = "default_sect_v")]
+    sect_v: i32,
+    #[serde(default)]
+    format: OutputFormat,
+    #[serde(default)]
+    config: PdfConfig,
+    // Registry entry of the region; REST_G__{height}__{width}__{sect_u}__{sect_v} by default
+    document: Option<String>,
+    #[serde(default)]
+    reuse: bool,
+}
+
+async fn generate_handler(
+    axum::Extension(codec): axum::Extension<SharedCodec>,
+    axum::Json(req): axum::Json<GenerateRequest>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    if req.height == 0 || req.width == 0 || req.height > MAX_PAGE_DOTS || req.width > MAX_PAGE_DOTS {
+        return bad_request(format!("height and width must be between 1 and {}", MAX_PAGE_DOTS));
+    }
+    if req.config.dpi <= 0.0 || req.config.dpi > 1200.0 {
+        return bad_request("config.dpi must be between 0 and 1200");
+    }
+
+    let codec = current_codec(&codec);
+    let result = tokio::task::spawn_blocking(move || -> Result<(&'static str, Vec<u8>), (axum::http::StatusCode, AnotoError)> {
+        let internal = |e: AnotoError| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e);
+        let conflict = |e: AnotoError| (axum::http::StatusCode::CONFLICT, e);
+        let document = req.document.clone().unwrap_or_else(|| format!("REST_G__{}__{}__{}__{}", req.height, req.width, req.sect_u, req.sect_v));
+        let allocation = Allocation::new(&codec, &document, (req.sect_u, req.sect_v), (0, 0), (req.width, req.height), "REST /generate");
+        Registry::open(REGISTRY_FILE).map_err(internal)?.check(&allocation, req.reuse).map_err(conflict)?;
+
+        let bitmatrix = generate_matrix_with_codec(&codec, req.height, req.width, req.sect_u, req.sect_v)
+            .map_err(|e| (axum::http::StatusCode::BAD_REQUEST, e))?;
+        let rendered = (|| match req.format {
+            OutputFormat::Pdf => {
+                let pattern = PatternInfo::generated(&codec, (req.sect_u, req.sect_v), (0, 0));
+                let bytes = gen_pdf_bytes(&bitmatrix, &req.config, &pattern)?;
+                Ok(("application/pdf", bytes))
+            }
+            OutputFormat::Svg => {
+                let bytes = gen_svg_bytes(&bitmatrix, &req.config)?;
+                Ok(("image/svg+xml", bytes))
+            }
+            OutputFormat::Eps => {
+                let bytes = gen_eps_bytes(&bitmatrix, &req.config)?;
+                Ok(("application/postscript", bytes))
+            }
+            OutputFormat::Png => {
+                let bytes = draw_preview_png_bytes(&bitmatrix.mapv(|x| x as i8), &req.config)?;
+                Ok(("image/png", bytes))
+            }
+        })().map_err(internal)?;
+        claim_allocation(allocation, req.reuse).map_err(conflict)?;
+        Ok(rendered)
+    }).await;
+
+    match result {
+        Ok(Ok((content_type, bytes))) => (
+            [(axum::http::header::CONTENT_TYPE, content_type)],
+            bytes,
+        ).into_response(),
+        Ok(Err((status, e))) => error_response(status, &e),
+        Err(e) => (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            axum::Json(serde_json::json!({ "error": e.to_string() })),
+        ).into_response(),
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct VerifyRequest {
+    grid: Value,
+    x: i64,
+    y: i64,
+    #[serde(default = "default_sect_u")]
+    sect_u: i32,
+    #[serde(default = "default_sect_v")]
+    sect_v: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct CellMismatch {
+    row: usize,
+    col: usize,
+    expected: &'static str,
+    actual: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifyResponse {
+    matches: bool,
+    mismatches: Vec<CellMismatch>,
+    decode: DecodeReport,
+}
+
+async fn verify_handler(
+    axum::Extension(codec): axum::Extension<SharedCodec>,
+    axum::Json(req): axum::Json<VerifyRequest>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    // The grid may be sent as a nested JSON value or as the same string body /decode accepts.
+    let codec = current_codec(&codec);
+    let result = run_blocking(move || -> Result<VerifyResponse, AnotoError> {
+        let grid = DirectionGrid::from_json(&req.grid)?;
+        let (height, width) = (grid.height(), grid.width());
+        if height > MAX_WINDOW_DOTS || width > MAX_WINDOW_DOTS {
+            return Err(format!("grid must not exceed {}x{}", MAX_WINDOW_DOTS, MAX_WINDOW_DOTS).into());
+        }
+
+        let expected = encode_window(&codec, (req.sect_u, req.sect_v), (req.x, req.y), (width, height))?;
+        let decode = grid.decode(&codec)?;
+        let mut mismatches = Vec::new();
+        for r in 0..height {
+            for c in 0..width {
+                let (b0, b1) = grid.cell(r, c);
+                let (e0, e1) = (expected[[r, c, 0]], expected[[r, c, 1]]);
+                if (b0, b1) != (e0, e1) {
+                    mismatches.push(CellMismatch {
+                        row: r,
+                        col: c,
+                        expected: direction_arrow((e0, e1)),
+                        actual: direction_arrow((b0, b1)),
+                    });
+                }
+            }
+        }
+        Ok(VerifyResponse {
+            matches: mismatches.is_empty(),
+            mismatches,
+            decode,
+        })
+    }).await;
+
+    match result {
+        Ok(Ok(response)) => axum::Json(response).into_response(),
+        Ok(Err(e)) => bad_request(e),
+        Err(response) => response,
+    }
+}
+
+// Frames a slow subscriber may fall behind before it starts skipping events.
+const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Events published on `/live` for every capture frame pushed to `/live/frames`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum LiveEvent {
+    /// Decoded nib position; `x`/`y` is the centre of the captured grid, `origin` its top-left dot.
+    Position {
+        pen: String,
+        stroke: u64,
+        x: f64,
+        y: f64,
+        origin: (i64, i64),
+        section: Option<(i32, i32)>,
+    },
+    StrokeEnd {
+        pen: String,
+        stroke: u64,
+    },
+    FrameError {
+        pen: String,
+        error: String,
+        kind: &'static str,
+    },
+}
+
+impl LiveEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::Position { .. } => "position",
+            LiveEvent::StrokeEnd { .. } => "stroke_end",
+            LiveEvent::FrameError { .. } => "frame_error",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LiveStroke {
+    pen: String,
+    stroke: u64,
+    points: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Default)]
+struct PenState {
+    stroke: u64,
+    down: bool,
+}
+
+fn default_pen() -> String { "pen".to_string() }
+
+/// A frame pushed by a pen gateway. A bare grid body is accepted as a frame of the default pen.
+#[derive(Debug, serde::Deserialize)]
+struct CaptureFrame {
+    #[serde(default = "default_pen")]
+    pen: String,
+    #[serde(default)]
+    grid: Option<Value>,
+    /// Set on the frame sent when the nib is lifted; ends the current stroke.
+    #[serde(default)]
+    pen_up: bool,
+}
+
+fn parse_capture_frame(body: &str) -> Result<CaptureFrame, AnotoError> {
+    let body = body.trim();
+    if body.starts_with('{') {
+        Ok(serde_json::from_str(body)?)
+    } else {
+        Ok(CaptureFrame { pen: default_pen(), grid: Some(Value::String(body.to_string())), pen_up: false })
+    }
 }
 
+// Nib position (x, y), top-left dot and section of a frame, or the error and its kind
+type FramePosition = Result<(f64, f64, (i64, i64), Option<(i32, i32)>), (String, &'static str)>;
+
+fn decode_frame(codec: &AnotoCodec, grid: &DirectionGrid) -> FramePosition {
+    let report = grid.decode(codec).map_err(|e| (e.to_string(), e.kind()))?;
+    report.origin()
+        .map(|origin| {
+            let x = origin.0 as f64 + (report.width as f64 - 1.0) / 2.0;
+            let y = origin.1 as f64 + (report.height as f64 - 1.0) / 2.0;
+            (x, y, origin, report.decoded[0].section)
+        })
+        // Every window failed; the first one says why
+        .ok_or_else(|| report.errors.first()
+            .map(|e| (e.error.clone(), e.kind))
+            .unwrap_or_else(|| ("No valid positions found".to_string(), "invalid")))
+}
+
+// Only the pen state is updated under the lock; the frame is decoded before
+fn frame_events(pen: &str, position: Option<FramePosition>, pen_up: bool, pens: &std::sync::Mutex<HashMap<String, PenState>>) -> Vec<LiveEvent> {
+    let mut events = Vec::new();
+    let mut pens = pens.lock().unwrap_or_else(|e| e.into_inner());
+    let state = pens.entry(pen.to_string()).or_default();
+
+    match position {
+        Some(Ok((x, y, origin, section))) => {
+            if !state.down {
+                state.stroke += 1;
+                state.down = true;
+            }
+            events.push(LiveEvent::Position { pen: pen.to_string(), stroke: state.stroke, x, y, origin, section });
+        }
+        Some(Err((error, kind))) => events.push(LiveEvent::FrameError { pen: pen.to_string(), error, kind }),
+        None => {}
+    }
+
+    if pen_up && state.down {
+        state.down = false;
+        events.push(LiveEvent::StrokeEnd { pen: pen.to_string(), stroke: state.stroke });
+    }
+
+    events
+}
+
+async fn live_frame_handler(
+    axum::Extension(live_tx): axum::Extension<broadcast::Sender<LiveEvent>>,
+    axum::Extension(pens): axum::Extension<Arc<std::sync::Mutex<HashMap<String, PenState>>>>,
+    axum::Extension(codec): axum::Extension<SharedCodec>,
+    body: String,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let frame = match parse_capture_frame(&body) {
+        Ok(f) => f,
+        Err(e) => return bad_request(e),
+    };
+    if frame.grid.is_none() && !frame.pen_up {
+        return bad_request("frame needs a grid or pen_up");
+    }
+
+    let position = match frame.grid {
+        Some(value) => {
+            let codec = current_codec(&codec);
+            let result = run_blocking(move || -> Result<FramePosition, AnotoError> {
+                // An unreadable grid is a frame error for the subscribers, an oversized one a bad request
+                let grid = match DirectionGrid::from_json(&value) {
+                    Ok(grid) => grid,
+                    Err(e) => return Ok(Err((e.to_string(), e.kind()))),
+                };
+                if grid.height() > MAX_WINDOW_DOTS || grid.width() > MAX_WINDOW_DOTS {
+                    return Err(AnotoError::shape(format!("a grid of at most {0}x{0}", MAX_WINDOW_DOTS), format!("{}x{}", grid.width(), grid.height())));
+                }
+                Ok(decode_frame(&codec, &grid))
+            }).await;
+            match result {
+                Ok(Ok(position)) => Some(position),
+                Ok(Err(e)) => return bad_request(e),
+                Err(response) => return response,
+            }
+        }
+        None => None,
+    };
+
+    let events = frame_events(&frame.pen, position, frame.pen_up, &pens);
+    for event in &events {
+        // No subscribers is not an error for the gateway
+        let _ = live_tx.send(event.clone());
+    }
+    axum::Json(events).into_response()
+}
+
+async fn live_stream_handler(
+    axum::Extension(live_tx): axum::Extension<broadcast::Sender<LiveEvent>>,
+) -> impl axum::response::IntoResponse {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+
+    let stream = iced::futures::stream::unfold(live_tx.subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let sse_event = Event::default().event(event.name()).json_data(&event);
+                    return Some((sse_event, rx));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn openapi_handler() -> impl axum::response::IntoResponse {
+    ([(axum::http::header::CONTENT_TYPE, "application/json")], OPENAPI_JSON)
+}
+
+const OPENAPI_JSON: &str = r##"{
+  "openapi": "3.0.3",
+  "info": {
+    "title": "Anoto PDF Generator",
+    "version": "0.5.5",
+    "description": "Decode, encode, verify and render Anoto dot patterns with the codec selected in the GUI (anoto_6x6_a4_fixed by default)."
+  },
+  "paths": {
+    "/decode": {
+      "post": {
+        "summary": "Decode every window (codec order x order, 6x6 by default) of a direction grid",
+        "requestBody": {
+          "required": true,
+          "content": {
+            "application/json": { "schema": { "$ref": "#/components/schemas/Grid" } },
+            "text/plain": { "schema": { "type": "string", "description": "One row per line: CSV or space separated cells, bracketed pairs [1 0] or compact arrows" } }
+          }
+        },
+        "responses": {
+          "200": { "description": "Decoded windows", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/DecodeReport" } } } },
+          "400": { "description": "Malformed grid, or one larger than 1000x1000", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } }
+        }
+      }
+    },
+    "/encode": {
+      "post": {
+        "summary": "Encode the window at a position of a section",
+        "requestBody": {
+          "required": true,
+          "content": { "application/json": { "schema": { "$ref": "#/components/schemas/EncodeRequest" } } }
+        },
+        "responses": {
+          "200": { "description": "Encoded window", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/EncodeResponse" } } } },
+          "400": { "description": "Invalid position or size", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } }
+        }
+      }
+    },
+    "/generate": {
+      "post": {
+        "summary": "Render a pattern page as PDF, PNG, SVG or EPS",
+        "requestBody": {
+          "required": true,
+          "content": { "application/json": { "schema": { "$ref": "#/components/schemas/GenerateRequest" } } }
+        },
+        "responses": {
+          "200": {
+            "description": "Rendered page",
+            "content": {
+              "application/pdf": { "schema": { "type": "string", "format": "binary" } },
+              "image/png": { "schema": { "type": "string", "format": "binary" } },
+              "image/svg+xml": { "schema": { "type": "string" } },
+              "application/postscript": { "schema": { "type": "string" } }
+            }
+          },
+          "400": { "description": "Invalid shape or config", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+          "409": { "description": "Another document holds the region in the allocation registry", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } }
+        }
+      }
+    },
+    "/verify": {
+      "post": {
+        "summary": "Compare a captured grid against the pattern expected at a position",
+        "requestBody": {
+          "required": true,
+          "content": { "application/json": { "schema": { "$ref": "#/components/schemas/VerifyRequest" } } }
+        },
+        "responses": {
+          "200": { "description": "Comparison result", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/VerifyResponse" } } } },
+          "400": { "description": "Malformed grid or position", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } }
+        }
+      }
+    },
+    "/live/frames": {
+      "post": {
+        "summary": "Push a capture frame from a pen gateway",
+        "description": "Decodes the frame and publishes the resulting events on /live. A bare grid body is taken as a frame of pen \"pen\".",
+        "requestBody": {
+          "required": true,
+          "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CaptureFrame" } } }
+        },
+        "responses": {
+          "200": { "description": "Events published for this frame", "content": { "application/json": { "schema": { "type": "array", "items": { "$ref": "#/components/schemas/LiveEvent" } } } } },
+          "400": { "description": "Malformed frame, or a grid larger than 1000x1000", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } }
+        }
+      }
+    },
+    "/live": {
+      "get": {
+        "summary": "Server-Sent Events stream of decoded positions and strokes",
+        "description": "Each event is named position, stroke_end or frame_error and carries a LiveEvent as JSON data.",
+        "responses": {
+          "200": { "description": "Event stream", "content": { "text/event-stream": { "schema": { "$ref": "#/components/schemas/LiveEvent" } } } }
+        }
+      }
+    },
+    "/openapi.json": {
+      "get": {
+        "summary": "This document",
+        "responses": { "200": { "description": "OpenAPI description" } }
+      }
+    }
+  },
+  "components": {
+    "schemas": {
+      "Grid": {
+        "description": "Rows of cells; a cell is an arrow (\"↑\", \"←\", \"→\", \"↓\"), a direction name (\"up\", ...) or a bit pair [x, y]. A row may also be a compact string such as \"↓←←→←→\", and the grid may be wrapped in extra arrays or sent as a JSON string. At least one window of the codec order (6x6 by default).",
+        "type": "array",
+        "items": { "oneOf": [{ "type": "array", "items": {} }, { "type": "string" }] }
+      },
+      "Error": {
+        "type": "object",
+        "properties": {
+          "error": { "type": "string" },
+          "kind": { "$ref": "#/components/schemas/ErrorKind" }
+        }
+      },
+      "ErrorKind": {
+        "description": "Which failure occurred: the decoding kinds mns_miss, sns_miss, delta_out_of_range and crt_failure mean the grid has the right shape but is not readable pattern.",
+        "type": "string",
+        "enum": ["io", "parse", "shape_mismatch", "mns_miss", "sns_miss", "delta_out_of_range", "crt_failure", "invalid", "format", "cancelled"]
+      },
+      "DecodeReport": {
+        "type": "object",
+        "properties": {
+          "height": { "type": "integer" },
+          "width": { "type": "integer" },
+          "decoded": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "properties": {
+                "row": { "type": "integer" },
+                "col": { "type": "integer" },
+                "position": { "type": "array", "items": { "type": "integer" }, "minItems": 2, "maxItems": 2 },
+                "section": { "type": "array", "items": { "type": "integer" }, "minItems": 2, "maxItems": 2, "nullable": true }
+              }
+            }
+          },
+          "errors": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "properties": {
+                "row": { "type": "integer" },
+                "col": { "type": "integer" },
+                "error": { "type": "string" },
+                "kind": { "$ref": "#/components/schemas/ErrorKind" }
+              }
+            }
+          }
+        }
+      },
+      "EncodeRequest": {
+        "type": "object",
+        "required": ["x", "y"],
+        "properties": {
+          "x": { "type": "integer", "description": "Any signed dot coordinate; the pattern repeats every codec period" },
+          "y": { "type": "integer", "description": "Any signed dot coordinate; the pattern repeats every codec period" },
+          "sect_u": { "type": "integer", "default": 10 },
+          "sect_v": { "type": "integer", "default": 2 },
+          "width": { "type": "integer", "description": "Defaults to the codec window order", "minimum": 1, "maximum": 1000 },
+          "height": { "type": "integer", "description": "Defaults to the codec window order", "minimum": 1, "maximum": 1000 }
+        }
+      },
+      "EncodeResponse": {
+        "type": "object",
+        "properties": {
+          "x": { "type": "integer" },
+          "y": { "type": "integer" },
+          "sect_u": { "type": "integer" },
+          "sect_v": { "type": "integer" },
+          "bits": { "type": "array", "items": { "type": "array", "items": { "type": "array", "items": { "type": "integer" } } } },
+          "arrows": { "type": "array", "items": { "type": "array", "items": { "type": "string" } } }
+        }
+      },
+      "PdfConfig": {
+        "type": "object",
+        "properties": {
+          "dpi": { "type": "number", "default": 600 },
+          "color_up": { "type": "string", "default": "#649037" },
+          "color_down": { "type": "string", "default": "#FEA501" },
+          "color_left": { "type": "string", "default": "#4041FE" },
+          "color_right": { "type": "string", "default": "#FF00FF" },
+          "dot_size": { "type": "number", "default": 1.0 },
+          "offset_from_origin": { "type": "number", "default": 3.0 },
+          "grid_spacing": { "type": "number", "default": 10.0 }
+        }
+      },
+      "GenerateRequest": {
+        "type": "object",
+        "properties": {
+          "height": { "type": "integer", "default": 9, "minimum": 1, "maximum": 200 },
+          "width": { "type": "integer", "default": 16, "minimum": 1, "maximum": 200 },
+          "sect_u": { "type": "integer", "default": 10 },
+          "sect_v": { "type": "integer", "default": 2 },
+          "format": { "type": "string", "enum": ["pdf", "png", "svg", "eps"], "default": "pdf" },
+          "config": { "$ref": "#/components/schemas/PdfConfig" },
+          "document": { "type": "string", "description": "Document the region is allocated to in the registry; REST_G__{height}__{width}__{sect_u}__{sect_v} by default" },
+          "reuse": { "type": "boolean", "default": false, "description": "Render even when another document holds the region" }
+        }
+      },
+      "VerifyRequest": {
+        "type": "object",
+        "required": ["grid", "x", "y"],
+        "properties": {
+          "grid": { "$ref": "#/components/schemas/Grid" },
+          "x": { "type": "integer", "description": "Any signed dot coordinate; the pattern repeats every codec period" },
+          "y": { "type": "integer", "description": "Any signed dot coordinate; the pattern repeats every codec period" },
+          "sect_u": { "type": "integer", "default": 10 },
+          "sect_v": { "type": "integer", "default": 2 }
+        }
+      },
+      "CaptureFrame": {
+        "type": "object",
+        "properties": {
+          "pen": { "type": "string", "default": "pen" },
+          "grid": { "$ref": "#/components/schemas/Grid" },
+          "pen_up": { "type": "boolean", "default": false, "description": "Ends the current stroke" }
+        }
+      },
+      "LiveEvent": {
+        "type": "object",
+        "required": ["type", "pen"],
+        "properties": {
+          "type": { "type": "string", "enum": ["position", "stroke_end", "frame_error"] },
+          "pen": { "type": "string" },
+          "stroke": { "type": "integer" },
+          "x": { "type": "number", "description": "Grid centre, in dots" },
+          "y": { "type": "number", "description": "Grid centre, in dots" },
+          "origin": { "type": "array", "items": { "type": "integer" }, "minItems": 2, "maxItems": 2 },
+          "section": { "type": "array", "items": { "type": "integer" }, "minItems": 2, "maxItems": 2, "nullable": true },
+          "error": { "type": "string" },
+          "kind": { "$ref": "#/components/schemas/ErrorKind" }
+        }
+      },
+      "VerifyResponse": {
+        "type": "object",
+        "properties": {
+          "matches": { "type": "boolean" },
+          "mismatches": {
+            "type": "array",
+            "items": {
+              "type": "object",
+              "properties": {
+                "row": { "type": "integer" },
+                "col": { "type": "integer" },
+                "expected": { "type": "string" },
+                "actual": { "type": "string" }
+              }
+            }
+          },
+          "decode": { "$ref": "#/components/schemas/DecodeReport" }
+        }
+      }
+    }
+  }
+}
+"##;
+
 const INDEX_HTML: &str = r#"
 <!DOCTYPE html>
 <html lang="en">
@@ -1330,32 +2145,37 @@ const INDEX_HTML: &str = r#"
         <h1>Anoto PDF Generator</h1>
         <p>The Anoto PDF Generator server is currently running and listening for requests.</p>
         <div class="status">System Online</div>
+        <p>API description: <a href="/openapi.json" style="color: #61dafb;">/openapi.json</a></p>
     </div>
 </body>
 </html>
 "#;
 
-async fn generate_and_save(params: GenerationParams) -> Result<(image::Handle, String, u32, u32), String> {
-    // This is a blocking operation, but we run it in an async block.
-    // In a real async runtime, we should use spawn_blocking.
-    // Since we don't have easy access to spawn_blocking without adding tokio dependency explicitly,
-    // we will just run it here. It might block the UI thread if the executor is single threaded.
-    // However, for the purpose of this task, we are using Task::perform.
-    
-    let result = (|| -> Result<(image::Handle, String, u32, u32), Box<dyn std::error::Error>> {
-        let bitmatrix = generate_matrix_only(params.height, params.width, params.sect_u, params.sect_v)?;
+fn generate_and_save(params: GenerationParams, progress: &JobProgress) -> Result<(image::Handle, String, u32, u32), String> {
+    let result = (|| -> Result<(image::Handle, String, u32, u32), AnotoError> {
         let base_filename = format!("GUI_G__{}__{}__{}__{}", params.height, params.width, params.sect_u, params.sect_v);
+        let pdf_path = format!("output/{}.pdf", base_filename);
+        let allocation = Allocation::new(&params.codec, &pdf_path, (params.sect_u, params.sect_v), (0, 0), (params.width, params.height), &pdf_path);
+        Registry::open(REGISTRY_FILE)?.check(&allocation, params.reuse)?;
+
+        progress.start("Encoding", 1);
+        let bitmatrix = params.codec.encode_bitmatrix((params.height, params.width), (params.sect_u, params.sect_v))?;
+        progress.advance(1);
+        progress.check()?;
         
         // Generate PDF
-        gen_pdf_from_matrix_data(&bitmatrix, &format!("{}.pdf", base_filename), &params.config)?;
+        let tiles = std::iter::once(PatternTile::whole(bitmatrix.clone()));
+        let pattern = PatternInfo::generated(&params.codec, (params.sect_u, params.sect_v), (0, 0));
+        gen_pdf_from_tiles_with_progress(tiles, (params.width, params.height), &format!("{}.pdf", base_filename), &params.config, &pattern, progress)?;
 
         // Generate PNG
         if !std::path::Path::new("output").exists() {
             std::fs::create_dir("output")?;
         }
-        let bitmatrix_i8 = bitmatrix.mapv(|x| x as i8);
         let png_path = format!("output/{}__X.png", base_filename);
-        draw_preview_image(&bitmatrix_i8, &params.config, &png_path)?;
+        draw_preview_image_with_progress(&bitmatrix, &params.config, &png_path, progress)?;
+
+        claim_allocation(allocation, params.reuse)?;
 
         // Load image bytes to force refresh
         let bytes = std::fs::read(&png_path)?;
@@ -1366,6 +2186,17 @@ async fn generate_and_save(params: GenerationParams) -> Result<(image::Handle, S
     result.map_err(|e| e.to_string())
 }
 
+// Serialises the registry updates of the GUI and the REST server
+static REGISTRY_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Records `allocation` unless, since it was first checked, another document took its pattern.
+fn claim_allocation(allocation: Allocation, reuse: bool) -> Result<(), AnotoError> {
+    let _guard = REGISTRY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut registry = Registry::open(REGISTRY_FILE)?;
+    registry.check(&allocation, reuse)?;
+    registry.record(allocation)
+}
+
 fn color_to_hex(color: Color) -> String {
     let r = (color.r * 255.0).round() as u8;
     let g = (color.g * 255.0).round() as u8;
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use super::AnotoCodec;
+use crate::error::AnotoError;
+
+/// Identity of one page of pattern, written `segment.shelf.book.page` like Anoto page addresses.
+/// A segment is one section; its pages are numbered row by row and grouped into books and shelves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PageAddress {
+    pub segment: u64,
+    pub shelf: u64,
+    pub book: u64,
+    pub page: u64,
+}
+
+impl fmt::Display for PageAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.segment, self.shelf, self.book, self.page)
+    }
+}
+
+impl FromStr for PageAddress {
+    type Err = AnotoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .trim()
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| AnotoError::Invalid(format!("Invalid page address '{}'", s)))?;
+        match parts[..] {
+            [segment, shelf, book, page] => Ok(PageAddress { segment, shelf, book, page }),
+            _ => Err(AnotoError::Invalid(format!("Expected a page address as segment.shelf.book.page, got '{}'", s))),
+        }
+    }
+}
+
+/// A section and a pattern position (x, y) in it
+pub type SectionPosition = ((i32, i32), (i64, i64));
+
+/// Pattern position (x, y) relative to the first dot of the page it falls on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PagePosition {
+    pub address: PageAddress,
+    pub local: (i64, i64),
+}
+
+/// Partition of a codec's pattern space into pages of `page_size` dots. Each section is cut into
+/// as many whole pages as fit its period along x and y; the strip past the last whole page is unused.
+#[derive(Clone, Debug)]
+pub struct AddressSpace {
+    /// (width, height) of a page in dots
+    pub page_size: (usize, usize),
+    pub pages_per_book: u64,
+    pub books_per_shelf: u64,
+    period: i64,
+    mns_length: usize,
+}
+
+impl AddressSpace {
+    /// Pattern of an A4 sheet inside 10 mm margins at the default spacing, as `--poster` prints it
+    pub const DEFAULT_PAGE_SIZE: (usize, usize) = (54, 78);
+
+    /// Pages of `page_size` dots, 100 pages to a book and 100 books to a shelf.
+    pub fn new(codec: &AnotoCodec, page_size: (usize, usize)) -> Result<Self, AnotoError> {
+        if page_size.0 < codec.mns_order() || page_size.1 < codec.mns_order() {
+            return Err(AnotoError::Invalid(format!("Pages need at least {0}x{0} dots to decode", codec.mns_order())));
+        }
+        if page_size.0 as i64 > codec.period() || page_size.1 as i64 > codec.period() {
+            return Err(AnotoError::Invalid(format!("Pages cannot be larger than the pattern period of {} dots", codec.period())));
+        }
+        Ok(AddressSpace {
+            page_size,
+            pages_per_book: 100,
+            books_per_shelf: 100,
+            period: codec.period(),
+            mns_length: codec.mns_length,
+        })
+    }
+
+    pub fn with_books(mut self, pages_per_book: u64, books_per_shelf: u64) -> Self {
+        self.pages_per_book = pages_per_book.max(1);
+        self.books_per_shelf = books_per_shelf.max(1);
+        self
+    }
+
+    /// Whole pages across and down one section.
+    pub fn pages_per_section(&self) -> (u64, u64) {
+        (self.period as u64 / self.page_size.0 as u64, self.period as u64 / self.page_size.1 as u64)
+    }
+
+    /// Page and local position of a decoded position `(x, y)` in `section`.
+    pub fn locate(&self, section: (i32, i32), position: (i64, i64)) -> Result<PagePosition, AnotoError> {
+        let (columns, rows) = self.pages_per_section();
+        let (x, y) = (position.0.rem_euclid(self.period), position.1.rem_euclid(self.period));
+        let (column, row) = (x as u64 / self.page_size.0 as u64, y as u64 / self.page_size.1 as u64);
+        if column >= columns || row >= rows {
+            return Err(AnotoError::Invalid(format!("Position ({}, {}) lies past the last whole page of its section", x, y)));
+        }
+
+        let mns_length = self.mns_length as i64;
+        let segment = (section.0 as i64).rem_euclid(mns_length) * mns_length + (section.1 as i64).rem_euclid(mns_length);
+        let index = row * columns + column;
+        let pages_per_shelf = self.pages_per_book.saturating_mul(self.books_per_shelf);
+        Ok(PagePosition {
+            address: PageAddress {
+                segment: segment as u64,
+                shelf: index / pages_per_shelf,
+                book: index % pages_per_shelf / self.pages_per_book,
+                page: index % self.pages_per_book,
+            },
+            local: (x - (column * self.page_size.0 as u64) as i64, y - (row * self.page_size.1 as u64) as i64),
+        })
+    }
+
+    /// Section and pattern position (x, y) of the first dot of page `address`.
+    pub fn page_origin(&self, address: &PageAddress) -> Result<SectionPosition, AnotoError> {
+        let (columns, rows) = self.pages_per_section();
+        let mns_length = self.mns_length as u64;
+        if address.segment >= mns_length * mns_length || address.book >= self.books_per_shelf || address.page >= self.pages_per_book {
+            return Err(AnotoError::Invalid(format!("Page address {} is outside the address space", address)));
+        }
+        let index = address
+            .shelf
+            .checked_mul(self.pages_per_book.saturating_mul(self.books_per_shelf))
+            .and_then(|index| index.checked_add(address.book.checked_mul(self.pages_per_book)?.checked_add(address.page)?))
+            // A page count past 64 bits is treated like a page past the end
+            .filter(|&index| columns.checked_mul(rows).is_some_and(|pages| index < pages))
+            .ok_or_else(|| AnotoError::Invalid(format!("Page address {} is past the last page of its segment", address)))?;
+
+        let section = ((address.segment / mns_length) as i32, (address.segment % mns_length) as i32);
+        let origin = ((index % columns * self.page_size.0 as u64) as i64, (index / columns * self.page_size.1 as u64) as i64);
+        Ok((section, origin))
+    }
+
+    /// Section and pattern position of `local` on page `address`, the inverse of `locate`.
+    pub fn position(&self, page: &PagePosition) -> Result<SectionPosition, AnotoError> {
+        let (local_x, local_y) = page.local;
+        if local_x < 0 || local_y < 0 || local_x >= self.page_size.0 as i64 || local_y >= self.page_size.1 as i64 {
+            return Err(AnotoError::Invalid(format!("Local position ({}, {}) is off the page", local_x, local_y)));
+        }
+        let (section, (x, y)) = self.page_origin(&page.address)?;
+        Ok((section, (x + local_x, y + local_y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::anoto_6x6_a4_fixed;
+
+    fn space() -> AddressSpace {
+        AddressSpace::new(&anoto_6x6_a4_fixed(), AddressSpace::DEFAULT_PAGE_SIZE).unwrap()
+    }
+
+    #[test]
+    fn locate_and_position_round_trip() {
+        let space = space();
+        let (columns, rows) = space.pages_per_section();
+        let (width, height) = (space.page_size.0 as i64, space.page_size.1 as i64);
+        let last = (columns as i64 * width - 1, rows as i64 * height - 1);
+        for position in [(0, 0), (53, 77), (54, 78), (12345, 67890), last] {
+            let page = space.locate((10, 2), position).unwrap();
+            assert_eq!(space.position(&page).unwrap(), ((10, 2), position), "{:?}", page);
+        }
+        let page = space.locate((10, 2), last).unwrap();
+        assert_eq!(page.local, (width - 1, height - 1));
+        assert_eq!(space.locate((10, 2), (54, 78)).unwrap().local, (0, 0));
+    }
+
+    #[test]
+    fn page_origin_is_local_zero() {
+        let space = space();
+        let (columns, rows) = space.pages_per_section();
+        let pages_per_shelf = space.pages_per_book * space.books_per_shelf;
+        let last = columns * rows - 1;
+        for index in [0, 1, columns - 1, columns, last] {
+            let address = PageAddress { segment: 7, shelf: index / pages_per_shelf, book: index % pages_per_shelf / 100, page: index % 100 };
+            let (section, origin) = space.page_origin(&address).unwrap();
+            assert_eq!(space.locate(section, origin).unwrap(), PagePosition { address, local: (0, 0) });
+        }
+        let past = last + 1;
+        let address = PageAddress { segment: 7, shelf: past / pages_per_shelf, book: past % pages_per_shelf / 100, page: past % 100 };
+        assert!(matches!(space.page_origin(&address), Err(AnotoError::Invalid(_))));
+    }
+
+    #[test]
+    fn positions_past_the_last_whole_page_have_no_page() {
+        let space = space();
+        let codec = anoto_6x6_a4_fixed();
+        let (columns, rows) = space.pages_per_section();
+        let strip = (columns as i64 * space.page_size.0 as i64, rows as i64 * space.page_size.1 as i64);
+        assert!(strip.0 < codec.period() && strip.1 < codec.period());
+        assert!(matches!(space.locate((10, 2), (strip.0, 0)), Err(AnotoError::Invalid(_))));
+        assert!(matches!(space.locate((10, 2), (0, strip.1)), Err(AnotoError::Invalid(_))));
+        assert!(matches!(space.locate((10, 2), (codec.period() - 1, 0)), Err(AnotoError::Invalid(_))));
+        // Positions wrap around the period before they are placed on a page
+        assert_eq!(space.locate((10, 2), (codec.period(), -codec.period())).unwrap(), space.locate((10, 2), (0, 0)).unwrap());
+    }
+
+    #[test]
+    fn segments_are_sections() {
+        let space = space();
+        let mns_length = anoto_6x6_a4_fixed().mns_length as i32;
+        let page = space.locate((10, 2), (0, 0)).unwrap();
+        assert_eq!(page.address.segment, (10 * mns_length + 2) as u64);
+        assert_eq!(space.locate((10 + mns_length, 2 - mns_length), (0, 0)).unwrap(), page);
+        assert_eq!(space.page_origin(&page.address).unwrap(), ((10, 2), (0, 0)));
+        let outside = PageAddress { segment: (mns_length * mns_length) as u64, ..page.address };
+        assert!(space.page_origin(&outside).is_err());
+        assert!(space.position(&PagePosition { local: (54, 0), ..page }).is_err());
+        assert!(space.position(&PagePosition { local: (-1, 0), ..page }).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{AnotoCodec, anoto_6x6_a4_fixed_definition};
+use crate::error::AnotoError;
+
+/// Everything `AnotoCodec::new` needs, in a form that can live in a JSON file:
+///
+/// ```json
+/// { "name": "my_codec", "mns": [0, 0, 1, ...], "mns_order": 6,
+///   "sns": [[0, 1, ...], ...], "pfactors": [3, 3, 2, 3], "delta_range": [5, 58] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodecDefinition {
+    #[serde(default)]
+    pub name: String,
+    pub mns: Vec<i8>,
+    pub mns_order: usize,
+    pub sns: Vec<Vec<i8>>,
+    pub pfactors: Vec<i32>,
+    pub delta_range: (i32, i32),
+}
+
+/// Names accepted by `resolve_codec` without a file.
+pub const BUILTIN_CODECS: &[&str] = &["anoto_6x6_a4_fixed"];
+
+/// Codec used when nothing else is picked.
+pub const DEFAULT_CODEC: &str = "anoto_6x6_a4_fixed";
+
+// Directory searched for `{name}.json` when a name is not built in
+const CODEC_DIR: &str = "codecs";
+
+impl CodecDefinition {
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, AnotoError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| AnotoError::from(e).in_file(format!("codec file {}", path.display())))?;
+        let mut definition: CodecDefinition = serde_json::from_str(&content)
+            .map_err(|e| AnotoError::from(e).in_file(format!("codec file {}", path.display())))?;
+        if definition.name.is_empty() {
+            definition.name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        }
+        Ok(definition)
+    }
+
+    /// Writes the definition with one sequence per line, so it stays readable and diffable.
+    pub fn save_json_file(&self, path: impl AsRef<Path>) -> Result<(), AnotoError> {
+        let sns: Vec<String> = self.sns.iter()
+            .map(|s| serde_json::to_string(s).map(|s| format!("    {}", s)))
+            .collect::<Result<_, _>>()?;
+        let json = format!(
+            "{{\n  \"name\": {},\n  \"mns_order\": {},\n  \"mns\": {},\n  \"sns\": [\n{}\n  ],\n  \"pfactors\": {},\n  \"delta_range\": {}\n}}\n",
+            serde_json::to_string(&self.name)?,
+            self.mns_order,
+            serde_json::to_string(&self.mns)?,
+            sns.join(",\n"),
+            serde_json::to_string(&self.pfactors)?,
+            serde_json::to_string(&self.delta_range)?,
+        );
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    // Only what `AnotoCodec::new` and the encoder would otherwise panic on
+    pub(super) fn check_shape(&self) -> Result<(), AnotoError> {
+        if self.mns_order < 2 {
+            return Err(AnotoError::shape("mns_order of at least 2", self.mns_order.to_string()));
+        }
+        if self.mns.len() < self.mns_order {
+            return Err(AnotoError::shape(format!("an MNS of at least its order {} bits", self.mns_order), format!("{} bits", self.mns.len())));
+        }
+        if self.sns.is_empty() || self.sns.len() != self.pfactors.len() {
+            return Err(AnotoError::shape(
+                "one prime factor per SNS",
+                format!("{} SNS and {} factors", self.sns.len(), self.pfactors.len()),
+            ));
+        }
+        if let Some(i) = self.sns.iter().position(|s| s.len() < self.mns_order - 1) {
+            return Err(AnotoError::shape(format!("SNS {} of at least its order {}", i, self.mns_order - 1), format!("{} digits", self.sns[i].len())));
+        }
+        if self.pfactors.iter().any(|&p| p < 2) {
+            return Err(AnotoError::shape("prime factors of at least 2", format!("{:?}", self.pfactors)));
+        }
+        if self.delta_range.0 > self.delta_range.1 {
+            return Err(AnotoError::shape("a non-empty delta_range", format!("{:?}", self.delta_range)));
+        }
+        Ok(())
+    }
+}
+
+impl AnotoCodec {
+    /// Builds a codec from a definition, e.g. one loaded with `CodecDefinition::from_json_file`.
+    /// Definitions that fail `CodecDefinition::validate` are rejected with the full report.
+    pub fn from_definition(definition: &CodecDefinition) -> Result<Self, AnotoError> {
+        let report = definition.validate();
+        if !report.is_valid() {
+            return Err(report.to_string().into());
+        }
+        Ok(AnotoCodec::new(
+            definition.mns.clone(),
+            definition.mns_order,
+            definition.sns.clone(),
+            definition.pfactors.clone(),
+            definition.delta_range,
+        )
+        .with_name(&definition.name))
+    }
+}
+
+/// Definition of a built-in codec by name.
+pub fn builtin_codec_definition(name: &str) -> Option<CodecDefinition> {
+    match name {
+        "anoto_6x6_a4_fixed" => Some(anoto_6x6_a4_fixed_definition()),
+        _ => None,
+    }
+}
+
+/// Finds a codec definition by built-in name, by path to a JSON file, or by name in the
+/// `codecs/` directory (`codecs/{name}.json`), in that order.
+pub fn resolve_codec_definition(spec: &str) -> Result<CodecDefinition, AnotoError> {
+    let spec = spec.trim();
+    if let Some(definition) = builtin_codec_definition(spec) {
+        return Ok(definition);
+    }
+    if Path::new(spec).is_file() {
+        return CodecDefinition::from_json_file(spec);
+    }
+    let named = Path::new(CODEC_DIR).join(format!("{}.json", spec));
+    if named.is_file() {
+        return CodecDefinition::from_json_file(named);
+    }
+    Err(format!(
+        "Unknown codec '{}': not a built-in ({}), a file, or {}/{}.json",
+        spec, BUILTIN_CODECS.join(", "), CODEC_DIR, spec
+    ).into())
+}
+
+/// `resolve_codec_definition` followed by `AnotoCodec::from_definition`.
+pub fn resolve_codec(spec: &str) -> Result<AnotoCodec, AnotoError> {
+    AnotoCodec::from_definition(&resolve_codec_definition(spec)?)
+}
@@ -1,8 +1,24 @@
 use ndarray::{Array2, Array3, Axis, s};
-use std::error::Error;
-use std::fmt;
+use rayon::prelude::*;
+use crate::error::AnotoError;
+
+pub mod address;
+pub mod definition;
+pub mod synth;
+pub mod tiles;
+pub mod validate;
+pub use address::*;
+pub use definition::*;
+pub use synth::*;
+pub use tiles::*;
+pub use validate::*;
 
 pub fn anoto_6x6_a4_fixed() -> AnotoCodec {
+    let d = anoto_6x6_a4_fixed_definition();
+    AnotoCodec::new(d.mns, d.mns_order, d.sns, d.pfactors, d.delta_range).with_name(&d.name)
+}
+
+pub fn anoto_6x6_a4_fixed_definition() -> CodecDefinition {
     let mns = vec![
         0,0,0,0,0,0,1,0,0,1,1,1,1,1,0,1,0,0,
         1,0,0,0,0,1,1,1,0,1,1,1,0,0,1,0,1,0,
@@ -61,34 +77,13 @@ pub fn anoto_6x6_a4_fixed() -> AnotoCodec {
         2, 0, 1, 2, 0, 1, 0, 1, 1, 0, 2, 0, 1, 1, 0, 1, 0, 1, 0, 0, 1
     ];
     
-    AnotoCodec::new(
-        mns.into_iter().map(|x| x as i8).collect(),
-        6,
-        vec![a1, a2, a3, a4_alt].into_iter().map(|a| a.into_iter().map(|x| x as i8).collect()).collect(),
-        vec![3, 3, 2, 3],
-        (5, 58),
-    )
-}
-
-// Custom error type for decoding errors
-#[derive(Debug)]
-pub struct DecodingError {
-    message: String,
-}
-
-impl fmt::Display for DecodingError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Decoding error: {}", self.message)
-    }
-}
-
-impl Error for DecodingError {}
-
-impl DecodingError {
-    pub fn new(message: &str) -> Self {
-        DecodingError {
-            message: message.to_string(),
-        }
+    CodecDefinition {
+        name: "anoto_6x6_a4_fixed".to_string(),
+        mns: mns.into_iter().map(|x| x as i8).collect(),
+        mns_order: 6,
+        sns: vec![a1, a2, a3, a4_alt].into_iter().map(|a| a.into_iter().map(|x| x as i8).collect()).collect(),
+        pfactors: vec![3, 3, 2, 3],
+        delta_range: (5, 58),
     }
 }
 
@@ -102,16 +97,6 @@ impl NumberBasis {
         NumberBasis { factors }
     }
 
-    fn reconstruct(&self, coeffs: &[i8]) -> i64 {
-        let mut result = 0i64;
-        let mut base = 1i64;
-        for (i, &coeff) in coeffs.iter().enumerate() {
-            result += coeff as i64 * base;
-            base *= self.factors[i];
-        }
-        result
-    }
-
     fn project(&self, values: &[i64]) -> Vec<Vec<i8>> {
         let mut result = vec![vec![0i8; self.factors.len()]; values.len()];
         for (i, &val) in values.iter().enumerate() {
@@ -135,27 +120,31 @@ impl CRT {
         CRT { moduli }
     }
 
-    fn solve(&self, remainders: &[i64]) -> Result<i64, DecodingError> {
+    // Intermediate products go through i128, so any period that fits i64 solves without wrapping
+    fn solve(&self, remainders: &[i64]) -> Result<i64, AnotoError> {
         if remainders.len() != self.moduli.len() {
-            return Err(DecodingError::new("Remainders and moduli length mismatch"));
+            return Err(AnotoError::CrtFailure(format!("{} remainders for {} moduli", remainders.len(), self.moduli.len())));
         }
 
-        let mut result = 0i64;
-        let product: i64 = self.moduli.iter().product();
+        let product = self.moduli.iter()
+            .try_fold(1i128, |acc, &m| acc.checked_mul(m as i128).filter(|&p| p <= i64::MAX as i128))
+            .ok_or_else(|| AnotoError::CrtFailure("CRT modulus product exceeds the 64-bit position range".to_string()))?;
+        let mut result = 0i128;
 
         for (&remainder, &modulus) in remainders.iter().zip(self.moduli.iter()) {
-            let partial_product = product / modulus;
-            let inverse = self.mod_inverse(partial_product, modulus)?;
-            result = (result + remainder * partial_product * inverse) % product;
+            let partial_product = product / modulus as i128;
+            let inverse = self.mod_inverse((partial_product % modulus as i128) as i64, modulus)?;
+            let term = (remainder as i128 * partial_product) % product * inverse as i128 % product;
+            result = (result + term) % product;
         }
 
-        Ok(result)
+        i64::try_from(result).map_err(|_| AnotoError::CrtFailure("CRT result exceeds the 64-bit position range".to_string()))
     }
 
-    fn mod_inverse(&self, a: i64, m: i64) -> Result<i64, DecodingError> {
+    fn mod_inverse(&self, a: i64, m: i64) -> Result<i64, AnotoError> {
         let (gcd, x, _) = self.extended_gcd(a, m);
         if gcd != 1 {
-            return Err(DecodingError::new("Modular inverse does not exist"));
+            return Err(AnotoError::CrtFailure(format!("{} has no inverse modulo {}", a, m)));
         }
         Ok((x % m + m) % m)
     }
@@ -174,17 +163,20 @@ impl CRT {
 
 // Main Anoto codec implementation
 pub struct AnotoCodec {
+    name: String,
     mns: Vec<i8>,
     pub mns_length: usize,
     mns_cyclic: Vec<i8>,
     mns_order: usize,
     _sns_order: usize,
     _sns: Vec<Vec<i8>>,
-    sns_lengths: Vec<usize>,
     sns_cyclic: Vec<Vec<i8>>,
+    // sns_prefix[j][k] is the sum of the first k digits of SNS j
+    sns_prefix: Vec<Vec<i64>>,
     num_basis: NumberBasis,
     crt: CRT,
     delta_range: (i32, i32),
+    period: i64,
 }
 
 impl AnotoCodec {
@@ -202,108 +194,111 @@ impl AnotoCodec {
         let sns_cyclic: Vec<Vec<i8>> = sns.iter().map(|s| make_cyclic(s, sns_order)).collect();
         let num_basis = NumberBasis::new(pfactors.into_iter().map(|x| x as i64).collect());
         let crt = CRT::new(sns_lengths.iter().map(|&l| l as i64).collect());
+        let sns_prefix = sns.iter()
+            .map(|s| std::iter::once(0).chain(s.iter().scan(0i64, |acc, &d| { *acc += d as i64; Some(*acc) })).collect())
+            .collect();
+        // Saturates for definitions `validate` would refuse
+        let period = sns_lengths.iter()
+            .try_fold(1i64, |acc, &l| acc.checked_mul(l as i64))
+            .unwrap_or(i64::MAX);
 
         AnotoCodec {
+            name: String::new(),
             mns,
             mns_length,
             mns_cyclic,
             mns_order,
             _sns_order: sns_order,
             _sns: sns,
-            sns_lengths,
             sns_cyclic,
+            sns_prefix,
             num_basis,
             crt,
             delta_range,
+            period,
         }
     }
 
-    pub fn encode_bitmatrix(&self, shape: (usize, usize), section: (i32, i32)) -> Array3<i8> {
-        // Find nearest multiples of MNS length for ease of generation
-        let mshape = (
-            (self.mns_length as f64 * (shape.0 as f64 / self.mns_length as f64).ceil()) as usize,
-            (self.mns_length as f64 * (shape.1 as f64 / self.mns_length as f64).ceil()) as usize,
-        );
-
-        let mut m = Array3::<i8>::zeros((mshape.0, mshape.1, 2));
-
-        // x-direction
-        let mut roll = section.0 % self.mns_length as i32;
-        let _ytiles = mshape.0 / self.mns_length;
-
-        for x in 0..mshape.1 {
-            roll = self.next_roll(x as i32, roll);
-            let rolled_mns = rotate_vec(&self.mns, -(roll as isize));
-            let tiled: Vec<i8> = rolled_mns.iter().cycle().take(mshape.0).cloned().collect();
-
-            for y in 0..mshape.0 {
-                m[[y, x, 0]] = tiled[y];
-            }
-        }
+    /// Names the codec, as its definition does; generated PDFs record it.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
 
-        // y-direction
-        let mut roll = section.1 % self.mns_length as i32;
-        let _xtiles = mshape.1 / self.mns_length;
+    /// Definition name, empty for codecs built with `new` alone.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-        for y in 0..mshape.0 {
-            roll = self.next_roll(y as i32, roll);
-            let rolled_mns = rotate_vec(&self.mns, -(roll as isize));
-            let tiled: Vec<i8> = rolled_mns.iter().cycle().take(mshape.1).cloned().collect();
+    pub fn mns_order(&self) -> usize {
+        self.mns_order
+    }
 
-            for x in 0..mshape.1 {
-                m[[y, x, 1]] = tiled[x];
-            }
-        }
+    /// Number of distinct positions along each axis, the product of the SNS lengths.
+    pub fn period(&self) -> i64 {
+        self.period
+    }
 
-        // Return the requested shape
-        m.slice(s![0..shape.0, 0..shape.1, ..]).to_owned()
+    /// The position `decode_position` reports for a window whose top-left dot is at signed `pos`.
+    pub fn wrap_position(&self, pos: i64) -> i64 {
+        pos.rem_euclid(self.period)
     }
 
-    fn next_roll(&self, pos: i32, prev_roll: i32) -> i32 {
-        if pos == 0 {
-            return prev_roll;
+    /// Errors when a `size` long run starting at `start` does not fit the 64-bit coordinate range,
+    /// or reaches past one period, where its dots would repeat.
+    pub fn check_span(&self, start: i64, size: usize) -> Result<(), AnotoError> {
+        if i64::try_from(size).ok().and_then(|size| start.checked_add(size)).is_none() {
+            return Err(AnotoError::Invalid(format!(
+                "Positions from {} over {} dots leave the 64-bit coordinate range",
+                start, size
+            )));
+        }
+        if size as u64 > self.period as u64 {
+            return Err(AnotoError::Invalid(format!(
+                "Positions from {} over {} dots reach past the pattern period of {} dots",
+                start, size, self.period
+            )));
         }
-        (prev_roll + self.delta(pos - 1)) % self.mns_length as i32
+        Ok(())
     }
 
-    fn delta(&self, pos: i32) -> i32 {
-        let rs: Vec<usize> = self.sns_lengths.iter()
-            .map(|&len| (pos as usize) % len)
-            .collect();
-
-        let coeffs: Vec<i8> = rs.iter().zip(self.sns_cyclic.iter())
-            .map(|(&r, s)| s[r])
-            .collect();
-
-        (self.num_basis.reconstruct(&coeffs) + self.delta_range.0 as i64) as i32
+    /// The `(height, width)` pattern at the origin of `section`. Errors for areas longer than
+    /// the period, which would repeat dots.
+    pub fn encode_bitmatrix(&self, shape: (usize, usize), section: (i32, i32)) -> Result<Array3<i8>, AnotoError> {
+        let rolls = (section.0 % self.mns_length as i32, section.1 % self.mns_length as i32);
+        // Rows and columns are integrated in closed form, so this is the patch at the origin
+        self.encode_patch((0, 0), (shape.1, shape.0), rolls)
     }
 
-    pub fn decode_position(&self, bits: &Array3<i8>) -> Result<(i32, i32), DecodingError> {
+    pub fn decode_position(&self, bits: &Array3<i8>) -> Result<(i64, i64), AnotoError> {
         if bits.dim().2 != 2 {
-            return Err(DecodingError::new("Expected (M,N,2) matrix"));
+            return Err(AnotoError::shape("an (M,N,2) matrix", format!("{:?}", bits.dim())));
+        }
+        if bits.dim().0 < self.mns_order || bits.dim().1 < self.mns_order {
+            return Err(AnotoError::shape(format!("at least a {0}x{0} window", self.mns_order), format!("{}x{}", bits.dim().0, bits.dim().1)));
         }
 
         let sub_bits = bits.slice(s![0..self.mns_order, 0..self.mns_order, ..]);
         
         // Decode x (transpose for x-direction)
         let x_bits = sub_bits.slice(s![.., .., 0]).t().to_owned();
-        let x = self.decode_position_along_direction(&x_bits)?;
+        let x = self.decode_position_along_direction(&x_bits, 'x')?;
         
         // Decode y
         let y_bits = sub_bits.slice(s![.., .., 1]).to_owned();
-        let y = self.decode_position_along_direction(&y_bits)?;
+        let y = self.decode_position_along_direction(&y_bits, 'y')?;
 
         Ok((x, y))
     }
 
-    fn decode_position_along_direction(&self, bits: &Array2<i8>) -> Result<i32, DecodingError> {
+    fn decode_position_along_direction(&self, bits: &Array2<i8>, axis: char) -> Result<i64, AnotoError> {
         let mut locs = Vec::new();
         
         for row in bits.axis_iter(Axis(0)) {
             let row_vec: Vec<i8> = row.to_vec();
             match find_subsequence(&self.mns_cyclic, &row_vec) {
                 Some(pos) => locs.push(pos as i32),
-                None => return Err(DecodingError::new("Failed to find partial sequence in MNS")),
+                None => return Err(AnotoError::MnsMiss { axis }),
             }
         }
 
@@ -312,7 +307,7 @@ impl AnotoCodec {
         for i in 1..locs.len() {
             let diff = (locs[i] - locs[i-1] + self.mns_length as i32) % self.mns_length as i32;
             if diff < self.delta_range.0 || diff > self.delta_range.1 {
-                return Err(DecodingError::new("Delta value out of range"));
+                return Err(AnotoError::DeltaOutOfRange { delta: diff, range: self.delta_range });
             }
             deltae.push(diff - self.delta_range.0);
         }
@@ -327,69 +322,81 @@ impl AnotoCodec {
             let coeff_seq: Vec<i8> = coeffs.iter().map(|c| c[i]).collect();
             match find_subsequence(sns_seq, &coeff_seq) {
                 Some(pos) => ps.push(pos as i64),
-                None => return Err(DecodingError::new("Failed to find coefficients in SNS")),
+                None => return Err(AnotoError::SnsMiss { sequence: i }),
             }
         }
 
-        self.crt.solve(&ps).map(|x| x as i32)
+        self.crt.solve(&ps)
     }
 
-    pub fn decode_section(&self, bits: &Array3<i8>, pos: (i32, i32)) -> Result<(i32, i32), DecodingError> {
+    pub fn decode_section(&self, bits: &Array3<i8>, pos: (i64, i64)) -> Result<(i32, i32), AnotoError> {
         let px_seq = bits.slice(s![0..self.mns_order, 0, 0]).to_vec();
         let py_seq = bits.slice(s![0, 0..self.mns_order, 1]).to_vec();
 
         let px_mns = find_subsequence(&self.mns_cyclic, &px_seq)
-            .ok_or_else(|| DecodingError::new("Failed to find x sequence in MNS"))?;
+            .ok_or(AnotoError::MnsMiss { axis: 'x' })?;
         let py_mns = find_subsequence(&self.mns_cyclic, &py_seq)
-            .ok_or_else(|| DecodingError::new("Failed to find y sequence in MNS"))?;
+            .ok_or(AnotoError::MnsMiss { axis: 'y' })?;
 
         let sx = self.integrate_roll(pos.0, 0);
         let sy = self.integrate_roll(pos.1, 0);
 
-        let section_x = (px_mns as i32 - pos.1 - sx) % self.mns_length as i32;
-        let section_y = (py_mns as i32 - pos.0 - sy) % self.mns_length as i32;
+        let mns_length = self.mns_length as i64;
+        let section_x = (px_mns as i64 - pos.1 - sx as i64).rem_euclid(mns_length) as i32;
+        let section_y = (py_mns as i64 - pos.0 - sy as i64).rem_euclid(mns_length) as i32;
 
         Ok((section_x, section_y))
     }
 
-    pub fn integrate_roll(&self, pos: i32, first_roll: i32) -> i32 {
-        let mut r = 0i64;
-        for i in 0..pos {
-            r += self.delta(i) as i64;
+    /// MNS roll at `pos`: `first_roll` plus the deltas of all earlier positions, modulo the MNS length.
+    pub fn integrate_roll(&self, pos: i64, first_roll: i32) -> i32 {
+        // Each SNS repeats with its own length, so sum whole repeats and the partial tail per SNS
+        let mns_length = self.mns_length as i128;
+        let mut r = self.delta_range.0 as i128 * pos as i128;
+        let mut base = 1i128;
+        for (prefix, &factor) in self.sns_prefix.iter().zip(&self.num_basis.factors) {
+            let len = (prefix.len() - 1) as i64;
+            let repeats = pos.div_euclid(len) as i128;
+            let tail = prefix[pos.rem_euclid(len) as usize] as i128;
+            r = (r + base * (repeats * prefix[prefix.len() - 1] as i128 + tail)) % mns_length;
+            base *= factor as i128;
         }
-        ((first_roll as i64 + r) % self.mns_length as i64) as i32
+        (first_roll as i128 + r).rem_euclid(mns_length) as i32
     }
 
-    pub fn encode_patch(&self, pos: (i32, i32), size: (usize, usize), section_start_rolls: (i32, i32)) -> Array3<i8> {
+    /// Bits of the `size` = (width, height) patch whose top-left dot is at `pos`, which may be
+    /// negative. The pattern repeats every `period()` dots along both axes; windows fully on one
+    /// side of the seam decode to their position modulo the period, the `order - 1` straddling it are not valid code.
+    pub fn encode_patch(&self, pos: (i64, i64), size: (usize, usize), section_start_rolls: (i32, i32)) -> Result<Array3<i8>, AnotoError> {
         let (x_start, y_start) = pos;
         let (w, h) = size;
-        let mut m = Array3::<i8>::zeros((h, w, 2));
-
-        // x-direction
-        for c in 0..w {
-            let abs_x = x_start + c as i32;
-            let roll = self.integrate_roll(abs_x, section_start_rolls.0);
-            let rolled_mns = rotate_vec(&self.mns, -(roll as isize));
-            
-            for r in 0..h {
-                let abs_y = y_start + r as i32;
-                m[[r, c, 0]] = rolled_mns[(abs_y as usize) % self.mns_length];
-            }
-        }
-
-        // y-direction
-        for r in 0..h {
-            let abs_y = y_start + r as i32;
-            let roll = self.integrate_roll(abs_y, section_start_rolls.1);
-            let rolled_mns = rotate_vec(&self.mns, -(roll as isize));
-            
-            for c in 0..w {
-                let abs_x = x_start + c as i32;
-                m[[r, c, 1]] = rolled_mns[(abs_x as usize) % self.mns_length];
-            }
+        self.check_span(x_start, w)?;
+        self.check_span(y_start, h)?;
+        let mns_length = self.mns_length as i64;
+
+        // Column c carries the MNS rolled by x_rolls[c] down the y axis, row r the MNS rolled by y_rolls[r]
+        // along the x axis. Every roll is independent of its neighbours, so both run in parallel.
+        let wrapped = |start: i64, n: usize| -> Vec<i64> {
+            (0..n).into_par_iter().map(|i| self.wrap_position(start + i as i64)).collect()
+        };
+        let abs_x = wrapped(x_start, w);
+        let abs_y = wrapped(y_start, h);
+        let rolls = |abs: &[i64], first_roll: i32| -> Vec<i64> {
+            abs.par_iter().map(|&p| self.integrate_roll(p, first_roll) as i64).collect()
+        };
+        let x_rolls = rolls(&abs_x, section_start_rolls.0);
+        let y_rolls = rolls(&abs_y, section_start_rolls.1);
+
+        let mut data = vec![0i8; h * w * 2];
+        if w > 0 {
+            data.par_chunks_mut(w * 2).enumerate().for_each(|(r, row)| {
+                for (c, cell) in row.chunks_mut(2).enumerate() {
+                    cell[0] = self.mns[((abs_y[r] + x_rolls[c]) % mns_length) as usize];
+                    cell[1] = self.mns[((abs_x[c] + y_rolls[r]) % mns_length) as usize];
+                }
+            });
         }
-        
-        m
+        Ok(Array3::from_shape_vec((h, w, 2), data).expect("one x and one y bit per cell"))
     }
 }
 
@@ -400,27 +407,97 @@ fn make_cyclic(seq: &[i8], order: usize) -> Vec<i8> {
     result
 }
 
-fn rotate_vec(vec: &[i8], shift: isize) -> Vec<i8> {
-    let len = vec.len() as isize;
-    let shift = ((shift % len) + len) % len;
-    let mut result = Vec::with_capacity(vec.len());
-    
-    // For positive shift, rotate right; for negative shift, rotate left
-    if shift >= 0 {
-        // Rotate right by shift
-        result.extend_from_slice(&vec[(len - shift) as usize..]);
-        result.extend_from_slice(&vec[0..(len - shift) as usize]);
-    } else {
-        // Rotate left by -shift
-        let left_shift = (-shift) as usize;
-        result.extend_from_slice(&vec[left_shift..]);
-        result.extend_from_slice(&vec[0..left_shift]);
-    }
-    
-    result
-}
 
 fn find_subsequence(haystack: &[i8], needle: &[i8]) -> Option<usize> {
     haystack.windows(needle.len())
         .position(|window| window == needle)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROLLS: (i32, i32) = (10, 2);
+
+    fn window_at(codec: &AnotoCodec, pos: (i64, i64)) -> Array3<i8> {
+        let order = codec.mns_order();
+        codec.encode_patch(pos, (order, order), ROLLS).unwrap()
+    }
+
+    #[test]
+    fn decodes_position_zero() {
+        let codec = anoto_6x6_a4_fixed();
+        assert_eq!(codec.decode_position(&window_at(&codec, (0, 0))).unwrap(), (0, 0));
+        assert_eq!(codec.decode_section(&window_at(&codec, (0, 0)), (0, 0)).unwrap(), ROLLS);
+    }
+
+    #[test]
+    fn decodes_up_to_period_minus_one() {
+        let codec = anoto_6x6_a4_fixed();
+        let last = codec.period() - codec.mns_order() as i64;
+        // The last window before the seam ends on dot period - 1
+        assert_eq!(codec.decode_position(&window_at(&codec, (last, last))).unwrap(), (last, last));
+        assert_eq!(codec.decode_position(&window_at(&codec, (last, 0))).unwrap(), (last, 0));
+        assert_eq!(codec.decode_section(&window_at(&codec, (last, last)), (last, last)).unwrap(), ROLLS);
+        // Signed positions name the same dots
+        assert_eq!(window_at(&codec, (-(codec.mns_order() as i64), 0)), window_at(&codec, (last, 0)));
+    }
+
+    #[test]
+    fn decodes_windows_around_the_wrap() {
+        let codec = anoto_6x6_a4_fixed();
+        let order = codec.mns_order();
+        let start = codec.period() - 8;
+        let patch = codec.encode_patch((start, 0), (16, order), ROLLS).unwrap();
+        for col in 0..=(16 - order) {
+            let window = patch.slice(s![.., col..col + order, ..]).to_owned();
+            let x = codec.wrap_position(start + col as i64);
+            let decoded = codec.decode_position(&window).ok();
+            if x + order as i64 <= codec.period() || x < start {
+                assert_eq!(decoded, Some((x, 0)), "window at column {}", col);
+            } else {
+                // Straddles the seam, which is not valid code
+                assert_ne!(decoded, Some((x, 0)), "window at column {}", col);
+            }
+        }
+    }
+
+    #[test]
+    fn crt_solves_near_the_64_bit_limit() {
+        // Remainder * partial product alone overflows i64 here
+        let crt = CRT::new(vec![2147483647, 2147483629]);
+        assert_eq!(crt.solve(&[2147483646, 2147483628]).unwrap(), 2147483647 * 2147483629 - 1);
+    }
+
+    #[test]
+    fn crt_reports_overflow_instead_of_wrapping() {
+        let crt = CRT::new(vec![4294967291, 4294967279, 65521]);
+        assert!(matches!(crt.solve(&[1, 2, 3]), Err(AnotoError::CrtFailure(_))));
+        assert!(matches!(CRT::new(vec![3, 5]).solve(&[1]), Err(AnotoError::CrtFailure(_))));
+    }
+
+    #[test]
+    fn check_span_rejects_runs_past_the_period() {
+        let codec = anoto_6x6_a4_fixed();
+        let period = codec.period();
+        assert!(codec.check_span(0, period as usize).is_ok());
+        assert!(codec.check_span(period - 1, period as usize).is_ok());
+        assert!(matches!(codec.check_span(0, period as usize + 1), Err(AnotoError::Invalid(_))));
+        assert!(matches!(codec.check_span(period - 5, period as usize + 1), Err(AnotoError::Invalid(_))));
+        assert!(matches!(codec.check_span(i64::MAX - 2, 3), Err(AnotoError::Invalid(_))));
+        assert!(codec.encode_patch((period - 5, 0), (period as usize + 1, 1), ROLLS).is_err());
+        assert!(matches!(codec.encode_bitmatrix((1, period as usize + 1), ROLLS), Err(AnotoError::Invalid(_))));
+        assert_eq!(codec.encode_bitmatrix((6, 6), ROLLS).unwrap(), window_at(&codec, (0, 0)));
+    }
+
+    #[test]
+    fn wrap_position_maps_negative_positions_into_the_period() {
+        let codec = anoto_6x6_a4_fixed();
+        let period = codec.period();
+        assert_eq!(codec.wrap_position(-1), period - 1);
+        assert_eq!(codec.wrap_position(-period), 0);
+        assert_eq!(codec.wrap_position(-period - 1), period - 1);
+        assert_eq!(codec.wrap_position(period), 0);
+        assert!((0..period).contains(&codec.wrap_position(i64::MIN)));
+    }
+}
@@ -0,0 +1,231 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use super::validate::gcd;
+use super::{CodecDefinition, anoto_6x6_a4_fixed_definition};
+use crate::error::AnotoError;
+
+/// What to synthesize. Unset lengths pick the longest ones the order allows.
+#[derive(Clone, Debug)]
+pub struct SynthesisSpec {
+    pub name: String,
+    pub mns_order: usize,
+    /// Defaults to `2^mns_order - 1`, like the published 63-bit MNS
+    pub mns_length: Option<usize>,
+    /// One SNS per factor; the SNS digits run over `0..factor`
+    pub pfactors: Vec<i32>,
+    /// Must be pairwise coprime; defaults to the longest coprime lengths found
+    pub sns_lengths: Option<Vec<usize>>,
+    pub seed: u64,
+}
+
+impl Default for SynthesisSpec {
+    fn default() -> Self {
+        SynthesisSpec {
+            name: "synthetic".to_string(),
+            mns_order: 6,
+            mns_length: None,
+            pfactors: vec![3, 3, 2, 3],
+            sns_lengths: None,
+            seed: 0,
+        }
+    }
+}
+
+// Restarts from a new random start window before giving up on a length
+const SEARCH_ATTEMPTS: usize = 64;
+// Backtracking steps per attempt, per symbol of the target length
+const STEPS_PER_SYMBOL: usize = 2_000;
+
+/// Searches a binary cyclic sequence of `length` bits in which every window of `order` bits
+/// occurs once (a quasi de Bruijn sequence when `length` is close to `2^order`).
+pub fn synthesize_mns(order: usize, length: usize, rng: &mut StdRng) -> Result<Vec<i8>, AnotoError> {
+    synthesize_sequence(2, order, length, rng)
+}
+
+/// Searches a cyclic sequence of `length` digits in `0..radix` in which every window of `order` digits occurs once.
+pub fn synthesize_sns(radix: usize, order: usize, length: usize, rng: &mut StdRng) -> Result<Vec<i8>, AnotoError> {
+    synthesize_sequence(radix, order, length, rng)
+}
+
+/// Builds a complete codec definition from freshly searched sequences. The result passes
+/// `CodecDefinition::validate` and never reuses the published Anoto MNS.
+pub fn synthesize_codec(spec: &SynthesisSpec) -> Result<CodecDefinition, AnotoError> {
+    let order = spec.mns_order;
+    if !(2..=16).contains(&order) {
+        return Err(format!("mns_order must be between 2 and 16, got {}", order).into());
+    }
+    if spec.pfactors.is_empty() || spec.pfactors.iter().any(|&p| !(2..=127).contains(&p)) {
+        return Err(format!("Need at least one prime factor between 2 and 127, got {:?}", spec.pfactors).into());
+    }
+    let mns_length = spec.mns_length.unwrap_or((1 << order) - 1);
+    let basis: usize = spec.pfactors.iter().map(|&p| p as usize).product();
+    if basis + 1 > mns_length {
+        return Err(format!(
+            "The prime factors {:?} express {} deltas, more than an MNS of {} bits can hold",
+            spec.pfactors, basis, mns_length
+        ).into());
+    }
+
+    let mut rng = StdRng::seed_from_u64(spec.seed);
+    let published = anoto_6x6_a4_fixed_definition().mns;
+    let mns = loop {
+        let mns = synthesize_mns(order, mns_length, &mut rng)?;
+        if !is_rotation(&mns, &published) {
+            break mns;
+        }
+    };
+
+    let sns_order = order - 1;
+    let sns = match &spec.sns_lengths {
+        Some(lengths) => {
+            if lengths.len() != spec.pfactors.len() {
+                return Err(format!("Need one SNS length per factor, got {} lengths and {} factors", lengths.len(), spec.pfactors.len()).into());
+            }
+            lengths.iter().zip(&spec.pfactors)
+                .map(|(&len, &p)| synthesize_sns(p as usize, sns_order, len, &mut rng))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        None => {
+            let mut chosen: Vec<Vec<i8>> = Vec::new();
+            for &p in &spec.pfactors {
+                let max = (p as usize).checked_pow(sns_order as u32).filter(|&m| m <= 1 << 16)
+                    .ok_or_else(|| format!("SNS of order {} over {} digits is too long to search", sns_order, p))?;
+                let seq = (sns_order..=max).rev()
+                    .filter(|&len| chosen.iter().all(|s| gcd(s.len(), len) == 1))
+                    .find_map(|len| synthesize_sns(p as usize, sns_order, len, &mut rng).ok())
+                    .ok_or_else(|| format!("No SNS over {} digits is coprime with the others", p))?;
+                chosen.push(seq);
+            }
+            chosen
+        }
+    };
+
+    // Centre the deltas in the MNS length, as the published codec does (5..=58 of 63)
+    let low = ((mns_length - basis).div_ceil(2)).max(1) as i32;
+    let definition = CodecDefinition {
+        name: spec.name.clone(),
+        mns,
+        mns_order: order,
+        sns,
+        pfactors: spec.pfactors.clone(),
+        delta_range: (low, low + basis as i32 - 1),
+    };
+    let report = definition.validate();
+    if !report.is_valid() {
+        return Err(report.to_string().into());
+    }
+    Ok(definition)
+}
+
+fn synthesize_sequence(radix: usize, order: usize, length: usize, rng: &mut StdRng) -> Result<Vec<i8>, AnotoError> {
+    let windows = radix.checked_pow(order as u32).unwrap_or(usize::MAX);
+    if order < 2 || length < order || length > windows {
+        return Err(format!(
+            "No cyclic sequence of {} digits over {} symbols has unique windows of {} (at most {})",
+            length, radix, order, windows
+        ).into());
+    }
+    for _ in 0..SEARCH_ATTEMPTS {
+        if let Some(seq) = search_cycle(radix, order, length, rng) {
+            return Ok(seq);
+        }
+    }
+    Err(format!("Search for {} digits over {} symbols with window {} gave up", length, radix, order).into())
+}
+
+// Randomised depth-first search for a closed walk of `length` distinct edges in the de Bruijn
+// graph whose nodes are windows of `order - 1` digits; each edge is one window of `order` digits.
+fn search_cycle(radix: usize, order: usize, length: usize, rng: &mut StdRng) -> Option<Vec<i8>> {
+    let node_count = radix.pow(order as u32 - 1);
+    let start: Vec<usize> = (0..order - 1).map(|_| rng.random_range(0..radix)).collect();
+    let start_node = start.iter().fold(0, |node, &d| node * radix + d);
+    // From here on the walk has to spell the start window again to close the cycle
+    let closing = length - (order - 1);
+
+    let mut used = vec![false; node_count * radix];
+    let mut symbols: Vec<usize> = Vec::with_capacity(length);
+    let options = |step: usize, rng: &mut StdRng| -> Vec<usize> {
+        if step >= closing {
+            vec![start[step - closing]]
+        } else {
+            let mut digits: Vec<usize> = (0..radix).collect();
+            digits.shuffle(rng);
+            digits
+        }
+    };
+    // (node, candidate digits, next candidate)
+    let mut frames = vec![(start_node, options(0, rng), 0usize)];
+    let mut budget = STEPS_PER_SYMBOL * length;
+
+    while budget > 0 {
+        budget -= 1;
+        let frame = frames.last_mut()?;
+        if frame.2 == frame.1.len() {
+            frames.pop();
+            let digit = symbols.pop()?;
+            let node = frames.last()?.0;
+            used[node * radix + digit] = false;
+            continue;
+        }
+        let digit = frame.1[frame.2];
+        frame.2 += 1;
+        let edge = frame.0 * radix + digit;
+        if used[edge] {
+            continue;
+        }
+        used[edge] = true;
+        symbols.push(digit);
+        if symbols.len() == length {
+            let seq = start.iter().chain(&symbols[..closing]).map(|&d| d as i8).collect();
+            return Some(seq);
+        }
+        let next = options(symbols.len(), rng);
+        frames.push((edge % node_count, next, 0));
+    }
+    None
+}
+
+fn is_rotation(a: &[i8], b: &[i8]) -> bool {
+    a.len() == b.len() && (0..b.len()).any(|shift| a.iter().zip(b.iter().cycle().skip(shift)).all(|(x, y)| x == y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::AnotoCodec;
+
+    fn small(seed: u64) -> SynthesisSpec {
+        SynthesisSpec { mns_order: 5, pfactors: vec![3, 2], seed, ..Default::default() }
+    }
+
+    #[test]
+    fn synthesized_codecs_validate() {
+        let definition = synthesize_codec(&small(7)).unwrap();
+        assert!(definition.validate().is_valid());
+        assert_eq!(definition.mns.len(), 31);
+        assert_eq!(definition.delta_range.1 - definition.delta_range.0 + 1, 6);
+        assert!(AnotoCodec::from_definition(&definition).is_ok());
+    }
+
+    #[test]
+    fn same_seed_same_codec() {
+        let a = synthesize_codec(&small(7)).unwrap();
+        assert_eq!(a, synthesize_codec(&small(7)).unwrap());
+        assert_ne!(a, synthesize_codec(&small(8)).unwrap());
+    }
+
+    #[test]
+    fn never_reuses_the_published_mns() {
+        let published = anoto_6x6_a4_fixed_definition();
+        let spec = SynthesisSpec { sns_lengths: Some(vec![31, 29, 23, 19]), ..Default::default() };
+        for seed in 0..4 {
+            let definition = synthesize_codec(&SynthesisSpec { seed, ..spec.clone() }).unwrap();
+            assert!(!is_rotation(&definition.mns, &published.mns));
+            assert!(definition.validate().is_valid());
+        }
+        assert!(is_rotation(&[0, 1, 1], &[1, 0, 1]));
+        assert!(!is_rotation(&[0, 1, 1], &[1, 0, 0]));
+    }
+}
@@ -0,0 +1,71 @@
+use ndarray::Array3;
+
+use super::AnotoCodec;
+use crate::error::AnotoError;
+
+/// One rectangle of a larger pattern area, encoded on demand.
+#[derive(Clone, Debug)]
+pub struct PatternTile {
+    /// Offset of `bits[[0, 0]]` from the top-left of the whole area, in dots
+    pub row: usize,
+    pub col: usize,
+    /// `(rows, cols, 2)` like `encode_patch`
+    pub bits: Array3<i8>,
+}
+
+impl PatternTile {
+    /// Wraps an already materialised matrix as a single tile.
+    pub fn whole(bits: Array3<i8>) -> Self {
+        PatternTile { row: 0, col: 0, bits }
+    }
+}
+
+/// Iterator over the tiles of a `(width, height)` area, row of tiles by row of tiles.
+/// Only the tile being yielded is ever held in memory.
+pub struct PatternTiles<'a> {
+    codec: &'a AnotoCodec,
+    rolls: (i32, i32),
+    origin: (i64, i64),
+    size: (usize, usize),
+    tile: (usize, usize),
+    next: (usize, usize),
+}
+
+impl AnotoCodec {
+    /// Tiles of at most `tile` = (width, height) dots covering the `size` = (width, height) area
+    /// whose top-left dot sits at `origin` in `section`. Joined together they equal
+    /// `encode_bitmatrix` for an area at the origin.
+    pub fn tiles(&self, section: (i32, i32), origin: (i64, i64), size: (usize, usize), tile: (usize, usize)) -> Result<PatternTiles<'_>, AnotoError> {
+        if tile.0 == 0 || tile.1 == 0 {
+            return Err(AnotoError::Invalid("Tiles need a non-zero width and height".to_string()));
+        }
+        self.check_span(origin.0, size.0)?;
+        self.check_span(origin.1, size.1)?;
+        let rolls = (section.0 % self.mns_length as i32, section.1 % self.mns_length as i32);
+        Ok(PatternTiles { codec: self, rolls, origin, size, tile, next: (0, 0) })
+    }
+
+    /// Full-width bands of `rows` rows, the shape the row-oriented writers stream.
+    pub fn row_bands(&self, section: (i32, i32), origin: (i64, i64), size: (usize, usize), rows: usize) -> Result<PatternTiles<'_>, AnotoError> {
+        self.tiles(section, origin, size, (size.0.max(1), rows))
+    }
+}
+
+impl Iterator for PatternTiles<'_> {
+    type Item = PatternTile;
+
+    fn next(&mut self) -> Option<PatternTile> {
+        let (row, col) = self.next;
+        if row >= self.size.1 || self.size.0 == 0 {
+            return None;
+        }
+        let w = self.tile.0.min(self.size.0 - col);
+        let h = self.tile.1.min(self.size.1 - row);
+        self.next = if col + w >= self.size.0 { (row + h, 0) } else { (row, col + w) };
+
+        let pos = (self.origin.0 + col as i64, self.origin.1 + row as i64);
+        let bits = self.codec.encode_patch(pos, (w, h), self.rolls)
+            .expect("tiles() checked the whole area with check_span, so every tile is in range");
+        Some(PatternTile { row, col, bits })
+    }
+}
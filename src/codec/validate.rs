@@ -0,0 +1,269 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::CodecDefinition;
+
+/// Sequence property a codec definition has to satisfy to decode unambiguously.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum CodecProperty {
+    /// Lengths and counts `AnotoCodec::new` relies on
+    Shape,
+    /// MNS only holds 0 and 1
+    MnsAlphabet,
+    /// Every cyclic MNS window of `mns_order` bits occurs once
+    MnsWindows,
+    /// SNS `i` only holds digits below `pfactors[i]`
+    SnsAlphabet,
+    /// Every cyclic SNS window of `mns_order - 1` digits occurs once
+    SnsWindows,
+    /// SNS lengths are pairwise coprime, as `CRT::solve` needs
+    SnsCoprime,
+    /// The product of the SNS lengths (the position period) fits in i64
+    Period,
+    /// `delta_range` covers exactly the numbers the prime factors can express, below the MNS length
+    DeltaRange,
+}
+
+/// A window that breaks a property, with every cyclic position it starts at.
+#[derive(Clone, Debug, Serialize)]
+pub struct OffendingWindow {
+    pub sequence: String,
+    pub positions: Vec<usize>,
+    pub window: Vec<i8>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CodecViolation {
+    pub property: CodecProperty,
+    pub message: String,
+    pub windows: Vec<OffendingWindow>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CodecReport {
+    pub name: String,
+    pub violations: Vec<CodecViolation>,
+}
+
+// Windows listed per violation when printing; the report itself keeps all of them
+const PRINTED_WINDOWS: usize = 8;
+
+impl CodecReport {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    fn push(&mut self, property: CodecProperty, message: String, windows: Vec<OffendingWindow>) {
+        self.violations.push(CodecViolation { property, message, windows });
+    }
+}
+
+impl fmt::Display for CodecReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_valid() {
+            return write!(f, "Codec '{}' is valid", self.name);
+        }
+        write!(f, "Codec '{}' has {} violation(s):", self.name, self.violations.len())?;
+        for v in &self.violations {
+            write!(f, "\n  {:?}: {}", v.property, v.message)?;
+            for w in v.windows.iter().take(PRINTED_WINDOWS) {
+                let digits: String = w.window.iter().map(|d| d.to_string()).collect();
+                write!(f, "\n    {} window {} at positions {:?}", w.sequence, digits, w.positions)?;
+            }
+            if v.windows.len() > PRINTED_WINDOWS {
+                write!(f, "\n    ... and {} more", v.windows.len() - PRINTED_WINDOWS)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CodecDefinition {
+    /// Checks every sequence property the codec depends on and reports all violations found.
+    pub fn validate(&self) -> CodecReport {
+        let mut report = CodecReport { name: self.name.clone(), violations: Vec::new() };
+        if let Err(e) = self.check_shape() {
+            // The remaining checks index by order and factor, so stop here
+            report.push(CodecProperty::Shape, e.to_string(), Vec::new());
+            return report;
+        }
+
+        let bad_bits = positions_where(&self.mns, |b| b != 0 && b != 1);
+        if !bad_bits.is_empty() {
+            report.push(
+                CodecProperty::MnsAlphabet,
+                format!("MNS holds values other than 0 and 1 at positions {:?}", bad_bits),
+                Vec::new(),
+            );
+        }
+        let repeated = repeated_windows("MNS", &self.mns, self.mns_order);
+        if !repeated.is_empty() {
+            report.push(
+                CodecProperty::MnsWindows,
+                format!("{} MNS window(s) of {} bits occur more than once", repeated.len(), self.mns_order),
+                repeated,
+            );
+        }
+
+        let sns_order = self.mns_order - 1;
+        for (i, (sns, &factor)) in self.sns.iter().zip(&self.pfactors).enumerate() {
+            let label = format!("SNS {}", i);
+            let bad_digits = positions_where(sns, |d| d < 0 || d as i32 >= factor);
+            if !bad_digits.is_empty() {
+                report.push(
+                    CodecProperty::SnsAlphabet,
+                    format!("{} holds digits outside 0..{} at positions {:?}", label, factor, bad_digits),
+                    Vec::new(),
+                );
+            }
+            let repeated = repeated_windows(&label, sns, sns_order);
+            if !repeated.is_empty() {
+                report.push(
+                    CodecProperty::SnsWindows,
+                    format!("{} window(s) of {} digits occur more than once in {}", repeated.len(), sns_order, label),
+                    repeated,
+                );
+            }
+        }
+
+        for i in 0..self.sns.len() {
+            for j in i + 1..self.sns.len() {
+                let (a, b) = (self.sns[i].len(), self.sns[j].len());
+                let g = gcd(a, b);
+                if g != 1 {
+                    report.push(
+                        CodecProperty::SnsCoprime,
+                        format!("SNS {} (length {}) and SNS {} (length {}) share the factor {}", i, a, j, b, g),
+                        Vec::new(),
+                    );
+                }
+            }
+        }
+
+        let period = self.sns.iter().try_fold(1i64, |acc, s| acc.checked_mul(s.len() as i64));
+        if period.is_none() {
+            let lengths: Vec<usize> = self.sns.iter().map(|s| s.len()).collect();
+            report.push(
+                CodecProperty::Period,
+                format!("SNS lengths {:?} multiply to more than the 64-bit position range", lengths),
+                Vec::new(),
+            );
+        }
+
+        let basis: i64 = self.pfactors.iter().map(|&p| p as i64).product();
+        let (lo, hi) = self.delta_range;
+        let span = hi as i64 - lo as i64 + 1;
+        if span != basis {
+            report.push(
+                CodecProperty::DeltaRange,
+                format!(
+                    "delta_range {:?} holds {} values but the prime factors {:?} express {}",
+                    self.delta_range, span, self.pfactors, basis
+                ),
+                Vec::new(),
+            );
+        }
+        if lo < 1 || hi as i64 >= self.mns.len() as i64 {
+            report.push(
+                CodecProperty::DeltaRange,
+                format!("delta_range {:?} must lie within 1..{} (MNS length - 1)", self.delta_range, self.mns.len() - 1),
+                Vec::new(),
+            );
+        }
+
+        report
+    }
+}
+
+fn positions_where(seq: &[i8], bad: impl Fn(i8) -> bool) -> Vec<usize> {
+    seq.iter().enumerate().filter(|&(_, &v)| bad(v)).map(|(i, _)| i).collect()
+}
+
+// Windows of `order` values read cyclically that start at more than one position, in order of first occurrence
+fn repeated_windows(label: &str, seq: &[i8], order: usize) -> Vec<OffendingWindow> {
+    let n = seq.len();
+    let mut seen: HashMap<Vec<i8>, Vec<usize>> = HashMap::new();
+    let mut order_seen = Vec::new();
+    for start in 0..n {
+        let window: Vec<i8> = (0..order).map(|k| seq[(start + k) % n]).collect();
+        let positions = seen.entry(window.clone()).or_default();
+        if positions.is_empty() {
+            order_seen.push(window);
+        }
+        positions.push(start);
+    }
+    order_seen
+        .into_iter()
+        .filter_map(|window| {
+            let positions = seen.remove(&window)?;
+            (positions.len() > 1).then(|| OffendingWindow { sequence: label.to_string(), positions, window })
+        })
+        .collect()
+}
+
+pub(super) fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::anoto_6x6_a4_fixed_definition;
+
+    // Properties the report flags, each once
+    fn failing(definition: &CodecDefinition) -> Vec<CodecProperty> {
+        let mut properties: Vec<CodecProperty> = definition.validate().violations.iter().map(|v| v.property).collect();
+        properties.dedup();
+        properties
+    }
+
+    #[test]
+    fn builtin_definition_is_valid() {
+        let report = anoto_6x6_a4_fixed_definition().validate();
+        assert!(report.is_valid(), "{}", report);
+    }
+
+    #[test]
+    fn flags_a_repeated_mns_window() {
+        let mut definition = anoto_6x6_a4_fixed_definition();
+        definition.mns[10] ^= 1;
+        assert_eq!(failing(&definition), [CodecProperty::MnsWindows]);
+        let report = definition.validate();
+        assert!(report.violations[0].windows.iter().all(|w| w.sequence == "MNS" && w.positions.len() > 1));
+    }
+
+    #[test]
+    fn flags_sns_lengths_with_a_common_factor() {
+        let mut definition = anoto_6x6_a4_fixed_definition();
+        // SNS 0 and 1 share the prime factor 3, so a copy keeps its own windows unique
+        definition.sns[1] = definition.sns[0].clone();
+        assert_eq!(failing(&definition), [CodecProperty::SnsCoprime]);
+    }
+
+    #[test]
+    fn flags_a_delta_range_that_differs_from_the_factors() {
+        let mut definition = anoto_6x6_a4_fixed_definition();
+        definition.delta_range.1 -= 1;
+        assert_eq!(failing(&definition), [CodecProperty::DeltaRange]);
+    }
+
+    #[test]
+    fn flags_digits_outside_the_alphabet() {
+        let mut definition = anoto_6x6_a4_fixed_definition();
+        definition.mns[0] = 2;
+        assert_eq!(failing(&definition), [CodecProperty::MnsAlphabet]);
+
+        let mut definition = anoto_6x6_a4_fixed_definition();
+        definition.sns[2][0] = definition.pfactors[2] as i8;
+        assert_eq!(failing(&definition), [CodecProperty::SnsAlphabet]);
+    }
+
+    #[test]
+    fn stops_at_a_broken_shape() {
+        let mut definition = anoto_6x6_a4_fixed_definition();
+        definition.pfactors.pop();
+        definition.mns[0] = 2;
+        assert_eq!(failing(&definition), [CodecProperty::Shape]);
+    }
+}
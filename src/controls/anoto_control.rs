@@ -13,7 +13,7 @@ pub enum Direction {
     Right,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct State {
     pub show_up: bool,
     pub show_down: bool,
@@ -21,17 +21,7 @@ pub struct State {
     pub show_right: bool,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            show_up: false,
-            show_down: false,
-            show_left: false,
-            show_right: false,
-        }
-    }
-}
-
+#[allow(clippy::too_many_arguments)]
 pub fn anoto_control<'a, Message>(
     state: &'a State,
     dot_size: f32,
@@ -2,19 +2,11 @@ use iced::widget::{checkbox, column, row, slider, text, vertical_space, containe
 use iced::{Element, Length, Alignment, Border, Color};
 use iced_aw::number_input::NumberInput;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct State {
     pub autodetect: bool,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            autodetect: false,
-        }
-    }
-}
-
 pub fn page_layout_control<'a, Message>(
     state: &'a State,
     width: usize,
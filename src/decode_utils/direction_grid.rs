@@ -0,0 +1,358 @@
+use ndarray::{Array3, s};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::codec::AnotoCodec;
+use crate::error::AnotoError;
+
+/// Bit pair (x, y) of a direction written as an arrow, a word or its initial.
+pub fn direction_bits(name: &str) -> Option<(i8, i8)> {
+    match name.trim().to_lowercase().as_str() {
+        "↑" | "up" | "u" => Some((0, 0)),
+        "←" | "left" | "l" => Some((1, 0)),
+        "→" | "right" | "r" => Some((0, 1)),
+        "↓" | "down" | "d" => Some((1, 1)),
+        _ => None,
+    }
+}
+
+/// Arrow of a bit pair (x, y), "?" for anything but 0 and 1.
+pub fn direction_arrow(bits: (i8, i8)) -> &'static str {
+    match bits {
+        (0, 0) => "↑",
+        (1, 0) => "←",
+        (0, 1) => "→",
+        (1, 1) => "↓",
+        _ => "?",
+    }
+}
+
+/// Captured dot directions, as a pen or a user types them in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirectionGrid {
+    /// (height, width, 2) bits, as `AnotoCodec::decode_position` reads them
+    pub bits: Array3<i8>,
+}
+
+impl DirectionGrid {
+    /// Reads a grid in any of these forms:
+    /// - JSON rows of cells, where a cell is an arrow, a word ("up", "Left", ...), a bit pair [x, y] or ["↑"];
+    /// - JSON rows of compact strings such as "↓←←→←→";
+    /// - the same JSON wrapped in extra arrays or encoded again as a JSON string;
+    /// - text with one row per line, as CSV or space separated cells ("↓,←" or "up left" or "10 01"),
+    ///   bracketed pairs ("[1 0] [0 1]") or compact arrows ("↓←←→←→").
+    pub fn parse(input: &str) -> Result<Self, AnotoError> {
+        let input = input.trim();
+        // cmd.exe passes the single quotes of a shell command line through
+        let input = input.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')).unwrap_or(input);
+        match serde_json::from_str::<Value>(input) {
+            Ok(value) => Self::from_json(&value),
+            Err(json_error) => parse_text(input).and_then(Self::from_rows).map_err(|text_error| {
+                // Input that only JSON could have meant is reported against JSON
+                let nested = input.strip_prefix('[').is_some_and(|rest| rest.trim_start().starts_with(['[', '"', '{']));
+                if nested || input.starts_with(['{', '"']) { json_error.into() } else { text_error }
+            }),
+        }
+    }
+
+    /// Grid of an already parsed JSON value, in the JSON forms `parse` accepts.
+    pub fn from_json(value: &Value) -> Result<Self, AnotoError> {
+        if let Value::String(text) = value {
+            return Self::parse(text);
+        }
+        let rows = find_rows(value)?
+            .iter()
+            .enumerate()
+            .map(|(r, row)| match row {
+                Value::String(text) => parse_text_row(text)
+                    .map_err(|(column, message)| AnotoError::parse(format!("{} in row {}, column {}", message, r, column), 0, 0)),
+                Value::Array(cells) => cells
+                    .iter()
+                    .enumerate()
+                    .map(|(c, cell)| json_cell(cell).ok_or_else(|| AnotoError::parse(format!("Invalid cell {} at [{}, {}]", cell, r, c), 0, 0)))
+                    .collect(),
+                other => Err(AnotoError::shape(format!("row {} to be an array or a string", r), other.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_rows(rows)
+    }
+
+    /// Grid of equally long rows of (x, y) bit pairs.
+    pub fn from_rows(rows: Vec<Vec<(i8, i8)>>) -> Result<Self, AnotoError> {
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 {
+            return Err(AnotoError::shape("at least one cell", "an empty grid"));
+        }
+        if let Some(r) = rows.iter().position(|row| row.len() != width) {
+            return Err(AnotoError::shape(format!("rows of {} cells", width), format!("{} cells in row {}", rows[r].len(), r)));
+        }
+        let height = rows.len();
+        let data = rows.into_iter().flatten().flat_map(|(x, y)| [x, y]).collect();
+        Ok(DirectionGrid { bits: Array3::from_shape_vec((height, width, 2), data).expect("two bits per cell") })
+    }
+
+    pub fn height(&self) -> usize {
+        self.bits.dim().0
+    }
+
+    pub fn width(&self) -> usize {
+        self.bits.dim().1
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> (i8, i8) {
+        (self.bits[[row, col, 0]], self.bits[[row, col, 1]])
+    }
+
+    /// Errors unless at least one `order` x `order` window fits.
+    pub fn require_window(&self, order: usize) -> Result<(), AnotoError> {
+        if self.height() < order || self.width() < order {
+            return Err(AnotoError::shape(format!("at least a {0}x{0} grid", order), format!("{}x{}", self.width(), self.height())));
+        }
+        Ok(())
+    }
+
+    /// `order` x `order` window with its top-left cell at (`row`, `col`).
+    pub fn window(&self, row: usize, col: usize, order: usize) -> Array3<i8> {
+        self.bits.slice(s![row..row + order, col..col + order, ..]).to_owned()
+    }
+
+    /// Decodes every window of the codec's order. Only a grid smaller than one window is an
+    /// error; windows that fail are listed in the report.
+    pub fn decode(&self, codec: &AnotoCodec) -> Result<DecodeReport, AnotoError> {
+        let order = codec.mns_order();
+        self.require_window(order)?;
+        let (height, width) = (self.height(), self.width());
+        let mut report = DecodeReport { height, width, decoded: Vec::new(), errors: Vec::new() };
+
+        for r in 0..=(height - order) {
+            for c in 0..=(width - order) {
+                let bits = self.window(r, c, order);
+                match codec.decode_position(&bits) {
+                    Ok(position) => {
+                        let section = codec.decode_section(&bits, position).ok();
+                        report.decoded.push(DecodedWindow { row: r, col: c, position, section });
+                    }
+                    Err(e) => report.errors.push(WindowError { row: r, col: c, error: e.to_string(), kind: e.kind() }),
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DecodedWindow {
+    pub row: usize,
+    pub col: usize,
+    pub position: (i64, i64),
+    pub section: Option<(i32, i32)>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WindowError {
+    pub row: usize,
+    pub col: usize,
+    pub error: String,
+    /// `AnotoError::kind` of the failure
+    pub kind: &'static str,
+}
+
+/// Outcome of every window of a `DirectionGrid`.
+#[derive(Clone, Debug, Serialize)]
+pub struct DecodeReport {
+    pub height: usize,
+    pub width: usize,
+    pub decoded: Vec<DecodedWindow>,
+    pub errors: Vec<WindowError>,
+}
+
+impl DecodeReport {
+    /// Position (x, y) of the grid's top-left cell, from the first window that decoded.
+    pub fn origin(&self) -> Option<(i64, i64)> {
+        self.decoded.first().map(|d| (d.position.0 - d.col as i64, d.position.1 - d.row as i64))
+    }
+}
+
+// Rows of the grid inside any number of wrapping arrays
+fn find_rows(value: &Value) -> Result<&Vec<Value>, AnotoError> {
+    let mut value = value;
+    loop {
+        let rows = value.as_array().ok_or_else(|| AnotoError::shape("an array of rows", value.to_string()))?;
+        match rows.first() {
+            None => return Err(AnotoError::shape("at least one row", "[]")),
+            // A row holding arrays of arrays is one more level of wrapping
+            Some(first @ Value::Array(cells)) if cells.first().is_some_and(|cell| cell.is_array() && !is_cell(cell)) => value = first,
+            Some(_) => return Ok(rows),
+        }
+    }
+}
+
+// A string or an array `json_cell` reads: "↑", [0, 1], ["↑"]
+fn is_cell(value: &Value) -> bool {
+    match value {
+        Value::String(_) => true,
+        Value::Array(_) => json_cell(value).is_some(),
+        _ => false,
+    }
+}
+
+fn json_cell(cell: &Value) -> Option<(i8, i8)> {
+    match cell {
+        Value::String(text) => text_cell(text),
+        Value::Array(parts) => match parts.as_slice() {
+            [x, y] if x.is_number() => bit_pair(x.as_i64()?, y.as_i64()?),
+            [Value::String(text)] | [Value::String(text), _] => direction_bits(text),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn bit_pair(x: i64, y: i64) -> Option<(i8, i8)> {
+    matches!((x, y), (0 | 1, 0 | 1)).then_some((x as i8, y as i8))
+}
+
+// One cell written as a direction or as two bits: "↑", "up", "10", "1 0", "1,0"
+fn text_cell(text: &str) -> Option<(i8, i8)> {
+    let text = text.trim().trim_matches('"');
+    direction_bits(text).or_else(|| {
+        let digits = text.chars().filter(|c| !c.is_whitespace() && *c != ',').collect::<Vec<_>>();
+        match digits[..] {
+            [x, y] => bit_pair(x.to_digit(10)? as i64, y.to_digit(10)? as i64),
+            _ => None,
+        }
+    })
+}
+
+// Rows of a text grid, one per non-blank line
+fn parse_text(input: &str) -> Result<Vec<Vec<(i8, i8)>>, AnotoError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| parse_text_row(line).map_err(|(column, message)| AnotoError::parse(message, index + 1, column)))
+        .collect()
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == ';'
+}
+
+// Cells of one text row. Errors carry the 1-based column of the offending cell.
+fn parse_text_row(line: &str) -> Result<Vec<(i8, i8)>, (usize, String)> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let mut cells = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        if is_separator(chars[i]) {
+            i += 1;
+            continue;
+        }
+        if let Some(close) = match chars[i] { '[' => Some(']'), '(' => Some(')'), _ => None } {
+            // Bracketed pair "[1 0]"; its comma is not a cell separator
+            let end = chars[i..].iter().position(|&c| c == close).map(|n| i + n).ok_or((start + 1, format!("Unclosed '{}'", chars[i])))?;
+            let inner = chars[i + 1..end].iter().collect::<String>();
+            cells.push(text_cell(&inner).ok_or((start + 1, format!("Invalid pair '{}'", inner)))?);
+            i = end + 1;
+            continue;
+        }
+        while i < chars.len() && !is_separator(chars[i]) && chars[i] != '[' && chars[i] != '(' {
+            i += 1;
+        }
+        let token = chars[start..i].iter().collect::<String>();
+        if let Some(bits) = text_cell(&token) {
+            cells.push(bits);
+            continue;
+        }
+        // Compact form, one arrow or initial per cell: "↓←←→←→"
+        for (offset, c) in token.trim_matches('"').chars().enumerate() {
+            let bits = direction_bits(&c.to_string()).ok_or((start + offset + 1, format!("Invalid cell '{}'", token)))?;
+            cells.push(bits);
+        }
+    }
+    Ok(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::anoto_6x6_a4_fixed;
+
+    const ROWS: [[(i8, i8); 3]; 2] = [[(1, 1), (1, 0), (0, 1)], [(0, 0), (0, 1), (1, 1)]];
+
+    fn expected() -> DirectionGrid {
+        DirectionGrid::from_rows(ROWS.iter().map(|row| row.to_vec()).collect()).unwrap()
+    }
+
+    #[test]
+    fn reads_json_forms() {
+        for input in [
+            r#"[["↓","←","→"],["↑","→","↓"]]"#,
+            r#"[["down","Left","r"],["UP","right","d"]]"#,
+            "[[[1,1],[1,0],[0,1]],[[0,0],[0,1],[1,1]]]",
+            r#"[[["↓"],["←"],["→"]],[["↑"],["→"],["↓"]]]"#,
+            r#"["↓←→","↑→↓"]"#,
+            r#"[[["↓","←","→"],["↑","→","↓"]]]"#,
+            r#""[[\"↓\",\"←\",\"→\"],[\"↑\",\"→\",\"↓\"]]""#,
+            r#"'[["↓","←","→"],["↑","→","↓"]]'"#,
+        ] {
+            assert_eq!(DirectionGrid::parse(input).unwrap(), expected(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn reads_text_forms() {
+        for input in [
+            "↓,←,→\n↑,→,↓",
+            "down left right\nup right down\n",
+            "11 10 01\n00 01 11",
+            "[1 1] [1 0] [0 1]\n[0,0] [0,1] [1,1]",
+            "↓←→\n\n↑→↓",
+            "dlr\nurd",
+        ] {
+            assert_eq!(DirectionGrid::parse(input).unwrap(), expected(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        for input in ["↓←→\n↑→", r#"[["↓","←","→"],["↑","→"]]"#] {
+            assert!(matches!(DirectionGrid::parse(input), Err(AnotoError::ShapeMismatch { .. })), "{:?}", input);
+        }
+        assert!(matches!(DirectionGrid::parse("[]"), Err(AnotoError::ShapeMismatch { .. })));
+    }
+
+    #[test]
+    fn reports_unknown_symbols_with_their_place() {
+        match DirectionGrid::parse("↓←→\n↑x↓") {
+            Err(AnotoError::Parse { line, column, .. }) => assert_eq!((line, column), (2, 2)),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(DirectionGrid::parse(r#"[["↓","?"]]"#), Err(AnotoError::Parse { .. })));
+        assert!(matches!(DirectionGrid::parse("[[2,0]]"), Err(AnotoError::Parse { .. })));
+        // Broken JSON is reported against JSON, not as a text grid
+        assert!(matches!(DirectionGrid::parse("[[1,0],[0,1"), Err(AnotoError::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn origin_comes_from_the_first_window_that_decodes() {
+        let codec = anoto_6x6_a4_fixed();
+        let bits = codec.encode_patch((100, 200), (8, 7), (10, 2)).unwrap();
+        let mut grid = DirectionGrid { bits };
+        // Six 1s in a row are no window of the MNS, which breaks both windows of the first column
+        grid.bits.slice_mut(s![.., 0, 0]).fill(1);
+        let report = grid.decode(&codec).unwrap();
+        assert_eq!(report.decoded.len() + report.errors.len(), 3 * 2);
+        assert_eq!(report.errors.iter().map(|e| (e.row, e.col, e.kind)).collect::<Vec<_>>(), [(0, 0, "mns_miss"), (1, 0, "mns_miss")]);
+        assert_eq!((report.decoded[0].row, report.decoded[0].col), (0, 1));
+        assert_eq!(report.origin(), Some((100, 200)));
+        assert!(report.decoded.iter().all(|d| d.section == Some((10, 2))));
+    }
+
+    #[test]
+    fn needs_one_window() {
+        let codec = anoto_6x6_a4_fixed();
+        assert!(matches!(expected().decode(&codec), Err(AnotoError::ShapeMismatch { .. })));
+    }
+}
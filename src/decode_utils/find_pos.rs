@@ -1,4 +1,5 @@
-use ndarray::s;                                                     
+use ndarray::s;
+use crate::error::AnotoError;
 //   POS: (np.int64(7), np.int64(3))                                                                                            
 //   SEC: (np.int64(10), np.int64(2))                                                                                           
 //   [1 0] [1 0] [0 0] [1 0] [0 1] [0 0] [1 0] [1 1] [1 1] [1 1] [0 1] [0 1] [1 0] [1 1] [1 0] [1 0] [0 1] [1 0] [1 0] [0 0]  0 
@@ -46,10 +47,15 @@ use ndarray::s;
  * and section_{row}_{col}.txt
  */                                                                                                                           
 pub fn get_6x6_section(bitmatrix: &ndarray::Array3<i8>, pos: (i64, i64)) -> ndarray::Array3<i8> {
+    get_section(bitmatrix, pos, 6)
+}
+
+/// `order` x `order` window of the bitmatrix with its top-left at `pos`.
+pub fn get_section(bitmatrix: &ndarray::Array3<i8>, pos: (i64, i64), order: usize) -> ndarray::Array3<i8> {
     let (start_row, start_col) = (pos.0 as usize, pos.1 as usize);
     let section = bitmatrix.slice(s![
-        start_row..start_row + 6,
-        start_col..start_col + 6,
+        start_row..start_row + order,
+        start_col..start_col + order,
         ..
     ]);
     section.to_owned()
@@ -79,10 +85,15 @@ pub fn get_6x6_section(bitmatrix: &ndarray::Array3<i8>, pos: (i64, i64)) -> ndar
  * either from json or generated
  */
 pub fn find_position(bitmatrix: &ndarray::Array3<i8>, section: &ndarray::Array3<i8>) -> Option<(i64, i64)> {
+    // The window order comes from the section itself
+    let order = section.dim().0;
     let (rows, cols) = (bitmatrix.dim().0, bitmatrix.dim().1);
-    for r in 0..=rows - 6 {
-        for c in 0..=cols - 6 {
-            let candidate = get_6x6_section(bitmatrix, (r as i64, c as i64));
+    if section.dim() != (order, order, 2) || rows < order || cols < order {
+        return None;
+    }
+    for r in 0..=rows - order {
+        for c in 0..=cols - order {
+            let candidate = get_section(bitmatrix, (r as i64, c as i64), order);
             if candidate == *section {
                 return Some((r as i64, c as i64));
             }
@@ -104,18 +115,15 @@ pub fn find_position(bitmatrix: &ndarray::Array3<i8>, section: &ndarray::Array3<
  *     rot = codec.decode_rotation(R)
  * ```
  * 
- * If position cannot be decoded, return None
- * If position is decoded, return Some((row, col))
+ * If position cannot be decoded, return the AnotoError saying why
+ * If position is decoded, return Ok((row, col))
  *  
  * Example usage:
  *      anoto.exe --decode file_6x6.json | -decode file_6x6.txt
  *      anoto.exe -d file_6x6.json | -d file_6x6.txt
  *      POS (7, 3)
  */
-pub fn decode_position(section: &ndarray::Array3<i8>) -> Option<(i64, i64)> {
+pub fn decode_position(section: &ndarray::Array3<i8>) -> Result<(i64, i64), AnotoError> {
     let codec = crate::codec::anoto_6x6_a4_fixed();
-    match codec.decode_position(section) {
-        Ok((x, y)) => Some((x as i64, y as i64)),
-        Err(_) => None,
-    }
+    codec.decode_position(section)
 }
\ No newline at end of file
@@ -0,0 +1,743 @@
+use ndarray::{Array2, Array3, s};
+use serde::Serialize;
+
+use crate::codec::AnotoCodec;
+use crate::error::AnotoError;
+
+/// Where a dot sits relative to its grid node, as drawn by `gen_pdf`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DotDirection {
+    Up,
+    Left,
+    Right,
+    Down,
+}
+
+impl DotDirection {
+    /// The `[x_bit, y_bit]` pair `gen_pdf` turns into this direction.
+    pub fn bits(self) -> (i8, i8) {
+        match self {
+            DotDirection::Up => (0, 0),
+            DotDirection::Left => (1, 0),
+            DotDirection::Right => (0, 1),
+            DotDirection::Down => (1, 1),
+        }
+    }
+
+    fn displacement(self) -> (f64, f64) {
+        match self {
+            DotDirection::Up => (0.0, 1.0),
+            DotDirection::Left => (-1.0, 0.0),
+            DotDirection::Right => (1.0, 0.0),
+            DotDirection::Down => (0.0, -1.0),
+        }
+    }
+}
+
+/// A centroid that was matched to a grid node.
+#[derive(Clone, Debug)]
+pub struct FittedDot {
+    /// Index into the centroids passed to `fit_grid`
+    pub index: usize,
+    /// Node in grid units, relative to the fitted lattice origin
+    pub node: (i32, i32),
+    pub direction: DotDirection,
+    /// Distance from the ideal dot position, in grid spacings
+    pub residual: f64,
+}
+
+/// Lattice recovered from a cloud of dot centroids.
+#[derive(Clone, Debug)]
+pub struct GridFit {
+    /// Maps lattice coordinates (x right, y up the page, one unit per dot) to image pixels
+    pub homography: [[f64; 3]; 3],
+    /// First estimate of the lattice rotation in the image, radians, before the homography
+    /// was fitted; it can be a degree or so off under tilt, see `rotation_at`
+    pub angle: f64,
+    /// Grid spacing in pixels near the image centre
+    pub spacing: f64,
+    /// Dot offset from its node, in grid spacings
+    pub offset: f64,
+    /// Node of `bits[[0, 0]]`
+    pub origin: (i32, i32),
+    /// Direction bits per node: rows go up the page, columns to the right
+    pub bits: Array3<i8>,
+    /// Nodes that received a dot
+    pub mask: Array2<bool>,
+    pub dots: Vec<FittedDot>,
+    /// Centroids that did not fit the lattice (dirt, noise, doubles)
+    pub rejected: Vec<usize>,
+}
+
+// Windows decoded per orientation to check the fit against itself; windows that fail
+// (misread dots, the period seam) do not count
+const MAX_VOTE_WINDOWS: usize = 9;
+// Dots closest to the centre of the cloud that each round of assign -> fit homography
+// -> reproject trusts; grows so strong perspective is picked up gradually
+const REFINE_COUNTS: [usize; 9] = [9, 14, 20, 28, 40, 56, 80, usize::MAX, usize::MAX];
+// Periodicity peaks that `fit_grid` checks against the dots before settling on a frame
+const FRAME_CANDIDATES: usize = 3;
+
+/// Fits the dot lattice to `centroids` (image pixels, v pointing down) and classifies
+/// every dot into the four `gen_pdf` directions.
+///
+/// The lattice orientation is only known up to quarter turns; `quarter_turns` rotates
+/// the estimated axes by that many 90 degree steps. `decode_centroids` tries all four.
+pub fn fit_grid(centroids: &[(f64, f64)], quarter_turns: u8) -> Result<GridFit, AnotoError> {
+    if centroids.len() < 9 {
+        return Err(format!("Need at least 9 dots to fit a grid, got {}", centroids.len()).into());
+    }
+
+    // Image coordinates with y up, so lattice and image share handedness
+    let points: Vec<(f64, f64)> = centroids.iter().map(|&(u, v)| (u, -v)).collect();
+    // Centre the node numbering on the middle of the cloud's bounding box; the mean would
+    // drift towards the far side of a tilted capture, where the dots crowd together
+    let (min, max) = points.iter().fold(
+        ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)),
+        |(min, max), p| ((min.0.min(p.0), min.1.min(p.1)), (max.0.max(p.0), max.1.max(p.1))),
+    );
+    let centre = ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0);
+
+    let mut by_distance: Vec<usize> = (0..points.len()).collect();
+    by_distance.sort_by(|&i, &j| {
+        let d = |k: usize| (points[k].0 - centre.0).powi(2) + (points[k].1 - centre.1).powi(2);
+        d(i).total_cmp(&d(j))
+    });
+    let guess = nearest_spacing(&points).ok_or("Could not estimate the grid spacing")?;
+    let central = &by_distance[..REFINE_COUNTS[1].min(points.len())];
+
+    let mut rank = vec![0; points.len()];
+    for (r, &i) in by_distance.iter().enumerate() {
+        rank[i] = r;
+    }
+
+    // Initial similarity transform: rotate, scale, then shift by the lattice phase. Under
+    // tilt a false periodicity peak can beat the true one, so every candidate frame is
+    // tried and the one whose phase puts the most central dots on dot positions wins.
+    let (angle, spacing, rotated, phase, start_offset) = lattice_frames(&points, centre, guess).into_iter()
+        .map(|(angle, spacing)| {
+            let angle = angle + quarter_turns as f64 * std::f64::consts::FRAC_PI_2;
+            let (cos, sin) = (angle.cos(), angle.sin());
+            let rotated: Vec<(f64, f64)> = points.iter()
+                .map(|&(x, y)| (
+                    ((x - centre.0) * cos + (y - centre.1) * sin) / spacing,
+                    (-(x - centre.0) * sin + (y - centre.1) * cos) / spacing,
+                ))
+                .collect();
+            let (phase, offset, score) = lattice_phase(&rotated, central);
+            (score, (angle, spacing, rotated, phase, offset))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, frame)| frame)
+        .ok_or("Could not estimate the grid orientation")?;
+    let (cos, sin) = (angle.cos(), angle.sin());
+    let mut lattice: Vec<(f64, f64)> = rotated.iter().map(|&(a, b)| (a - phase.0, b - phase.1)).collect();
+
+    let mut homography = None;
+    let mut offset = start_offset;
+    let mut assigned = assign(&lattice, offset);
+
+    for count in REFINE_COUNTS {
+        let correspondences: Vec<Correspondence> = assigned.iter()
+            .filter(|d| rank[d.index] < count)
+            .map(|d| {
+                let (dx, dy) = d.direction.displacement();
+                let ideal = (d.node.0 as f64 + dx * offset, d.node.1 as f64 + dy * offset);
+                let p = points[d.index];
+                (ideal, ((p.0 - centre.0) / spacing, (p.1 - centre.1) / spacing))
+            })
+            .collect();
+        let Some(h) = fit_homography(&correspondences) else { break };
+        let Some(h_inv) = invert(&h) else { break };
+        homography = Some(h);
+
+        lattice = points.iter()
+            .map(|&(x, y)| apply(&h_inv, ((x - centre.0) / spacing, (y - centre.1) / spacing)))
+            .collect();
+        offset = estimate_offset(&lattice);
+        assigned = assign(&lattice, offset);
+    }
+    if assigned.is_empty() {
+        return Err("No dot matched the fitted grid".into());
+    }
+
+    // Keep the best dot per node, everything else is rejected
+    assigned.sort_by(|a, b| a.node.cmp(&b.node).then(a.residual.total_cmp(&b.residual)));
+    let mut dots: Vec<FittedDot> = Vec::with_capacity(assigned.len());
+    let mut rejected: Vec<usize> = (0..centroids.len())
+        .filter(|i| !assigned.iter().any(|d| d.index == *i))
+        .collect();
+    for d in assigned {
+        if dots.last().is_some_and(|last| last.node == d.node) {
+            rejected.push(d.index);
+        } else {
+            dots.push(d);
+        }
+    }
+    rejected.sort_unstable();
+
+    let min_x = dots.iter().map(|d| d.node.0).min().unwrap_or(0);
+    let max_x = dots.iter().map(|d| d.node.0).max().unwrap_or(0);
+    let min_y = dots.iter().map(|d| d.node.1).min().unwrap_or(0);
+    let max_y = dots.iter().map(|d| d.node.1).max().unwrap_or(0);
+    let height = (max_y - min_y + 1) as usize;
+    let width = (max_x - min_x + 1) as usize;
+    let mut bits = Array3::<i8>::zeros((height, width, 2));
+    let mut mask = Array2::<bool>::from_elem((height, width), false);
+    for d in &dots {
+        let (r, c) = ((d.node.1 - min_y) as usize, (d.node.0 - min_x) as usize);
+        let (b0, b1) = d.direction.bits();
+        bits[[r, c, 0]] = b0;
+        bits[[r, c, 1]] = b1;
+        mask[[r, c]] = true;
+    }
+
+    // Express the homography in image pixels with v pointing down again
+    let to_image = [
+        [spacing, 0.0, centre.0],
+        [0.0, -spacing, -centre.1],
+        [0.0, 0.0, 1.0],
+    ];
+    let homography = match homography {
+        Some(h) => multiply(&to_image, &h),
+        // No refinement happened, fall back to the similarity estimate
+        None => multiply(&to_image, &[
+            [cos, -sin, phase.0 * cos - phase.1 * sin],
+            [sin, cos, phase.0 * sin + phase.1 * cos],
+            [0.0, 0.0, 1.0],
+        ]),
+    };
+
+    Ok(GridFit {
+        homography,
+        angle,
+        spacing,
+        offset,
+        origin: (min_x, min_y),
+        bits,
+        mask,
+        dots,
+        rejected,
+    })
+}
+
+impl GridFit {
+    /// Every `order` x `order` window with all nodes present, as `(row, col)` into `bits`,
+    /// the ones closest to the middle of the fit first.
+    pub fn complete_windows(&self, order: usize) -> Vec<(usize, usize)> {
+        let (height, width) = self.mask.dim();
+        if height < order || width < order {
+            return Vec::new();
+        }
+        let mid = ((height - order) as f64 / 2.0, (width - order) as f64 / 2.0);
+        let mut windows: Vec<((usize, usize), f64)> = Vec::new();
+        for r in 0..=(height - order) {
+            for c in 0..=(width - order) {
+                if self.mask.slice(s![r..r + order, c..c + order]).iter().all(|&m| m) {
+                    let dist = (r as f64 - mid.0).powi(2) + (c as f64 - mid.1).powi(2);
+                    windows.push(((r, c), dist));
+                }
+            }
+        }
+        windows.sort_by(|a, b| a.1.total_cmp(&b.1));
+        windows.into_iter().map(|(rc, _)| rc).collect()
+    }
+
+    /// Image pixel of a lattice point given in `bits` coordinates (col, row), fractions allowed.
+    pub fn to_image(&self, col: f64, row: f64) -> (f64, f64) {
+        apply(&self.homography, (col + self.origin.0 as f64, row + self.origin.1 as f64))
+    }
+
+    /// Lattice point in `bits` coordinates (col, row) seen at an image pixel.
+    pub fn from_image(&self, u: f64, v: f64) -> Option<(f64, f64)> {
+        let inv = invert(&self.homography)?;
+        let (x, y) = apply(&inv, (u, v));
+        Some((x - self.origin.0 as f64, y - self.origin.1 as f64))
+    }
+
+    /// Rotation of the lattice x axis at an image pixel, radians counterclockwise, read
+    /// from the fitted homography.
+    pub fn rotation_at(&self, u: f64, v: f64) -> Option<f64> {
+        let (col, row) = self.from_image(u, v)?;
+        let a = self.to_image(col, row);
+        let b = self.to_image(col + 1e-3, row);
+        // Image v points down
+        Some((a.1 - b.1).atan2(b.0 - a.0).rem_euclid(std::f64::consts::TAU))
+    }
+}
+
+/// A decoded capture: the fit that worked and where its window sits.
+pub struct CentroidDecode {
+    pub fit: GridFit,
+    /// Quarter turns applied to the estimated lattice axes
+    pub quarter_turns: u8,
+    /// `(row, col)` of the decoded window in `fit.bits`
+    pub window: (usize, usize),
+    /// Position of the window's top-left dot, as `AnotoCodec::decode_position` returns it
+    pub position: (i64, i64),
+    /// `AnotoCodec::period` of the decoding codec; positions wrap around it
+    pub period: i64,
+}
+
+/// A point on the pattern at sub-dot precision.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct SubgridPosition {
+    /// Pattern units (one per dot), x along columns and y along rows as in `decode_position`
+    pub grid: (f64, f64),
+    /// The same point in millimetres from the pattern's first dot
+    pub mm: (f64, f64),
+}
+
+impl SubgridPosition {
+    pub fn new(grid: (f64, f64), dot_pitch_mm: f64) -> Self {
+        SubgridPosition {
+            grid,
+            mm: (grid.0 * dot_pitch_mm, grid.1 * dot_pitch_mm),
+        }
+    }
+}
+
+impl CentroidDecode {
+    /// Pattern position of the dot at `fit.bits[[0, 0]]`.
+    pub fn bits_origin(&self) -> (i64, i64) {
+        (
+            (self.position.0 - self.window.1 as i64).rem_euclid(self.period),
+            (self.position.1 - self.window.0 as i64).rem_euclid(self.period),
+        )
+    }
+
+    /// Where an image pixel lies on the pattern, in fractional dot units. Passing the
+    /// image centre gives the nib position rather than the window's top-left dot.
+    pub fn locate(&self, u: f64, v: f64) -> Option<(f64, f64)> {
+        let (col, row) = self.fit.from_image(u, v)?;
+        let origin = self.bits_origin();
+        let period = self.period as f64;
+        Some(((origin.0 as f64 + col).rem_euclid(period), (origin.1 as f64 + row).rem_euclid(period)))
+    }
+
+    /// `locate` in both pattern units and millimetres; `dot_pitch_mm` is the printed grid
+    /// spacing (see `PdfConfig::dot_pitch_mm`).
+    pub fn locate_mm(&self, u: f64, v: f64, dot_pitch_mm: f64) -> Option<SubgridPosition> {
+        self.locate(u, v).map(|grid| SubgridPosition::new(grid, dot_pitch_mm))
+    }
+}
+
+/// Fits the lattice in each of the four orientations and decodes complete windows, centre
+/// first, until `MAX_VOTE_WINDOWS` succeed in each. A wrong orientation can still decode to some position, so
+/// the orientation whose windows agree on the most consistent origin wins.
+pub fn decode_centroids(codec: &AnotoCodec, centroids: &[(f64, f64)]) -> Result<CentroidDecode, AnotoError> {
+    let order = codec.mns_order();
+    let mut last_error = AnotoError::Invalid(format!("No complete {}x{} window among the fitted dots", order, order));
+    let mut best: Option<(usize, CentroidDecode)> = None;
+    let mut unverified = 0;
+
+    for quarter_turns in 0..4 {
+        let fit = match fit_grid(centroids, quarter_turns) {
+            Ok(f) => f,
+            Err(e) => {
+                last_error = e;
+                continue;
+            }
+        };
+        let windows = fit.complete_windows(order);
+
+        let mut decoded: Vec<((usize, usize), (i64, i64))> = Vec::new();
+        for &(r, c) in &windows {
+            if decoded.len() == MAX_VOTE_WINDOWS {
+                break;
+            }
+            let window = fit.bits.slice(s![r..r + order, c..c + order, ..]).to_owned();
+            match codec.decode_position(&window) {
+                Ok(position) => decoded.push(((r, c), position)),
+                Err(e) => last_error = e,
+            }
+        }
+
+        // Origin of the fitted bits implied by a decoded window, wrapped so windows on either side of the seam agree
+        let origin = |&((r, c), (x, y)): &((usize, usize), (i64, i64))| {
+            (codec.wrap_position(x - c as i64), codec.wrap_position(y - r as i64))
+        };
+        // Windows are ordered by distance to the middle, so ties go to the most central one
+        let Some((votes, &(window, position))) = decoded.iter().rev()
+            .map(|d| (decoded.iter().filter(|o| origin(o) == origin(d)).count(), d))
+            .max_by_key(|(votes, _)| *votes)
+        else {
+            continue;
+        };
+        // A single window can only be trusted when the fit offers no second one to check against
+        if votes < 2 && windows.len() > 1 {
+            last_error = "Windows decoded to inconsistent positions".into();
+            continue;
+        }
+        if votes < 2 {
+            unverified += 1;
+        }
+        if best.as_ref().is_none_or(|(v, _)| votes > *v) {
+            best = Some((votes, CentroidDecode { fit, quarter_turns, window, position, period: codec.period() }));
+        }
+    }
+    match best {
+        Some((1, _)) if unverified > 1 => Err("Several orientations decode from a single window".into()),
+        Some((_, d)) => Ok(d),
+        None => Err(last_error),
+    }
+}
+
+// Median distance to the third nearest dot; offsets make the nearest one unreliable
+fn nearest_spacing(points: &[(f64, f64)]) -> Option<f64> {
+    let mut distances: Vec<f64> = points.iter()
+        .filter_map(|p| {
+            let mut d: Vec<f64> = points.iter()
+                .map(|q| ((q.0 - p.0).powi(2) + (q.1 - p.1).powi(2)).sqrt())
+                .filter(|&d| d > 0.0)
+                .collect();
+            d.sort_by(|a, b| a.total_cmp(b));
+            d.get(2).copied()
+        })
+        .collect();
+    if distances.is_empty() {
+        return None;
+    }
+    distances.sort_by(|a, b| a.total_cmp(b));
+    Some(distances[distances.len() / 2])
+}
+
+// Periodicity of the weighted dots along both lattice axes for a candidate angle and
+// spacing. Dot offsets only shrink the peak, they don't move it.
+fn periodicity(points: &[(f64, f64)], weights: &[f64], centre: (f64, f64), angle: f64, spacing: f64) -> ((f64, f64), (f64, f64)) {
+    let tau = std::f64::consts::TAU;
+    let (cos, sin) = (angle.cos(), angle.sin());
+    let (mut a, mut b) = ((0.0, 0.0), (0.0, 0.0));
+    for (p, w) in points.iter().zip(weights) {
+        let (x, y) = (p.0 - centre.0, p.1 - centre.1);
+        let pa = tau * (x * cos + y * sin) / spacing;
+        let pb = tau * (-x * sin + y * cos) / spacing;
+        a = (a.0 + w * pa.cos(), a.1 + w * pa.sin());
+        b = (b.0 + w * pb.cos(), b.1 + w * pb.sin());
+    }
+    (a, b)
+}
+
+// Coarse search for the angle (modulo 90 degrees) and spacing around `guess`, then a fine
+// search around each of the strongest `FRAME_CANDIDATES` local maxima of the periodicity.
+fn lattice_frames(points: &[(f64, f64)], centre: (f64, f64), guess: f64) -> Vec<(f64, f64)> {
+    const ANGLES: usize = 90;
+    const SPACINGS: usize = 56;
+    // Perspective changes the spacing across the image, so favour the middle
+    let sigma = 2.0 * guess;
+    let weights: Vec<f64> = points.iter()
+        .map(|p| (-((p.0 - centre.0).powi(2) + (p.1 - centre.1).powi(2)) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let score = |angle: f64, spacing: f64| {
+        let (a, b) = periodicity(points, &weights, centre, angle, spacing);
+        a.0 * a.0 + a.1 * a.1 + b.0 * b.0 + b.1 * b.1
+    };
+
+    let coarse_angle = |step: usize| (step as f64 - 45.0).to_radians();
+    let coarse_spacing = |k: usize| guess * (0.75 + 0.01 * k as f64);
+    let grid: Vec<Vec<f64>> = (0..ANGLES)
+        .map(|step| (0..SPACINGS).map(|k| score(coarse_angle(step), coarse_spacing(k))).collect())
+        .collect();
+
+    // Angles wrap around at 90 degrees, spacings do not
+    let mut peaks: Vec<(usize, usize, f64)> = Vec::new();
+    for step in 0..ANGLES {
+        for k in 0..SPACINGS {
+            let s = grid[step][k];
+            let is_peak = [ANGLES - 1, 0, 1].iter().all(|&da| {
+                let row = &grid[(step + da) % ANGLES];
+                (k.saturating_sub(1)..(k + 2).min(SPACINGS)).all(|j| row[j] <= s)
+            });
+            if is_peak {
+                peaks.push((step, k, s));
+            }
+        }
+    }
+    peaks.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    peaks.iter().take(FRAME_CANDIDATES)
+        .map(|&(step, k, s)| {
+            let mut best = (coarse_angle(step), coarse_spacing(k), s);
+            let (start_angle, start_spacing) = (best.0, best.1);
+            for i in -10..=10 {
+                let angle = start_angle + (0.1 * i as f64).to_radians();
+                for j in -10..=10 {
+                    let spacing = start_spacing * (1.0 + 0.001 * j as f64);
+                    let s = score(angle, spacing);
+                    if s > best.2 {
+                        best = (angle, spacing, s);
+                    }
+                }
+            }
+            (best.0, best.1)
+        })
+        .collect()
+}
+
+// Offset of the node grid and dot offset in already rotated and scaled coordinates:
+// the pair that puts most of the dots in `from` onto one of the four dot positions, with
+// how well it does. A plain circular mean is skewed whenever the directions are unbalanced.
+fn lattice_phase(rotated: &[(f64, f64)], from: &[usize]) -> ((f64, f64), f64, f64) {
+    const TOLERANCE: f64 = 0.15;
+    let mut best = ((0.0, 0.0), 0.3, f64::MIN);
+    for k in 0..11 {
+        let offset = 0.15 + 0.025 * k as f64;
+        for i in 0..50 {
+            for j in 0..50 {
+                let phase = (i as f64 / 50.0 - 0.5, j as f64 / 50.0 - 0.5);
+                let score: f64 = from.iter()
+                    .map(|&d| {
+                        let p = (rotated[d].0 - phase.0, rotated[d].1 - phase.1);
+                        let distance = [(offset, 0.0), (-offset, 0.0), (0.0, offset), (0.0, -offset)].iter()
+                            .map(|&(dx, dy)| {
+                                let (ra, rb) = residual((p.0 - dx, p.1 - dy));
+                                (ra * ra + rb * rb).sqrt()
+                            })
+                            .fold(f64::MAX, f64::min);
+                        (1.0 - distance / TOLERANCE).max(0.0)
+                    })
+                    .sum();
+                if score > best.2 {
+                    best = (phase, offset, score);
+                }
+            }
+        }
+    }
+    best
+}
+
+fn residual(p: (f64, f64)) -> (f64, f64) {
+    (p.0 - p.0.round(), p.1 - p.1.round())
+}
+
+fn estimate_offset(lattice: &[(f64, f64)]) -> f64 {
+    let mut majors: Vec<f64> = lattice.iter()
+        .map(|&p| {
+            let (ra, rb) = residual(p);
+            ra.abs().max(rb.abs())
+        })
+        .collect();
+    majors.sort_by(|a, b| a.total_cmp(b));
+    majors[majors.len() / 2]
+}
+
+fn assign(lattice: &[(f64, f64)], offset: f64) -> Vec<FittedDot> {
+    lattice.iter().enumerate()
+        .filter_map(|(index, &p)| {
+            let (ra, rb) = residual(p);
+            let direction = if ra.abs() > rb.abs() {
+                if ra > 0.0 { DotDirection::Right } else { DotDirection::Left }
+            } else if rb > 0.0 {
+                DotDirection::Up
+            } else {
+                DotDirection::Down
+            };
+            let (dx, dy) = direction.displacement();
+            let node = ((p.0 - dx * offset).round() as i32, (p.1 - dy * offset).round() as i32);
+            let ideal = (node.0 as f64 + dx * offset, node.1 as f64 + dy * offset);
+            let residual = ((p.0 - ideal.0).powi(2) + (p.1 - ideal.1).powi(2)).sqrt();
+            // Anything closer to another dot position than to its own is not a pattern dot
+            (residual < offset.max(0.05) * 0.7).then_some(FittedDot { index, node, direction, residual })
+        })
+        .collect()
+}
+
+// Ideal lattice point and the normalised image point it was seen at
+type Correspondence = ((f64, f64), (f64, f64));
+
+// Direct linear transform with h33 = 1, solved through the normal equations.
+fn fit_homography(pairs: &[Correspondence]) -> Option<[[f64; 3]; 3]> {
+    if pairs.len() < 4 {
+        return None;
+    }
+    let mut ata = [[0.0; 8]; 8];
+    let mut atb = [0.0; 8];
+    for &((x, y), (u, v)) in pairs {
+        let rows = [
+            ([x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y], u),
+            ([0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y], v),
+        ];
+        for (row, rhs) in rows {
+            for i in 0..8 {
+                atb[i] += row[i] * rhs;
+                for j in 0..8 {
+                    ata[i][j] += row[i] * row[j];
+                }
+            }
+        }
+    }
+    // Keep the perspective terms small when the points only span a few dots
+    let damping = 1e-3 * pairs.len() as f64;
+    ata[6][6] += damping;
+    ata[7][7] += damping;
+    let h = solve8(ata, atb)?;
+    Some([
+        [h[0], h[1], h[2]],
+        [h[3], h[4], h[5]],
+        [h[6], h[7], 1.0],
+    ])
+}
+
+// Gaussian elimination with partial pivoting
+fn solve8(mut a: [[f64; 8]; 8], mut b: [f64; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot_value) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot_value;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; 8];
+    for row in (0..8).rev() {
+        let sum: f64 = ((row + 1)..8).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+fn apply(h: &[[f64; 3]; 3], p: (f64, f64)) -> (f64, f64) {
+    let w = h[2][0] * p.0 + h[2][1] * p.1 + h[2][2];
+    (
+        (h[0][0] * p.0 + h[0][1] * p.1 + h[0][2]) / w,
+        (h[1][0] * p.0 + h[1][1] * p.1 + h[1][2]) / w,
+    )
+}
+
+fn multiply(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut m = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            m[i][j] = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn invert(h: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let det = h[0][0] * (h[1][1] * h[2][2] - h[1][2] * h[2][1])
+        - h[0][1] * (h[1][0] * h[2][2] - h[1][2] * h[2][0])
+        + h[0][2] * (h[1][0] * h[2][1] - h[1][1] * h[2][0]);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    Some([
+        [
+            (h[1][1] * h[2][2] - h[1][2] * h[2][1]) * inv_det,
+            (h[0][2] * h[2][1] - h[0][1] * h[2][2]) * inv_det,
+            (h[0][1] * h[1][2] - h[0][2] * h[1][1]) * inv_det,
+        ],
+        [
+            (h[1][2] * h[2][0] - h[1][0] * h[2][2]) * inv_det,
+            (h[0][0] * h[2][2] - h[0][2] * h[2][0]) * inv_det,
+            (h[0][2] * h[1][0] - h[0][0] * h[1][2]) * inv_det,
+        ],
+        [
+            (h[1][0] * h[2][1] - h[1][1] * h[2][0]) * inv_det,
+            (h[0][1] * h[2][0] - h[0][0] * h[2][1]) * inv_det,
+            (h[0][0] * h[1][1] - h[0][1] * h[1][0]) * inv_det,
+        ],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::anoto_6x6_a4_fixed;
+    use crate::pen_sim::{CameraConfig, simulate_capture};
+
+    const SECTION: (i32, i32) = (10, 2);
+    const NIB: (f64, f64) = (1234.3, 5678.6);
+
+    // Decodes a simulated capture, spurious dots included, and checks the result against
+    // what the simulator printed
+    fn decode_capture(config: &CameraConfig) -> CentroidDecode {
+        let codec = anoto_6x6_a4_fixed();
+        let capture = simulate_capture(&codec, SECTION, NIB, config).unwrap();
+        let mut centroids = capture.dots.clone();
+        centroids.extend(&capture.spurious);
+        let decode = decode_centroids(&codec, &centroids).unwrap();
+
+        let order = codec.mns_order();
+        let (r, c) = decode.window;
+        let rolls = (SECTION.0 % codec.mns_length as i32, SECTION.1 % codec.mns_length as i32);
+        assert_eq!(
+            decode.fit.bits.slice(s![r..r + order, c..c + order, ..]),
+            codec.encode_patch(decode.position, (order, order), rolls).unwrap(),
+            "window at {:?} does not hold the dots printed at {:?}", decode.window, decode.position,
+        );
+        // The image centre is over the nib, so it must fall on the nib's node
+        let centre = (config.image_width as f64 / 2.0, config.image_height as f64 / 2.0);
+        let (x, y) = decode.locate(centre.0, centre.1).unwrap();
+        assert_eq!((x.round(), y.round()), (NIB.0.round(), NIB.1.round()));
+        decode
+    }
+
+    #[test]
+    fn decodes_rotated_captures() {
+        for (rotation_deg, seed) in [(0.0, 1), (137.0, 2), (250.0, 3), (333.0, 4)] {
+            decode_capture(&CameraConfig { rotation_deg, seed, ..Default::default() });
+        }
+    }
+
+    #[test]
+    fn decodes_tilted_captures() {
+        for (rotation_deg, tilt, seed) in [(30.0, (0.1, -0.07), 5), (185.0, (-0.08, 0.1), 6)] {
+            decode_capture(&CameraConfig { rotation_deg, tilt, seed, ..Default::default() });
+        }
+    }
+
+    #[test]
+    fn decodes_through_dropout_and_dirt() {
+        // Every dot of a window has to survive, so the capture is wider than the default
+        let config = CameraConfig {
+            image_width: 160,
+            image_height: 160,
+            rotation_deg: 75.0,
+            tilt: (0.05, 0.05),
+            dropout: 0.03,
+            spurious_dots: 5,
+            seed: 7,
+            ..Default::default()
+        };
+        let decode = decode_capture(&config);
+        assert!(!decode.fit.rejected.is_empty());
+    }
+
+    #[test]
+    fn locates_the_nib_between_dots() {
+        let pitch_mm = 0.3;
+        for (rotation_deg, tilt, seed) in [(0.0, (0.0, 0.0), 1), (137.0, (0.0, 0.0), 2), (30.0, (0.1, -0.07), 5)] {
+            let config = CameraConfig { rotation_deg, tilt, seed, ..Default::default() };
+            let decode = decode_capture(&config);
+            let centre = (config.image_width as f64 / 2.0, config.image_height as f64 / 2.0);
+
+            let nib = decode.locate_mm(centre.0, centre.1, pitch_mm).unwrap();
+            assert!(
+                (nib.grid.0 - NIB.0).abs() < 0.02 && (nib.grid.1 - NIB.1).abs() < 0.02,
+                "located {:?} for a nib at {:?}", nib.grid, NIB,
+            );
+            assert_eq!(nib.mm, (nib.grid.0 * pitch_mm, nib.grid.1 * pitch_mm));
+
+            let rotation = decode.fit.rotation_at(centre.0, centre.1).unwrap().to_degrees();
+            let error = (rotation - rotation_deg + 180.0).rem_euclid(360.0) - 180.0;
+            assert!(error.abs() < 0.5, "rotation {} for a pen at {}", rotation, rotation_deg);
+        }
+    }
+
+    #[test]
+    fn needs_enough_dots() {
+        assert!(fit_grid(&[(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)], 0).is_err());
+        assert!(decode_centroids(&anoto_6x6_a4_fixed(), &[]).is_err());
+    }
+}
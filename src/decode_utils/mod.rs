@@ -1,2 +1,6 @@
+pub mod direction_grid;
 pub mod find_pos;
-pub use find_pos::*;
\ No newline at end of file
+pub mod grid_fit;
+pub use direction_grid::*;
+pub use find_pos::*;
+pub use grid_fit::*;
@@ -0,0 +1,181 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::progress::Cancelled;
+
+/// Error of every fallible library function. Each failure the decoder can hit has its own
+/// variant, so callers such as the REST server can tell a bad request from unreadable pattern.
+#[derive(Debug)]
+pub enum AnotoError {
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+    /// Input text is malformed; `line` and `column` are 1-based, 0 when unknown
+    Parse { message: String, line: usize, column: usize },
+    /// An array or window does not have the shape an operation needs
+    ShapeMismatch { expected: String, found: String },
+    /// A row of bits along `axis` ('x' or 'y') is no window of the main number sequence
+    MnsMiss { axis: char },
+    /// Projected coefficients are no window of secondary number sequence `sequence`
+    SnsMiss { sequence: usize },
+    /// Distance between neighbouring MNS positions lies outside the codec's delta range
+    DeltaOutOfRange { delta: i32, range: (i32, i32) },
+    /// The Chinese remainder step has no solution within 64 bits
+    CrtFailure(String),
+    /// An argument, setting or request is out of range or inconsistent
+    Invalid(String),
+    /// A PDF, image or plot backend failed
+    Format(String),
+    /// `JobProgress::cancel` stopped the work
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, AnotoError>;
+
+impl AnotoError {
+    /// Parse error at `line`, `column` of the input.
+    pub fn parse(message: impl Into<String>, line: usize, column: usize) -> Self {
+        AnotoError::Parse { message: message.into(), line, column }
+    }
+
+    pub fn shape(expected: impl Into<String>, found: impl Into<String>) -> Self {
+        AnotoError::ShapeMismatch { expected: expected.into(), found: found.into() }
+    }
+
+    /// The error with `path` named in its message, for failures reading that file.
+    pub fn in_file(self, path: impl fmt::Display) -> Self {
+        match self {
+            AnotoError::Io(e) => AnotoError::Io(std::io::Error::new(e.kind(), format!("{}: {}", path, e))),
+            AnotoError::Parse { message, line, column } => AnotoError::parse(format!("{}: {}", path, message), line, column),
+            other => other,
+        }
+    }
+
+    /// Stable name of the variant, as the REST server reports it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AnotoError::Io(_) => "io",
+            AnotoError::Parse { .. } => "parse",
+            AnotoError::ShapeMismatch { .. } => "shape_mismatch",
+            AnotoError::MnsMiss { .. } => "mns_miss",
+            AnotoError::SnsMiss { .. } => "sns_miss",
+            AnotoError::DeltaOutOfRange { .. } => "delta_out_of_range",
+            AnotoError::CrtFailure(_) => "crt_failure",
+            AnotoError::Invalid(_) => "invalid",
+            AnotoError::Format(_) => "format",
+            AnotoError::Cancelled => "cancelled",
+        }
+    }
+
+    /// True for failures to decode a window that has the right shape, which a pen expects on
+    /// smudged or misread dots.
+    pub fn is_decoding_failure(&self) -> bool {
+        matches!(self, AnotoError::MnsMiss { .. } | AnotoError::SnsMiss { .. } | AnotoError::DeltaOutOfRange { .. } | AnotoError::CrtFailure(_))
+    }
+}
+
+impl fmt::Display for AnotoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnotoError::Io(e) => write!(f, "{}", e),
+            AnotoError::Parse { message, line: 0, .. } => write!(f, "Parse error: {}", message),
+            AnotoError::Parse { message, line, column } => write!(f, "Parse error at line {}, column {}: {}", line, column, message),
+            AnotoError::ShapeMismatch { expected, found } => write!(f, "Expected {}, got {}", expected, found),
+            AnotoError::MnsMiss { axis } => write!(f, "Decoding error: {} bits are no window of the main number sequence", axis),
+            AnotoError::SnsMiss { sequence } => write!(f, "Decoding error: coefficients are no window of secondary sequence {}", sequence),
+            AnotoError::DeltaOutOfRange { delta, range } => write!(f, "Decoding error: delta {} outside {}..={}", delta, range.0, range.1),
+            AnotoError::CrtFailure(message) => write!(f, "Decoding error: {}", message),
+            AnotoError::Invalid(message) | AnotoError::Format(message) => write!(f, "{}", message),
+            AnotoError::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl Error for AnotoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AnotoError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AnotoError {
+    fn from(e: std::io::Error) -> Self {
+        AnotoError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for AnotoError {
+    fn from(e: serde_json::Error) -> Self {
+        match e.classify() {
+            serde_json::error::Category::Io => AnotoError::Io(e.into()),
+            _ => {
+                // Display appends " at line L column C", which Parse carries on its own
+                let text = e.to_string();
+                let message = text.rsplit_once(" at line ").map_or(text.as_str(), |(message, _)| message);
+                AnotoError::parse(message, e.line(), e.column())
+            }
+        }
+    }
+}
+
+impl From<std::num::ParseIntError> for AnotoError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        AnotoError::parse(e.to_string(), 0, 0)
+    }
+}
+
+impl From<std::num::ParseFloatError> for AnotoError {
+    fn from(e: std::num::ParseFloatError) -> Self {
+        AnotoError::parse(e.to_string(), 0, 0)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for AnotoError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        AnotoError::parse(e.to_string(), 0, 0)
+    }
+}
+
+impl From<std::num::TryFromIntError> for AnotoError {
+    fn from(e: std::num::TryFromIntError) -> Self {
+        AnotoError::Invalid(e.to_string())
+    }
+}
+
+impl From<Cancelled> for AnotoError {
+    fn from(_: Cancelled) -> Self {
+        AnotoError::Cancelled
+    }
+}
+
+impl From<String> for AnotoError {
+    fn from(message: String) -> Self {
+        AnotoError::Invalid(message)
+    }
+}
+
+impl From<&str> for AnotoError {
+    fn from(message: &str) -> Self {
+        AnotoError::Invalid(message.to_string())
+    }
+}
+
+// Backends whose errors only ever reach the user as text
+macro_rules! format_error {
+    ($($t:ty),*) => {
+        $(impl From<$t> for AnotoError {
+            fn from(e: $t) -> Self {
+                AnotoError::Format(e.to_string())
+            }
+        })*
+    };
+}
+
+format_error!(std::fmt::Error, oxidize_pdf::PdfError, oxidize_pdf::parser::ParseError, image::ImageError, tiff::TiffError, png::EncodingError);
+
+impl<E: Error + Send + Sync> From<plotters::drawing::DrawingAreaErrorKind<E>> for AnotoError {
+    fn from(e: plotters::drawing::DrawingAreaErrorKind<E>) -> Self {
+        AnotoError::Format(e.to_string())
+    }
+}
@@ -6,3 +6,6 @@ pub const JB_MONO: Font = Font {
     stretch: iced::font::Stretch::Normal,
     style: iced::font::Style::Normal,
 };
+
+/// The same font as TrueType bytes, for the GUI and for text in generated PDFs.
+pub const JB_MONO_TTF: &[u8] = include_bytes!("assets/fonts/ttf/JetBrainsMonoNL-Medium.ttf");
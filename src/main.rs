@@ -1,8 +1,15 @@
+use anoto_pdf::AnotoError;
 use clap::{Arg, Command};
-use std::error::Error;
 
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), AnotoError> {
 
     let app = Command::new("anoto_pdf")
         .version("1.0")
@@ -16,6 +23,143 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .num_args(0..=4)
                 .value_names(["height", "width", "sect_u", "sect_v"]),
         )
+        .arg(
+            Arg::new("vector")
+                .long("vector")
+                .help("Also write -g output as comma separated vector formats: svg, eps")
+                .num_args(1)
+                .value_names(["formats"]),
+        )
+        .arg(
+            Arg::new("raster")
+                .long("raster")
+                .help("Also write -g output as comma separated print rasters: tiff, png")
+                .num_args(1)
+                .value_names(["formats"]),
+        )
+        .arg(
+            Arg::new("dpi")
+                .long("dpi")
+                .help("Resolution of --raster output (default 1200, at most 4800)")
+                .num_args(1)
+                .value_names(["dpi"]),
+        )
+        .arg(
+            Arg::new("bilevel")
+                .long("bilevel")
+                .help("Write --raster output with 1 bit per pixel instead of grayscale")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("antialias")
+                .long("antialias")
+                .help("Samples per pixel edge on --raster dot outlines, 1 for none (default 4)")
+                .num_args(1)
+                .value_names(["samples"]),
+        )
+        .arg(
+            Arg::new("calibration")
+                .long("calibration")
+                .help("Write a calibration sheet of labelled swatches, sections counting up from: sect_u sect_v (defaults: 10 2)")
+                .num_args(0..=2)
+                .value_names(["sect_u", "sect_v"]),
+        )
+        .arg(
+            Arg::new("dot_sizes")
+                .long("dot-sizes")
+                .help("Comma separated dot radii of the --calibration columns, in points (default 0.5,0.75,1,1.25)")
+                .num_args(1)
+                .value_names(["sizes"]),
+        )
+        .arg(
+            Arg::new("offsets")
+                .long("offsets")
+                .help("Comma separated dot offsets of the --calibration rows, in points (default 2,3)")
+                .num_args(1)
+                .value_names(["offsets"]),
+        )
+        .arg(
+            Arg::new("spacings")
+                .long("spacings")
+                .help("Comma separated grid spacings of the --calibration rows, in points (default 8,10)")
+                .num_args(1)
+                .value_names(["spacings"]),
+        )
+        .arg(
+            Arg::new("poster")
+                .long("poster")
+                .help("Split one continuous pattern over several sheets: width_mm height_mm [sect_u sect_v] (defaults: 841 594 10 2)")
+                .num_args(0..=4)
+                .value_names(["width_mm", "height_mm", "sect_u", "sect_v"]),
+        )
+        .arg(
+            Arg::new("sheet")
+                .long("sheet")
+                .help("Sheet size of --poster: a3, a4, a5, letter, legal, tabloid or WxH in mm (default a4)")
+                .num_args(1)
+                .value_names(["size"]),
+        )
+        .arg(
+            Arg::new("overlap")
+                .long("overlap")
+                .help("Dot columns and rows neighbouring --poster sheets share (default 2)")
+                .num_args(1)
+                .value_names(["dots"]),
+        )
+        .arg(
+            Arg::new("page")
+                .long("page")
+                .help("Generate the pattern of one page address: segment.shelf.book.page")
+                .num_args(1)
+                .value_names(["address"]),
+        )
+        .arg(
+            Arg::new("locate")
+                .long("locate")
+                .help("Print the page address and local position of a decoded position: x y sect_u sect_v")
+                .num_args(4)
+                .value_names(["x", "y", "sect_u", "sect_v"]),
+        )
+        .arg(
+            Arg::new("page_size")
+                .long("page-size")
+                .help("Page size of --page, --locate and --decode addresses in dots (default 54 78)")
+                .num_args(2)
+                .value_names(["width", "height"]),
+        )
+        .arg(
+            Arg::new("document")
+                .long("document")
+                .help("Document the pattern of -g, --poster, --calibration or --page is allocated to in the registry (default: the output file)")
+                .num_args(1)
+                .value_names(["name"]),
+        )
+        .arg(
+            Arg::new("reuse")
+                .long("reuse")
+                .help("Generate even when the pattern is already allocated to another document")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("registry")
+                .long("registry")
+                .help("Allocation registry file (default anoto_registry.json in the working directory)")
+                .num_args(1)
+                .value_names(["filename"]),
+        )
+        .arg(
+            Arg::new("allocations")
+                .long("allocations")
+                .help("List the pattern allocated in the registry")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verify_pdf")
+                .long("verify-pdf")
+                .help("Read a generated PDF back and compare it with the pattern: filename [height width sect_u sect_v] (default from the PDF's metadata or a G__h__w__su__sv name)")
+                .num_args(1..=5)
+                .value_names(["filename", "height", "width", "sect_u", "sect_v"]),
+        )
         .arg(
             Arg::new("generate_json")
                 .short('j')
@@ -28,7 +172,14 @@ fn main() -> Result<(), Box<dyn Error>> {
             Arg::new("decode")
                 .short('d')
                 .long("decode")
-                .help("Decode position from 6x6 section file: filename")
+                .help("Decode position from a direction grid file of at least the codec window order (6x6 by default): JSON, CSV, [x y] pairs or arrows: filename")
+                .num_args(1)
+                .value_names(["filename"]),
+        )
+        .arg(
+            Arg::new("decode_dots")
+                .long("decode-dots")
+                .help("Decode position from dot centroids in a JSON file, e.g. a --simulate ground truth file: filename")
                 .num_args(1)
                 .value_names(["filename"]),
         )
@@ -36,10 +187,76 @@ fn main() -> Result<(), Box<dyn Error>> {
             Arg::new("position")
                 .short('p')
                 .long("pos")
-                .help("Extract 6x6 section at position: row col")
+                .help("Extract the section (codec window order, 6x6 by default) at position: row col")
                 .num_args(2)
                 .value_names(["row", "col"]),
         )
+        .arg(
+            Arg::new("simulate")
+                .short('s')
+                .long("simulate")
+                .help("Render a synthetic pen camera capture at a pattern position: x y [sect_u sect_v] (default section 10 2)")
+                .num_args(2..=4)
+                .allow_negative_numbers(true)
+                .value_names(["x", "y", "sect_u", "sect_v"]),
+        )
+        .arg(
+            Arg::new("rotation")
+                .long("rotation")
+                .help("Camera rotation in degrees for --simulate")
+                .num_args(1)
+                .value_names(["degrees"]),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .help("Random seed for --simulate (dropout, spurious dots, noise) and --synth-codec")
+                .num_args(1)
+                .value_names(["seed"]),
+        )
+        .arg(
+            Arg::new("codec")
+                .short('c')
+                .long("codec")
+                .help("Codec by built-in name, JSON file or name in codecs/ (default anoto_6x6_a4_fixed)")
+                .num_args(1)
+                .value_names(["name_or_file"]),
+        )
+        .arg(
+            Arg::new("export_codec")
+                .long("export-codec")
+                .help("Write the selected codec definition to a JSON file: filename")
+                .num_args(1)
+                .value_names(["filename"]),
+        )
+        .arg(
+            Arg::new("validate_codec")
+                .long("validate-codec")
+                .help("Check the selected codec's sequence properties and report every violation")
+                .num_args(0)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("synth_codec")
+                .long("synth-codec")
+                .help("Search new MNS/SNS sequences and write them as a codec definition: filename")
+                .num_args(1)
+                .value_names(["filename"]),
+        )
+        .arg(
+            Arg::new("order")
+                .long("order")
+                .help("Window order for --synth-codec (default 6)")
+                .num_args(1)
+                .value_names(["order"]),
+        )
+        .arg(
+            Arg::new("pfactors")
+                .long("pfactors")
+                .help("Comma separated SNS prime factors for --synth-codec (default 3,3,2,3)")
+                .num_args(1)
+                .value_names(["factors"]),
+        )
         .arg(
             Arg::new("file")
                 .short('f')
@@ -51,6 +268,49 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let matches = app.get_matches();
 
+    if let Some(filename) = matches.get_one::<String>("synth_codec") {
+        let mut spec = anoto_pdf::codec::SynthesisSpec {
+            name: std::path::Path::new(filename).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+            ..Default::default()
+        };
+        if let Some(order) = matches.get_one::<String>("order") {
+            spec.mns_order = order.parse()?;
+        }
+        if let Some(factors) = matches.get_one::<String>("pfactors") {
+            spec.pfactors = factors.split(',').map(|f| f.trim().parse()).collect::<Result<_, _>>()?;
+        }
+        if let Some(seed) = matches.get_one::<String>("seed") {
+            spec.seed = seed.parse()?;
+        }
+        let definition = anoto_pdf::codec::synthesize_codec(&spec)?;
+        definition.save_json_file(filename)?;
+        let lengths: Vec<usize> = definition.sns.iter().map(|s| s.len()).collect();
+        println!(
+            "Saved codec '{}' to {}: MNS {} bits of order {}, SNS lengths {:?}, delta_range {:?}",
+            definition.name, filename, definition.mns.len(), definition.mns_order, lengths, definition.delta_range
+        );
+        return Ok(());
+    }
+
+    let codec_spec = matches.get_one::<String>("codec").map(String::as_str).unwrap_or(anoto_pdf::codec::DEFAULT_CODEC);
+    let codec_definition = anoto_pdf::codec::resolve_codec_definition(codec_spec)?;
+
+    if let Some(filename) = matches.get_one::<String>("export_codec") {
+        codec_definition.save_json_file(filename)?;
+        println!("Saved codec '{}' to {}", codec_definition.name, filename);
+    }
+
+    if matches.get_flag("validate_codec") {
+        let report = codec_definition.validate();
+        println!("{}", report);
+        if !report.is_valid() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let codec = anoto_pdf::codec::AnotoCodec::from_definition(&codec_definition)?;
+
     // Get position if specified
     let position = if let Some(values) = matches.get_many::<String>("position") {
         let v: Vec<String> = values.map(|s| s.to_string()).collect();
@@ -69,7 +329,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         None
     };
 
-    // If position and file are both specified, load matrix and extract 6x6
+    // If position and file are both specified, load matrix and extract one codec window
     if let (Some(pos), Some(filename)) = (position, file) {
         let bitmatrix = if filename.ends_with(".json") {
             anoto_pdf::load_matrix_from_json(&filename)?
@@ -78,26 +338,206 @@ fn main() -> Result<(), Box<dyn Error>> {
         } else {
             return Err("Unsupported file format. Use .json or .txt".into());
         };
-        anoto_pdf::extract_6x6_section(&bitmatrix, pos)?;
+        anoto_pdf::extract_section(&bitmatrix, pos, codec.mns_order())?;
         return Ok(());
     }
 
+    let page_size = match matches.get_many::<String>("page_size") {
+        Some(values) => {
+            let v: Vec<usize> = values.map(|s| s.parse()).collect::<Result<_, _>>().map_err(|_| "Invalid --page-size")?;
+            (v[0], v[1])
+        }
+        None => anoto_pdf::codec::AddressSpace::DEFAULT_PAGE_SIZE,
+    };
+    let address_space = || anoto_pdf::codec::AddressSpace::new(&codec, page_size);
+
+    // Only commands that allocate pattern read the registry
+    let open_registry = || anoto_pdf::registry::Registry::open(matches.get_one::<String>("registry").map(String::as_str).unwrap_or(anoto_pdf::registry::REGISTRY_FILE));
+    let document = matches.get_one::<String>("document").cloned();
+    let reuse = matches.get_flag("reuse");
+
+    if matches.get_flag("allocations") {
+        for allocation in &open_registry()?.allocations {
+            println!("{}", allocation);
+        }
+    }
+
+    if let Some(address) = matches.get_one::<String>("page") {
+        let space = address_space()?;
+        let mut registry = open_registry()?;
+        let address: anoto_pdf::codec::PageAddress = if address == "next" {
+            // First free page of the default section
+            let segment = 10 * codec.mns_length as u64 + 2;
+            registry.fresh_page(&codec, document.as_deref().unwrap_or_default(), &space, segment).ok_or("No free page left in the default section")?
+        } else {
+            address.parse()?
+        };
+        let (section, origin) = space.page_origin(&address)?;
+        let file = format!("output/PAGE__{}.pdf", address);
+        let mut allocation = anoto_pdf::registry::Allocation::new(&codec, document.as_deref().unwrap_or(&file), section, origin, page_size, &file);
+        allocation.address = Some(address);
+        registry.check(&allocation, reuse)?;
+
+        let ((sect_u, sect_v), (x, y)) = anoto_pdf::anoto_matrix::save_page_pattern(&codec, &space, &address)?;
+        registry.record(allocation)?;
+        println!("Page {} -> {}: section ({}, {}), origin ({}, {}), {}x{} dots", address, file, sect_u, sect_v, x, y, page_size.0, page_size.1);
+    }
+
+    if let Some(values) = matches.get_many::<String>("locate") {
+        let v: Vec<i64> = values.map(|s| s.parse()).collect::<Result<_, _>>()?;
+        let page = address_space()?.locate((i32::try_from(v[2])?, i32::try_from(v[3])?), (v[0], v[1]))?;
+        println!("PAGE {} LOCAL ({}, {})", page.address, page.local.0, page.local.1);
+    }
+
     if let Some(values) = matches.get_many::<String>("generate") {
         let v: Vec<String> = values.map(|s| s.to_string()).collect();
         let height = v.first().unwrap_or(&"9".to_string()).parse().unwrap_or(9);
         let width = v.get(1).unwrap_or(&"16".to_string()).parse().unwrap_or(16);
-        let sect_u = v.get(2).unwrap_or(&"10".to_string()).parse().unwrap_or(10);
-        let sect_v = v.get(3).unwrap_or(&"2".to_string()).parse().unwrap_or(2);
+        let mut registry = open_registry()?;
+        let file = |(sect_u, sect_v)| format!("output/G__{}__{}__{}__{}.pdf", height, width, sect_u, sect_v);
+        let (sect_u, sect_v) = reserve_section(&registry, &codec, document.as_deref(), file, &v[v.len().min(2)..], (width, height))?;
+        let file = file((sect_u, sect_v));
+        let allocation = anoto_pdf::registry::Allocation::new(&codec, document.as_deref().unwrap_or(&file), (sect_u, sect_v), (0, 0), (width, height), &file);
+        registry.check(&allocation, reuse)?;
 
-        let bitmatrix = anoto_pdf::generate_matrix_only(height, width, sect_u, sect_v)?;
-        
-        // If position is specified, extract 6x6 section
+        // If position is specified, extract the codec window there
         if let Some(pos) = position {
-            anoto_pdf::extract_6x6_section(&bitmatrix, pos)?;
+            anoto_pdf::anoto_matrix::extract_generated_section(&codec, height, width, (sect_u, sect_v), pos)?;
         }
         
-        // Save the full matrix
-        anoto_pdf::save_generated_matrix(&bitmatrix, height, width, sect_u, sect_v)?;
+        // Stream the full pattern to disk
+        anoto_pdf::anoto_matrix::save_generated_pattern(&codec, height, width, sect_u, sect_v)?;
+
+        if let Some(formats) = matches.get_one::<String>("vector") {
+            for format in formats.split(',').map(|f| f.trim().to_lowercase()).filter(|f| !f.is_empty()) {
+                anoto_pdf::anoto_matrix::save_generated_vector(&codec, height, width, sect_u, sect_v, &format)?;
+            }
+        }
+
+        if let Some(formats) = matches.get_one::<String>("raster") {
+            let mut raster = anoto_pdf::pdf_dotpaper::gen_raster::RasterConfig { bilevel: matches.get_flag("bilevel"), ..Default::default() };
+            if let Some(dpi) = matches.get_one::<String>("dpi") {
+                raster.dpi = dpi.parse().map_err(|_| format!("Invalid --dpi '{}'", dpi))?;
+            }
+            if let Some(samples) = matches.get_one::<String>("antialias") {
+                raster.antialias = samples.parse().map_err(|_| format!("Invalid --antialias '{}'", samples))?;
+            }
+            for format in formats.split(',').map(|f| f.trim().to_lowercase()).filter(|f| !f.is_empty()) {
+                anoto_pdf::anoto_matrix::save_generated_raster(&codec, height, width, sect_u, sect_v, &format, &raster)?;
+            }
+        }
+        registry.record(allocation)?;
+    }
+
+    if let Some(values) = matches.get_many::<String>("calibration") {
+        let v: Vec<String> = values.map(|s| s.to_string()).collect();
+        let sect_u = v.first().unwrap_or(&"10".to_string()).parse().unwrap_or(10);
+        let sect_v = v.get(1).unwrap_or(&"2".to_string()).parse().unwrap_or(2);
+
+        let mut spec = anoto_pdf::pdf_dotpaper::gen_calibration::CalibrationSpec::default();
+        for (arg, values) in [("dot_sizes", &mut spec.dot_sizes), ("offsets", &mut spec.offsets), ("spacings", &mut spec.spacings)] {
+            if let Some(list) = matches.get_one::<String>(arg) {
+                *values = list
+                    .split(',')
+                    .map(|n| n.trim())
+                    .filter(|n| !n.is_empty())
+                    .map(|n| n.parse().map_err(|_| format!("Invalid --{} value '{}'", arg.replace('_', "-"), n)))
+                    .collect::<Result<_, _>>()?;
+            }
+        }
+        // Every swatch is a region of its own section
+        let mut registry = open_registry()?;
+        let file = format!("output/CAL__{}__{}.pdf", sect_u, sect_v);
+        let allocations = anoto_pdf::pdf_dotpaper::gen_calibration::calibration_swatches(&codec, (sect_u, sect_v), &spec)?
+            .iter()
+            .map(|swatch| anoto_pdf::registry::Allocation::new(&codec, document.as_deref().unwrap_or(&file), swatch.section, (0, 0), swatch.size, &file))
+            .collect::<Vec<_>>();
+        for allocation in &allocations {
+            registry.check(allocation, reuse)?;
+        }
+
+        let swatches = anoto_pdf::anoto_matrix::save_calibration_sheet(&codec, sect_u, sect_v, &spec)?;
+        for allocation in allocations {
+            registry.record(allocation)?;
+        }
+        println!("Calibration sheet output/CAL__{}__{}.pdf, sizes in points:", sect_u, sect_v);
+        for swatch in &swatches {
+            println!("{}", swatch);
+        }
+    }
+
+    if let Some(values) = matches.get_many::<String>("poster") {
+        let v: Vec<String> = values.map(|s| s.to_string()).collect();
+        let mut spec = anoto_pdf::pdf_dotpaper::gen_poster::PosterSpec::default();
+        spec.area_mm.0 = v.first().map(|w| w.parse()).transpose()?.unwrap_or(spec.area_mm.0);
+        spec.area_mm.1 = v.get(1).map(|h| h.parse()).transpose()?.unwrap_or(spec.area_mm.1);
+        if let Some(sheet) = matches.get_one::<String>("sheet") {
+            spec.sheet_mm = anoto_pdf::pdf_dotpaper::gen_poster::paper_size_mm(sheet).ok_or_else(|| format!("Unknown --sheet '{}'", sheet))?;
+        }
+        if let Some(overlap) = matches.get_one::<String>("overlap") {
+            spec.overlap = overlap.parse().map_err(|_| format!("Invalid --overlap '{}'", overlap))?;
+        }
+        let size = anoto_pdf::pdf_dotpaper::gen_poster::poster_layout(&spec, (0, 0))?.size;
+        let mut registry = open_registry()?;
+        let file = |(sect_u, sect_v)| format!("output/P__{}__{}__{}__{}.pdf", size.0, size.1, sect_u, sect_v);
+        let (sect_u, sect_v) = reserve_section(&registry, &codec, document.as_deref(), file, &v[v.len().min(2)..], size)?;
+        let file = file((sect_u, sect_v));
+        let allocation = anoto_pdf::registry::Allocation::new(&codec, document.as_deref().unwrap_or(&file), (sect_u, sect_v), (0, 0), size, &file);
+        registry.check(&allocation, reuse)?;
+
+        let (filename, poster) = anoto_pdf::anoto_matrix::save_poster(&codec, sect_u, sect_v, &spec)?;
+        registry.record(allocation)?;
+        println!(
+            "Poster output/{}: {}x{} dots on {} sheets ({} columns, {} rows)",
+            filename,
+            poster.size.0,
+            poster.size.1,
+            poster.sheets.len(),
+            poster.columns,
+            poster.rows
+        );
+        for sheet in &poster.sheets {
+            println!("{}", sheet);
+        }
+    }
+
+    if let Some(values) = matches.get_many::<String>("verify_pdf") {
+        let v: Vec<String> = values.map(|s| s.to_string()).collect();
+        let filename = &v[0];
+        let metadata = if v.len() > 1 { None } else { anoto_pdf::pdf_dotpaper::metadata::read_pattern_metadata(filename)? };
+        let mut labels = Vec::new();
+        let pages = if let Some(metadata) = metadata.filter(|m| m.pattern.section.is_some() || !m.swatches.is_empty()) {
+            let (_, pages) = anoto_pdf::anoto_matrix::verify_pdf_from_metadata(&codec, filename)?;
+            if let (Some((sect_u, sect_v)), Some(codec_name)) = (metadata.pattern.section, &metadata.pattern.codec) {
+                println!("Pattern from PDF metadata: codec {}, section ({}, {})", codec_name, sect_u, sect_v);
+            }
+            if !metadata.swatches.is_empty() {
+                println!("Calibration sheet from PDF metadata: {} swatches", metadata.swatches.len());
+                labels = metadata.swatches.iter().map(|swatch| format!("Swatch {}", swatch)).collect();
+            }
+            pages
+        } else {
+            let shape: Vec<i64> = if v.len() > 1 {
+                v[1..].iter().map(|s| s.parse()).collect::<Result<_, _>>()?
+            } else {
+                // G__{height}__{width}__{sect_u}__{sect_v}.pdf as written by -g
+                let stem = std::path::Path::new(filename).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+                stem.strip_prefix("G__").map(|rest| rest.split("__").filter_map(|n| n.parse().ok()).collect()).unwrap_or_default()
+            };
+            if shape.len() != 4 {
+                return Err(format!("Give the pattern as height width sect_u sect_v for {}", filename).into());
+            }
+            anoto_pdf::anoto_matrix::verify_generated_pdf(&codec, filename, shape[0] as usize, shape[1] as usize, shape[2] as i32, shape[3] as i32)?
+        };
+        for (index, page) in pages.iter().enumerate() {
+            if let Some(label) = labels.get(index) {
+                println!("{}", label);
+            }
+            println!("{}", page);
+        }
+        if pages.is_empty() || pages.iter().any(|p| !p.is_match()) {
+            std::process::exit(1);
+        }
     }
 
     if let Some(values) = matches.get_many::<String>("generate_json") {
@@ -106,9 +546,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         
         let bitmatrix = anoto_pdf::load_matrix_from_json(&filename)?;
         
-        // If position is specified, extract 6x6 section
+        // If position is specified, extract the codec window there
         if let Some(pos) = position {
-            anoto_pdf::extract_6x6_section(&bitmatrix, pos)?;
+            anoto_pdf::extract_section(&bitmatrix, pos, codec.mns_order())?;
         }
         
         // Save the full matrix
@@ -119,12 +559,120 @@ fn main() -> Result<(), Box<dyn Error>> {
         let v: Vec<String> = values.map(|s| s.to_string()).collect();
         let filename = v.first().unwrap().clone();
         
-        let section = anoto_pdf::persist_json::load_6x6_section(&filename)?;
-        match anoto_pdf::decode_utils::decode_position(&section) {
-            Some((row, col)) => println!("POS ({}, {})", row, col),
-            None => println!("Could not decode position from section"),
+        let grid = std::fs::read_to_string(&filename)
+            .map_err(AnotoError::from)
+            .and_then(|text| anoto_pdf::decode_utils::DirectionGrid::parse(&text))
+            .map_err(|e| e.in_file(&filename))?;
+        let report = grid.decode(&codec)?;
+        // POS is the grid's top-left dot, wherever the first readable window sits
+        match (report.origin(), report.decoded.first()) {
+            (Some((x, y)), Some(window)) => {
+                println!("POS ({}, {})", x, y);
+                if let Some(page) = window.section.and_then(|s| address_space().ok()?.locate(s, (x, y)).ok()) {
+                    println!("PAGE {} LOCAL ({}, {})", page.address, page.local.0, page.local.1);
+                }
+                if report.decoded.len() + report.errors.len() > 1 {
+                    println!("WINDOWS {} of {} decoded", report.decoded.len(), report.decoded.len() + report.errors.len());
+                }
+            }
+            _ => println!("Could not decode position from section: {}", report.errors[0].error),
         }
     }
 
+    if let Some(filename) = matches.get_one::<String>("decode_dots") {
+        let (centroids, image_centre) = load_centroids(filename)?;
+        let decoded = anoto_pdf::decode_utils::decode_centroids(&codec, &centroids)?;
+        // Without camera metadata the middle of the dot cloud stands in for the image centre
+        let reference = image_centre.unwrap_or_else(|| {
+            let n = centroids.len() as f64;
+            let sum = centroids.iter().fold((0.0, 0.0), |acc, p| (acc.0 + p.0, acc.1 + p.1));
+            (sum.0 / n, sum.1 / n)
+        });
+        println!(
+            "Fitted {} of {} dots, spacing {:.2}px, rotation {:.1} deg",
+            decoded.fit.dots.len(),
+            centroids.len(),
+            decoded.fit.spacing,
+            decoded.fit.rotation_at(reference.0, reference.1).unwrap_or(decoded.fit.angle).to_degrees().rem_euclid(360.0)
+        );
+        println!("POS ({}, {})", decoded.position.0, decoded.position.1);
+        let pitch_mm = anoto_pdf::pdf_dotpaper::gen_pdf::PdfConfig::default().dot_pitch_mm();
+        if let Some(nib) = decoded.locate_mm(reference.0, reference.1, pitch_mm) {
+            println!(
+                "NIB ({:.3}, {:.3}) = ({:.2} mm, {:.2} mm)",
+                nib.grid.0, nib.grid.1, nib.mm.0, nib.mm.1
+            );
+        }
+    }
+
+    if let Some(values) = matches.get_many::<String>("simulate") {
+        let v: Vec<String> = values.map(|s| s.to_string()).collect();
+        let x: f64 = v[0].parse()?;
+        let y: f64 = v[1].parse()?;
+        let sect_u = v.get(2).unwrap_or(&"10".to_string()).parse().unwrap_or(10);
+        let sect_v = v.get(3).unwrap_or(&"2".to_string()).parse().unwrap_or(2);
+
+        let config = anoto_pdf::pen_sim::CameraConfig {
+            rotation_deg: matches.get_one::<String>("rotation").map(|r| r.parse()).transpose()?.unwrap_or(0.0),
+            seed: matches.get_one::<String>("seed").map(|r| r.parse()).transpose()?.unwrap_or(0),
+            ..Default::default()
+        };
+        let capture = anoto_pdf::pen_sim::simulate_capture(&codec, (sect_u, sect_v), (x, y), &config)?;
+        let base_filename = format!("S__{}__{}__{}__{}", x, y, sect_u, sect_v);
+        anoto_pdf::pen_sim::save_capture(&capture, &config, &base_filename)?;
+        println!("POS ({}, {})", capture.position.0, capture.position.1);
+    }
+
     Ok(())
 }
+
+// Accepts a plain [[u, v], ...] array or an object with "dots" (and optionally
+// "spurious") arrays, as written by --simulate. Also returns the image centre when
+// the file carries the camera size.
+type Centroids = (Vec<(f64, f64)>, Option<(f64, f64)>);
+
+fn load_centroids(filename: &str) -> Result<Centroids, AnotoError> {
+    let value: serde_json::Value = std::fs::read_to_string(filename)
+        .map_err(AnotoError::from)
+        .and_then(|json| Ok(serde_json::from_str(&json)?))
+        .map_err(|e| e.in_file(filename))?;
+    let arrays: Vec<&serde_json::Value> = match &value {
+        serde_json::Value::Array(_) => vec![&value],
+        serde_json::Value::Object(map) => ["dots", "spurious"].iter().filter_map(|k| map.get(*k)).collect(),
+        _ => Vec::new(),
+    };
+    if arrays.is_empty() {
+        return Err("Expected an array of [u, v] centroids or an object with a \"dots\" array".into());
+    }
+    let mut centroids = Vec::new();
+    for array in arrays {
+        centroids.extend(serde_json::from_value::<Vec<(f64, f64)>>(array.clone())?);
+    }
+    let image_centre = value.get("camera").and_then(|camera| {
+        let width = camera.get("image_width")?.as_f64()?;
+        let height = camera.get("image_height")?.as_f64()?;
+        Some((width / 2.0, height / 2.0))
+    });
+    Ok((centroids, image_centre))
+}
+
+// Section of a new `size` region at the pattern origin: the one given as `sect_u sect_v`, or else the
+// first from the default (10, 2) on that the registry has free. Without a document name a section is
+// recorded under its output `file`, so running the same command again finds the section it took.
+fn reserve_section(
+    registry: &anoto_pdf::registry::Registry,
+    codec: &anoto_pdf::codec::AnotoCodec,
+    document: Option<&str>,
+    file: impl Fn((i32, i32)) -> String,
+    section: &[String],
+    size: (usize, usize),
+) -> Result<(i32, i32), AnotoError> {
+    if let Some(sect_u) = section.first() {
+        return Ok((sect_u.parse().unwrap_or(10), section.get(1).map(|v| v.parse().unwrap_or(2)).unwrap_or(2)));
+    }
+    let free = registry.fresh_section(codec, |section| document.map_or_else(|| file(section), str::to_string), (10, 2), size).ok_or("Every section is allocated at the pattern origin")?;
+    if free != (10, 2) {
+        println!("Section (10, 2) is allocated, using ({}, {})", free.0, free.1);
+    }
+    Ok(free)
+}
@@ -1,2 +1,2 @@
 pub mod plotting;
-pub use plotting::{draw_dots, draw_dots_y_axis, draw_preview_image, draw_dot_on_file, draw_dots_on_file};
+pub use plotting::{draw_dots, draw_dots_y_axis, draw_preview_image, draw_preview_image_with_progress, draw_preview_png_bytes, draw_dot_on_file, draw_dots_on_file};
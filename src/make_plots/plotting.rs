@@ -1,6 +1,9 @@
 use plotters::prelude::*;
-use std::error::Error;
+use rayon::prelude::*;
 use crate::pdf_dotpaper::gen_pdf::PdfConfig;
+use crate::progress::JobProgress;
+use crate::error::AnotoError;
+use image::ImageEncoder;
 
 fn parse_hex_to_rgb(hex: &str) -> RGBColor {
     let hex = hex.trim_start_matches('#');
@@ -18,7 +21,35 @@ pub fn draw_preview_image(
     bitmatrix: &ndarray::Array3<i8>,
     config: &PdfConfig,
     filename: &str
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), AnotoError> {
+    draw_preview_image_with_progress(bitmatrix, config, filename, &JobProgress::new())
+}
+
+// Image rows per strip; strips are rendered in parallel
+const PREVIEW_STRIP_ROWS: usize = 256;
+
+/// `draw_preview_image` reporting the strips rendered to `progress`, stopping with `Cancelled` when asked to.
+pub fn draw_preview_image_with_progress(
+    bitmatrix: &ndarray::Array3<i8>,
+    config: &PdfConfig,
+    filename: &str,
+    progress: &JobProgress,
+) -> Result<(), AnotoError> {
+    let (buffer, img_width, img_height) = render_preview(bitmatrix, config, progress)?;
+    image::save_buffer(filename, &buffer, img_width, img_height, image::ExtendedColorType::Rgb8)?;
+    Ok(())
+}
+
+/// The `draw_preview_image` page as PNG bytes, without touching the file system.
+pub fn draw_preview_png_bytes(bitmatrix: &ndarray::Array3<i8>, config: &PdfConfig) -> Result<Vec<u8>, AnotoError> {
+    let (buffer, img_width, img_height) = render_preview(bitmatrix, config, &JobProgress::new())?;
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png).write_image(&buffer, img_width, img_height, image::ExtendedColorType::Rgb8)?;
+    Ok(png)
+}
+
+// RGB pixels of the A4 preview with their width and height
+fn render_preview(bitmatrix: &ndarray::Array3<i8>, config: &PdfConfig, progress: &JobProgress) -> Result<(Vec<u8>, u32, u32), AnotoError> {
     // A4 dimensions in points (1/72 inch)
     let a4_width_pts = 595.276;
     let a4_height_pts = 841.89;
@@ -29,15 +60,6 @@ pub fn draw_preview_image(
     let img_width = (a4_width_pts * scale).ceil() as u32;
     let img_height = (a4_height_pts * scale).ceil() as u32;
 
-    let root_area = BitMapBackend::new(filename, (img_width, img_height))
-        .into_drawing_area();
-    root_area.fill(&WHITE)?;
-
-    // Use PDF coordinate system: 0..width, 0..height (points)
-    // This assumes PDF origin is bottom-left.
-    let mut chart = ChartBuilder::on(&root_area)
-        .build_cartesian_2d(0f64..a4_width_pts, 0f64..a4_height_pts)?;
-
     let height = bitmatrix.dim().0;
     let width = bitmatrix.dim().1;
     let radius_px = (config.dot_size as f64 * scale).max(1.0) as u32;
@@ -48,38 +70,75 @@ pub fn draw_preview_image(
     let margin_x = (a4_width_pts - grid_width) / 2.0;
     let margin_y = (a4_height_pts - grid_height) / 2.0;
 
-    chart.draw_series(
-        (0..height).flat_map(move |y| {
-            (0..width).map(move |x| {
-                let x_bit = bitmatrix[[y, x, 0]] as usize;
-                let y_bit = bitmatrix[[y, x, 1]] as usize;
-                let dot_type = x_bit + (y_bit << 1);
-                
-                let color = match dot_type {
-                    0 => parse_hex_to_rgb(&config.color_up),
-                    1 => parse_hex_to_rgb(&config.color_left),
-                    2 => parse_hex_to_rgb(&config.color_right),
-                    3 => parse_hex_to_rgb(&config.color_down),
-                    _ => BLACK,
-                };
-                
-                let x_pos = margin_x + x as f64 * config.grid_spacing as f64;
-                let y_pos = margin_y + y as f64 * config.grid_spacing as f64;
-                
-                let (dx, dy) = match dot_type {
-                    0 => (0.0, config.offset_from_origin as f64), // Up
-                    1 => (-config.offset_from_origin as f64, 0.0), // Left
-                    2 => (config.offset_from_origin as f64, 0.0), // Right
-                    3 => (0.0, -config.offset_from_origin as f64), // Down
-                    _ => (0.0, 0.0),
-                };
-                
-                Circle::new((x_pos + dx, y_pos + dy), radius_px, color.filled())
-            })
+    let colors = [
+        parse_hex_to_rgb(&config.color_up),
+        parse_hex_to_rgb(&config.color_left),
+        parse_hex_to_rgb(&config.color_right),
+        parse_hex_to_rgb(&config.color_down),
+    ];
+
+    // Dot centres in image pixels, PDF origin at the bottom-left
+    let dots: Vec<((i32, i32), RGBColor)> = (0..height).flat_map(|y| {
+        (0..width).map(move |x| {
+            let x_bit = bitmatrix[[y, x, 0]] as usize;
+            let y_bit = bitmatrix[[y, x, 1]] as usize;
+            let dot_type = x_bit + (y_bit << 1);
+            let color = colors.get(dot_type).copied().unwrap_or(BLACK);
+
+            let x_pos = margin_x + x as f64 * config.grid_spacing as f64;
+            let y_pos = margin_y + y as f64 * config.grid_spacing as f64;
+
+            let (dx, dy) = match dot_type {
+                0 => (0.0, config.offset_from_origin as f64), // Up
+                1 => (-config.offset_from_origin as f64, 0.0), // Left
+                2 => (config.offset_from_origin as f64, 0.0), // Right
+                3 => (0.0, -config.offset_from_origin as f64), // Down
+                _ => (0.0, 0.0),
+            };
+
+            // Clamped to the image like plotters does for elements on a drawing area
+            let px = map_axis(x_pos + dx, a4_width_pts, (0, img_width as i32 - 1)).clamp(0, img_width as i32);
+            let py = map_axis(y_pos + dy, a4_height_pts, (img_height as i32 - 1, 0)).clamp(0, img_height as i32);
+            ((px, py), color)
         })
-    )?;
+    }).collect();
 
-    Ok(())
+    let row_bytes = img_width as usize * 3;
+    let mut buffer = vec![255u8; row_bytes * img_height as usize];
+    progress.start("Rendering preview", buffer.len().div_ceil(row_bytes * PREVIEW_STRIP_ROWS));
+
+    buffer
+        .par_chunks_mut(row_bytes * PREVIEW_STRIP_ROWS)
+        .enumerate()
+        .try_for_each(|(i, strip)| -> Result<(), AnotoError> {
+            progress.check()?;
+            let top = (i * PREVIEW_STRIP_ROWS) as i32;
+            let rows = (strip.len() / row_bytes) as i32;
+            let reach = radius_px as i32 + 1;
+            // Drawn on the backend directly: a drawing area would clamp centres outside the strip onto its edge
+            let mut backend = BitMapBackend::with_buffer(strip, (img_width, rows as u32));
+            // Dots near the strip edges are drawn in both strips, each keeping its own pixels
+            for &((x, y), color) in dots.iter().filter(|((_, y), _)| *y + reach >= top && *y - reach < top + rows) {
+                backend.draw_circle((x, y - top), radius_px, &color.filled(), true)
+                    .map_err(|e| AnotoError::Format(e.to_string()))?;
+            }
+            backend.present().map_err(|e| AnotoError::Format(e.to_string()))?;
+            progress.advance(1);
+            Ok(())
+        })?;
+
+    Ok((buffer, img_width, img_height))
+}
+
+// Pixel plotters assigns to `v` on a 0..range_end chart axis spanning the `limit` pixels
+fn map_axis(v: f64, range_end: f64, limit: (i32, i32)) -> i32 {
+    let logic_length = v / range_end;
+    let actual_length = limit.1 - limit.0;
+    if actual_length > 0 {
+        limit.0 + (actual_length as f64 * logic_length + 1e-3).floor() as i32
+    } else {
+        limit.0 + (actual_length as f64 * logic_length - 1e-3).ceil() as i32
+    }
 }
 
 pub fn draw_dot_on_file(
@@ -89,7 +148,7 @@ pub fn draw_dot_on_file(
     matrix_height: usize,
     matrix_width: usize,
     config: &PdfConfig,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), AnotoError> {
     // Load image
     let mut img = image::open(filename)?.to_rgba8();
     
@@ -142,7 +201,7 @@ pub fn draw_dots_on_file(
     matrix_height: usize,
     matrix_width: usize,
     config: &PdfConfig,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), AnotoError> {
     // Load image
     let mut img = image::open(filename)?.to_rgba8();
     
@@ -193,7 +252,7 @@ pub fn draw_dots_on_file(
 pub fn draw_dots(
     bitmatrix: &ndarray::Array3<i8>,
     _grid_size: f64,
-    base_filename: &str) -> Result<(), Box<dyn Error>> {
+    base_filename: &str) -> Result<(), AnotoError> {
 
     // Persist the bitmatrix
     // crate::persist_json::save_bitmatrix_text(bitmatrix, &format!("{}.txt", base_filename))?;
@@ -276,7 +335,7 @@ pub fn draw_dots(
 pub fn draw_dots_y_axis(
     bitmatrix: &ndarray::Array3<i8>,
     _grid_size: f64,
-    filename: &str) -> Result<(), Box<dyn Error>> {
+    filename: &str) -> Result<(), AnotoError> {
 
     let root_area = BitMapBackend::new(filename, (800, 400))
     .into_drawing_area();
@@ -0,0 +1,218 @@
+use oxidize_pdf::text::Font;
+use oxidize_pdf::{Document, Page};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use super::gen_pdf::{DotLayout, PdfConfig, document_bytes, dot_operations, dots_by_direction, output_path, page_size};
+use super::metadata::{PatternInfo, PatternMetadata};
+use crate::codec::AnotoCodec;
+use crate::progress::JobProgress;
+use crate::error::AnotoError;
+
+const MARGIN: f64 = 36.0;
+const HEADER_HEIGHT: f64 = 22.0;
+const HEADER_FONT_SIZE: f64 = 9.0;
+const CELL_PADDING: f64 = 6.0;
+const CAPTION_FONT_SIZE: f64 = 6.5;
+const CAPTION_LINE_HEIGHT: f64 = 8.0;
+// JetBrains Mono advances every glyph by 0.6 em
+const GLYPH_ADVANCE: f64 = 0.6;
+const CAPTION_FONT: &str = "JetBrainsMonoNL";
+
+/// Settings a calibration sheet varies: one column per dot size, one row per spacing and offset.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalibrationSpec {
+    pub dot_sizes: Vec<f32>,
+    pub offsets: Vec<f32>,
+    pub spacings: Vec<f32>,
+    /// Colours of every swatch; its dot size, offset and spacing are replaced per swatch
+    pub config: PdfConfig,
+}
+
+impl Default for CalibrationSpec {
+    fn default() -> Self {
+        Self {
+            dot_sizes: vec![0.5, 0.75, 1.0, 1.25],
+            offsets: vec![2.0, 3.0],
+            spacings: vec![8.0, 10.0],
+            config: PdfConfig::default(),
+        }
+    }
+}
+
+/// One labelled pattern patch of a calibration sheet.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationSwatch {
+    /// (sect_u, sect_v), distinct for every swatch of a sheet
+    pub section: (i32, i32),
+    pub dot_size: f32,
+    pub offset_from_origin: f32,
+    pub grid_spacing: f32,
+    /// (width, height) in dots, pattern origin (0, 0)
+    pub size: (usize, usize),
+    /// Centre of the dot grid on the page, in PDF points
+    pub centre: (f64, f64),
+}
+
+impl CalibrationSwatch {
+    /// `base` with the swatch's dot size, offset and spacing.
+    pub fn config(&self, base: &PdfConfig) -> PdfConfig {
+        PdfConfig {
+            dot_size: self.dot_size,
+            offset_from_origin: self.offset_from_origin,
+            grid_spacing: self.grid_spacing,
+            ..base.clone()
+        }
+    }
+
+    fn caption(&self) -> [String; 2] {
+        [
+            format!("dot {:.2}  off {:.1}  pitch {:.1}", self.dot_size, self.offset_from_origin, self.grid_spacing),
+            format!("section ({}, {})  {}x{}", self.section.0, self.section.1, self.size.0, self.size.1),
+        ]
+    }
+}
+
+impl std::fmt::Display for CalibrationSwatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [settings, pattern] = self.caption();
+        write!(f, "{}  ({:.2} mm)  {}", settings, self.grid_spacing as f64 * 25.4 / 72.0, pattern)
+    }
+}
+
+// Cells of the swatch grid below the header: the swatch area of cell `index` as (left, top,
+// width, height), with the caption below it.
+struct CellGrid {
+    columns: usize,
+    cell_width: f64,
+    cell_height: f64,
+}
+
+impl CellGrid {
+    fn new(spec: &CalibrationSpec) -> Self {
+        let (page_width, page_height) = page_size();
+        let columns = spec.dot_sizes.len();
+        let rows = spec.spacings.len() * spec.offsets.len();
+        CellGrid {
+            columns,
+            cell_width: (page_width - 2.0 * MARGIN) / columns as f64,
+            cell_height: (page_height - 2.0 * MARGIN - HEADER_HEIGHT) / rows as f64,
+        }
+    }
+
+    fn area(&self, index: usize) -> (f64, f64, f64, f64) {
+        let (_, page_height) = page_size();
+        let (row, column) = (index / self.columns, index % self.columns);
+        (
+            MARGIN + column as f64 * self.cell_width + CELL_PADDING,
+            page_height - MARGIN - HEADER_HEIGHT - row as f64 * self.cell_height - CELL_PADDING,
+            self.cell_width - 2.0 * CELL_PADDING,
+            self.cell_height - 2.0 * CELL_PADDING - 2.0 * CAPTION_LINE_HEIGHT,
+        )
+    }
+}
+
+/// Lays out the swatches of `spec` on one A4 page, row by row. The sections count up in u from
+/// `section`; every swatch fills its cell above a two-line caption.
+pub fn calibration_swatches(codec: &AnotoCodec, section: (i32, i32), spec: &CalibrationSpec) -> Result<Vec<CalibrationSwatch>, AnotoError> {
+    if spec.dot_sizes.is_empty() || spec.offsets.is_empty() || spec.spacings.is_empty() {
+        return Err("A calibration sheet needs at least one dot size, offset and spacing".into());
+    }
+    let columns = spec.dot_sizes.len();
+    let rows = spec.spacings.len() * spec.offsets.len();
+    if columns * rows > codec.mns_length {
+        return Err(format!("{} swatches need more distinct sections than the codec has ({})", columns * rows, codec.mns_length).into());
+    }
+
+    let grid = CellGrid::new(spec);
+    let mut swatches = Vec::with_capacity(columns * rows);
+    for (row, (&grid_spacing, &offset_from_origin)) in spec.spacings.iter().flat_map(|s| spec.offsets.iter().map(move |o| (s, o))).enumerate() {
+        if grid_spacing <= 0.0 || offset_from_origin <= 0.0 || offset_from_origin >= grid_spacing / 2.0 {
+            return Err(format!("Offset {} needs to be positive and below half the spacing {}", offset_from_origin, grid_spacing).into());
+        }
+        for (column, &dot_size) in spec.dot_sizes.iter().enumerate() {
+            if dot_size <= 0.0 {
+                return Err(format!("Dot size {} needs to be positive", dot_size).into());
+            }
+            // Dots reach past the outer grid nodes on both sides by their offset and radius
+            let reach = 2.0 * (offset_from_origin + dot_size) as f64;
+            let dots = |extent: f64| ((extent - reach) / grid_spacing as f64).floor().max(0.0) as usize + 1;
+            let (left, top, width, height) = grid.area(row * columns + column);
+            let size = (dots(width), dots(height));
+            if size.0.min(size.1) < codec.mns_order() {
+                return Err(format!(
+                    "A swatch with spacing {} only fits {}x{} dots, the codec needs {}x{}; vary fewer settings",
+                    grid_spacing,
+                    size.0,
+                    size.1,
+                    codec.mns_order(),
+                    codec.mns_order()
+                )
+                .into());
+            }
+
+            swatches.push(CalibrationSwatch {
+                section: ((section.0 + (row * columns + column) as i32).rem_euclid(codec.mns_length as i32), section.1),
+                dot_size,
+                offset_from_origin,
+                grid_spacing,
+                size,
+                centre: (left + width / 2.0, top - height / 2.0),
+            });
+        }
+    }
+    Ok(swatches)
+}
+
+/// Writes the calibration sheet of `spec` to `output/{filename}` and returns its swatches, which
+/// are also recorded in the PDF's pattern metadata.
+pub fn gen_calibration_pdf(codec: &AnotoCodec, section: (i32, i32), spec: &CalibrationSpec, filename: &str) -> Result<Vec<CalibrationSwatch>, AnotoError> {
+    let swatches = calibration_swatches(codec, section, spec)?;
+    let (_, page_height) = page_size();
+
+    let mut doc = Document::new();
+    doc.set_title("Anoto calibration sheet");
+    doc.set_author("Rust");
+    doc.set_compress(true);
+    doc.add_font_from_bytes(CAPTION_FONT, crate::fonts::JB_MONO_TTF.to_vec())?;
+    let font = || Font::Custom(CAPTION_FONT.to_string());
+
+    let mut page = Page::a4();
+    let mut operations = String::new();
+    for swatch in &swatches {
+        let config = swatch.config(&spec.config);
+        let tiles = codec.tiles(swatch.section, (0, 0), swatch.size, swatch.size)?;
+        let layout = DotLayout::centred_at(swatch.size, &config, swatch.centre);
+        let paths = dots_by_direction(tiles, &layout, &JobProgress::new(), |out, x, y| {
+            let _ = writeln!(out, "{x:.2} {y:.2} m {x:.2} {y:.2} l");
+        })?;
+        operations.push_str(&dot_operations(&paths, &config));
+    }
+    page.graphics().add_command(operations.trim_end_matches('\n'));
+
+    let header = format!("Calibration sheet  codec {}  dot size / offset / pitch in pt", Some(codec.name()).filter(|n| !n.is_empty()).unwrap_or("custom"));
+    page.text().set_font(font(), HEADER_FONT_SIZE).at(MARGIN, page_height - MARGIN - HEADER_FONT_SIZE).write(&header)?;
+
+    let grid = CellGrid::new(spec);
+    for (index, swatch) in swatches.iter().enumerate() {
+        let (left, top, width, height) = grid.area(index);
+        let caption = swatch.caption();
+        // Shrink the caption to its cell on sheets with many columns
+        let longest = caption.iter().map(|line| line.chars().count()).max().unwrap_or(1) as f64;
+        let size = CAPTION_FONT_SIZE.min(width / (longest * GLYPH_ADVANCE));
+        for (line, text) in caption.iter().enumerate() {
+            page.text().set_font(font(), size).at(left, top - height - (line as f64 + 1.0) * CAPTION_LINE_HEIGHT).write(text)?;
+        }
+    }
+    doc.add_page(page);
+
+    let metadata = PatternMetadata {
+        pattern: PatternInfo { codec: Some(codec.name().to_string()).filter(|n| !n.is_empty()), section: None, origins: Vec::new(), address: None },
+        page_sizes: Vec::new(),
+        config: spec.config.clone(),
+        swatches: swatches.clone(),
+    };
+    std::fs::write(output_path(filename)?, document_bytes(doc, &metadata)?)?;
+    Ok(swatches)
+}
@@ -0,0 +1,62 @@
+use oxidize_pdf::Color;
+use std::fmt::Write;
+
+use super::gen_pdf::{AnotoDot, DotLayout, PdfConfig, dots_by_direction, matrix_tile, output_path, page_size, parse_hex_color};
+use crate::codec::PatternTile;
+use crate::progress::JobProgress;
+use crate::error::AnotoError;
+
+/// EPS with the same page and dot geometry as `gen_pdf_from_matrix_data`, saved to `output/`.
+pub fn gen_eps_from_matrix_data(bitmatrix: &ndarray::Array3<i32>, filename: &str, config: &PdfConfig) -> std::result::Result<(), AnotoError> {
+    let (height, width, _) = bitmatrix.dim();
+    gen_eps_from_tiles(std::iter::once(matrix_tile(bitmatrix)), (width, height), filename, config)
+}
+
+/// Writes the EPS from tiles (`AnotoCodec::tiles`) covering a `(width, height)` area.
+pub fn gen_eps_from_tiles(tiles: impl IntoIterator<Item = PatternTile>, size: (usize, usize), filename: &str, config: &PdfConfig) -> std::result::Result<(), AnotoError> {
+    std::fs::write(output_path(filename)?, eps_document(tiles, size, config)?)?;
+    Ok(())
+}
+
+/// Same document as `gen_eps_from_matrix_data`, returned as bytes instead of written to `output/`.
+pub fn gen_eps_bytes(bitmatrix: &ndarray::Array3<i32>, config: &PdfConfig) -> std::result::Result<Vec<u8>, AnotoError> {
+    let (height, width, _) = bitmatrix.dim();
+    Ok(eps_document(std::iter::once(matrix_tile(bitmatrix)), (width, height), config)?.into_bytes())
+}
+
+// PostScript shares the PDF coordinate system, so the dot centres are used as they are.
+// `D` fills one dot; each direction sets its colour once.
+fn eps_document(tiles: impl IntoIterator<Item = PatternTile>, size: (usize, usize), config: &PdfConfig) -> std::result::Result<String, AnotoError> {
+    let (page_width, page_height) = page_size();
+    let groups = dots_by_direction(tiles, &DotLayout::new(size, config), &JobProgress::new(), |out, x, y| {
+        let _ = writeln!(out, "{x:.2} {y:.2} D");
+    })?;
+
+    let mut eps = String::new();
+    writeln!(eps, "%!PS-Adobe-3.0 EPSF-3.0")?;
+    writeln!(eps, "%%BoundingBox: 0 0 {} {}", page_width.ceil(), page_height.ceil())?;
+    writeln!(eps, "%%HiResBoundingBox: 0 0 {:.3} {:.3}", page_width, page_height)?;
+    writeln!(eps, "%%Title: Anoto PDF")?;
+    writeln!(eps, "%%Creator: Rust")?;
+    writeln!(eps, "%%EndComments")?;
+    writeln!(eps, "/D {{ newpath {:.2} 0 360 arc fill }} bind def", config.dot_size)?;
+    for (dot, dots) in AnotoDot::ALL.into_iter().zip(&groups) {
+        if dots.is_empty() {
+            continue;
+        }
+        writeln!(eps, "% {} dots", dot.name())?;
+        writeln!(eps, "{}", eps_color_operator(&parse_hex_color(dot.color(config))))?;
+        eps.push_str(dots);
+    }
+    writeln!(eps, "showpage")?;
+    writeln!(eps, "%%EOF")?;
+    Ok(eps)
+}
+
+fn eps_color_operator(color: &Color) -> String {
+    match *color {
+        Color::Rgb(r, g, b) => format!("{r:.3} {g:.3} {b:.3} setrgbcolor"),
+        Color::Gray(g) => format!("{g:.3} setgray"),
+        Color::Cmyk(c, m, y, k) => format!("{c:.3} {m:.3} {y:.3} {k:.3} setcmykcolor"),
+    }
+}
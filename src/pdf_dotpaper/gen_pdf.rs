@@ -1,6 +1,15 @@
 use oxidize_pdf::{Document, Page, Color};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
 
-#[derive(Clone, Debug)]
+use super::metadata::{PatternInfo, PatternMetadata, embed_pattern_metadata};
+use crate::codec::PatternTile;
+use crate::progress::{Cancelled, JobProgress};
+use crate::error::AnotoError;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PdfConfig {
     pub dpi: f32,
     pub color_up: String,
@@ -27,7 +36,14 @@ impl Default for PdfConfig {
     }
 }
 
-fn parse_hex_color(hex: &str) -> Color {
+impl PdfConfig {
+    /// Printed distance between neighbouring grid nodes; `grid_spacing` is in PDF points.
+    pub fn dot_pitch_mm(&self) -> f64 {
+        self.grid_spacing as f64 * 25.4 / 72.0
+    }
+}
+
+pub(crate) fn parse_hex_color(hex: &str) -> Color {
     let hex = hex.trim_start_matches('#');
     if hex.len() == 6 {
         let r = u8::from_str_radix(&hex[0..2], 16).unwrap_or(0) as f64 / 255.0;
@@ -40,95 +56,301 @@ fn parse_hex_color(hex: &str) -> Color {
 }
 
 #[derive(Clone, Copy)]
-enum AnotoDot {
+pub(crate) enum AnotoDot {
     Up,
     Down,
     Left,
     Right,
 }
 
-pub fn gen_pdf_from_matrix_data(bitmatrix: &ndarray::Array3<i32>, filename: &str, config: &PdfConfig) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let mut doc = Document::new();
-    doc.set_title("Anoto PDF");
-    doc.set_author("Rust");
+impl AnotoDot {
+    pub(crate) const ALL: [AnotoDot; 4] = [AnotoDot::Up, AnotoDot::Down, AnotoDot::Left, AnotoDot::Right];
+
+    pub(crate) fn from_bits(x_bit: i8, y_bit: i8) -> Self {
+        match x_bit + (y_bit << 1) {
+            0 => AnotoDot::Up,
+            1 => AnotoDot::Left,
+            2 => AnotoDot::Right,
+            3 => AnotoDot::Down,
+            _ => AnotoDot::Up,
+        }
+    }
+
+    /// (x bit, y bit) of the direction, the inverse of `from_bits`
+    pub(crate) fn bits(self) -> (i8, i8) {
+        match self {
+            AnotoDot::Up => (0, 0),
+            AnotoDot::Left => (1, 0),
+            AnotoDot::Right => (0, 1),
+            AnotoDot::Down => (1, 1),
+        }
+    }
+
+    // Displacement of the dot from its grid node, in points
+    fn offset(self, offset: f64) -> (f64, f64) {
+        match self {
+            AnotoDot::Up => (0.0, offset),
+            AnotoDot::Down => (0.0, -offset),
+            AnotoDot::Left => (-offset, 0.0),
+            AnotoDot::Right => (offset, 0.0),
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            AnotoDot::Up => "up",
+            AnotoDot::Down => "down",
+            AnotoDot::Left => "left",
+            AnotoDot::Right => "right",
+        }
+    }
 
-    let mut page = oxidize_pdf::Page::a4();
-
-    let height = bitmatrix.dim().0;
-    let width = bitmatrix.dim().1;
-    
-    let page_width = page.width();
-    let page_height = page.height();
-    
-    let grid_width = (width as f64 - 1.0) * config.grid_spacing as f64;
-    let grid_height = (height as f64 - 1.0) * config.grid_spacing as f64;
-    
-    let margin_x = (page_width as f64 - grid_width) / 2.0;
-    let margin_y = (page_height as f64 - grid_height) / 2.0;
-
-    for y in 0..height {
-        for x in 0..width {
-            let x_pos = margin_x + x as f64 * config.grid_spacing as f64;
-            let y_pos = margin_y + y as f64 * config.grid_spacing as f64;
-            let x_bit = bitmatrix[[y, x, 0]];
-            let y_bit = bitmatrix[[y, x, 1]];
-            let dot_type = x_bit + (y_bit << 1);
-            let direction = match dot_type {
-                0 => AnotoDot::Up,
-                1 => AnotoDot::Left,
-                2 => AnotoDot::Right,
-                3 => AnotoDot::Down,
-                _ => AnotoDot::Up,
-            };
-            draw_anoto_dot(&mut page, x_pos, y_pos, direction, config);
+    pub(crate) fn color(self, config: &PdfConfig) -> &str {
+        match self {
+            AnotoDot::Up => &config.color_up,
+            AnotoDot::Down => &config.color_down,
+            AnotoDot::Left => &config.color_left,
+            AnotoDot::Right => &config.color_right,
         }
     }
+}
+
+/// PDF of a matrix whose origin is unknown; its metadata records only the page geometry.
+pub fn gen_pdf_from_matrix_data(bitmatrix: &ndarray::Array3<i32>, filename: &str, config: &PdfConfig) -> std::result::Result<(), AnotoError> {
+    let (height, width, _) = bitmatrix.dim();
+    gen_pdf_from_tiles(std::iter::once(matrix_tile(bitmatrix)), (width, height), filename, config, &PatternInfo::default())
+}
+
+/// Writes the page straight from tiles (`AnotoCodec::tiles`) covering a `(width, height)` area,
+/// so the full matrix is never held in memory. `pattern` goes into the PDF's XMP metadata.
+pub fn gen_pdf_from_tiles(
+    tiles: impl IntoIterator<Item = PatternTile>,
+    size: (usize, usize),
+    filename: &str,
+    config: &PdfConfig,
+    pattern: &PatternInfo,
+) -> std::result::Result<(), AnotoError> {
+    gen_pdf_from_tiles_with_progress(tiles, size, filename, config, pattern, &JobProgress::new())
+}
 
-    doc.add_page(page);
-    let output_dir = std::env::current_dir().unwrap().join("output");
+/// `gen_pdf_from_tiles` reporting the rows drawn to `progress`, stopping with `Cancelled` when asked to.
+pub fn gen_pdf_from_tiles_with_progress(
+    tiles: impl IntoIterator<Item = PatternTile>,
+    size: (usize, usize),
+    filename: &str,
+    config: &PdfConfig,
+    pattern: &PatternInfo,
+    progress: &JobProgress,
+) -> std::result::Result<(), AnotoError> {
+    progress.start("Drawing PDF", size.1);
+    let operations = page_operations(tiles, size, config, progress)?;
+    save_document(build_pdf_document(vec![operations]), filename, &pattern_metadata(pattern, vec![size], config))
+}
+
+/// One A4 page per matrix, in order. The pages are drawn in parallel; `pattern.origins` lists
+/// each page's origin.
+pub fn gen_pdf_from_pages(pages: &[ndarray::Array3<i32>], filename: &str, config: &PdfConfig, pattern: &PatternInfo) -> std::result::Result<(), AnotoError> {
+    let operations = pages
+        .par_iter()
+        .map(|bitmatrix| {
+            let (height, width, _) = bitmatrix.dim();
+            page_operations(std::iter::once(matrix_tile(bitmatrix)), (width, height), config, &JobProgress::new())
+        })
+        .collect::<Result<Vec<_>, Cancelled>>()?;
+    let sizes = pages.iter().map(|p| (p.dim().1, p.dim().0)).collect();
+    save_document(build_pdf_document(operations), filename, &pattern_metadata(pattern, sizes, config))
+}
+
+/// Same document as `gen_pdf_from_tiles` for a whole matrix, returned as bytes instead of written to `output/`.
+pub fn gen_pdf_bytes(bitmatrix: &ndarray::Array3<i32>, config: &PdfConfig, pattern: &PatternInfo) -> std::result::Result<Vec<u8>, AnotoError> {
+    let (height, width, _) = bitmatrix.dim();
+    let operations = page_operations(std::iter::once(matrix_tile(bitmatrix)), (width, height), config, &JobProgress::new())?;
+    document_bytes(build_pdf_document(vec![operations]), &pattern_metadata(pattern, vec![(width, height)], config))
+}
+
+pub(crate) fn matrix_tile(bitmatrix: &ndarray::Array3<i32>) -> PatternTile {
+    PatternTile::whole(bitmatrix.mapv(|b| b as i8))
+}
+
+/// `output/{filename}`, creating the directory when missing.
+pub(crate) fn output_path(filename: &str) -> std::io::Result<std::path::PathBuf> {
+    let output_dir = std::env::current_dir()?.join("output");
     if !output_dir.exists() {
         std::fs::create_dir(&output_dir)?;
     }
-    let path = output_dir.join(filename);
-    doc.save(path)?;
+    Ok(output_dir.join(filename))
+}
+
+fn save_document(doc: Document, filename: &str, metadata: &PatternMetadata) -> std::result::Result<(), AnotoError> {
+    std::fs::write(output_path(filename)?, document_bytes(doc, metadata)?)?;
     Ok(())
 }
 
-fn draw_anoto_dot(page: &mut Page, x: f64, y: f64, direction: AnotoDot, config: &PdfConfig) {
-
-    let radius = config.dot_size as f64;
-    let offset = config.offset_from_origin as f64;
-
-    match direction {
-        AnotoDot::Up => {
-            let y_up = y + offset;
-            page.graphics()
-                .set_fill_color(parse_hex_color(&config.color_up))
-                .circle(x, y_up, radius)
-                .fill();
-        },
-        AnotoDot::Down => {
-            let y_down = y - offset;
-            page.graphics()
-                .set_fill_color(parse_hex_color(&config.color_down))
-                .circle(x, y_down, radius)
-                .fill();
-        },
-        AnotoDot::Left => {
-            let x_left = x - offset;
-            page.graphics()
-                .set_fill_color(parse_hex_color(&config.color_left))
-                .circle(x_left, y, radius)
-                .fill();
-        },
-        AnotoDot::Right => {
-            let x_right = x + offset;
-            page.graphics()
-                .set_fill_color(parse_hex_color(&config.color_right))
-                .circle(x_right, y, radius)
-                .fill();
-        },
+pub(crate) fn document_bytes(mut doc: Document, metadata: &PatternMetadata) -> std::result::Result<Vec<u8>, AnotoError> {
+    let pdf = doc.to_bytes()?;
+    embed_pattern_metadata(pdf, &doc, metadata)
+}
+
+fn pattern_metadata(pattern: &PatternInfo, page_sizes: Vec<(usize, usize)>, config: &PdfConfig) -> PatternMetadata {
+    PatternMetadata { pattern: pattern.clone(), page_sizes, config: config.clone(), swatches: Vec::new() }
+}
+
+/// (width, height) of the A4 page every writer centres the pattern on, in points.
+pub(crate) fn page_size() -> (f64, f64) {
+    let page = Page::a4();
+    (page.width(), page.height())
+}
+
+fn build_pdf_document(pages: Vec<String>) -> Document {
+    let mut doc = Document::new();
+    doc.set_title("Anoto PDF");
+    doc.set_author("Rust");
+    doc.set_compress(true);
+
+    for operations in pages {
+        let mut page = Page::a4();
+        if !operations.is_empty() {
+            // add_command appends its own newline
+            page.graphics().add_command(operations.trim_end_matches('\n'));
+        }
+        doc.add_page(page);
+    }
+    doc
+}
+
+// Content stream of one centred A4 page.
+fn page_operations(tiles: impl IntoIterator<Item = PatternTile>, size: (usize, usize), config: &PdfConfig, progress: &JobProgress) -> Result<String, Cancelled> {
+    let paths = dots_by_direction(tiles, &DotLayout::new(size, config), progress, |out, x, y| {
+        let _ = writeln!(out, "{x:.2} {y:.2} m {x:.2} {y:.2} l");
+    })?;
+    Ok(dot_operations(&paths, config))
+}
+
+// Every dot is a zero-length subpath stroked with a round cap as wide as the dot, which paints the
+// same filled circle; dots share one path per direction, so a page sets each colour once.
+pub(crate) fn dot_operations(paths: &[String; 4], config: &PdfConfig) -> String {
+    let mut operations = String::new();
+    if paths.iter().all(|p| p.is_empty()) {
+        return operations;
+    }
+    let _ = writeln!(operations, "q\n1 J\n{:.2} w", 2.0 * config.dot_size as f64);
+    for (dot, path) in AnotoDot::ALL.into_iter().zip(paths) {
+        if path.is_empty() {
+            continue;
+        }
+        let _ = writeln!(operations, "{}", stroke_color_operator(&parse_hex_color(dot.color(config))));
+        operations.push_str(path);
+        operations.push_str("S\n");
+    }
+    operations.push_str("Q\n");
+    operations
+}
+
+/// Where the dots of a `(width, height)` pattern land, in PDF points (origin bottom-left).
+#[derive(Clone, Copy)]
+pub(crate) struct DotLayout {
+    margin_x: f64,
+    margin_y: f64,
+    spacing: f64,
+    offset: f64,
+}
 
+impl DotLayout {
+    /// Pattern centred on the A4 page.
+    pub(crate) fn new(size: (usize, usize), config: &PdfConfig) -> Self {
+        let (page_width, page_height) = page_size();
+        Self::centred_at(size, config, (page_width / 2.0, page_height / 2.0))
     }
 
+    /// Pattern whose grid is centred on `centre`.
+    pub(crate) fn centred_at(size: (usize, usize), config: &PdfConfig, centre: (f64, f64)) -> Self {
+        let (width, height) = size;
+        let spacing = config.grid_spacing as f64;
+
+        let grid_width = (width as f64 - 1.0) * spacing;
+        let grid_height = (height as f64 - 1.0) * spacing;
+
+        DotLayout {
+            margin_x: centre.0 - grid_width / 2.0,
+            margin_y: centre.1 - grid_height / 2.0,
+            spacing,
+            offset: config.offset_from_origin as f64,
+        }
+    }
+
+    /// Page position of grid node `(row, col)`; fractional indices fall between nodes.
+    pub(crate) fn position(&self, row: f64, col: f64) -> (f64, f64) {
+        (self.margin_x + col * self.spacing, self.margin_y + row * self.spacing)
+    }
+
+    /// Direction and centre of the dot at pattern `(row, col)` holding the x and y bits.
+    pub(crate) fn dot(&self, row: usize, col: usize, x_bit: i8, y_bit: i8) -> (AnotoDot, f64, f64) {
+        let dot = AnotoDot::from_bits(x_bit, y_bit);
+        let (dx, dy) = dot.offset(self.offset);
+        (dot, self.margin_x + col as f64 * self.spacing + dx, self.margin_y + row as f64 * self.spacing + dy)
+    }
+
+    /// Inverse of `dot`: the grid node `(row, col)` nearest to a dot centre and the direction the dot
+    /// is displaced in. `None` left or below the grid, or when the dot sits too close to the node to tell.
+    pub(crate) fn node(&self, x: f64, y: f64) -> Option<(usize, usize, AnotoDot)> {
+        let col = ((x - self.margin_x) / self.spacing).round();
+        let row = ((y - self.margin_y) / self.spacing).round();
+        if col < 0.0 || row < 0.0 {
+            return None;
+        }
+        let dx = x - (self.margin_x + col * self.spacing);
+        let dy = y - (self.margin_y + row * self.spacing);
+        if dx.abs().max(dy.abs()) < self.offset / 2.0 {
+            return None;
+        }
+        let dot = match dx.abs() > dy.abs() {
+            true if dx > 0.0 => AnotoDot::Right,
+            true => AnotoDot::Left,
+            false if dy > 0.0 => AnotoDot::Up,
+            false => AnotoDot::Down,
+        };
+        Some((row as usize, col as usize, dot))
+    }
+}
+
+/// Runs `write_dot(out, x, y)` for every dot centre of a pattern placed by `layout` (PDF points,
+/// origin bottom-left), collecting the output per direction in `AnotoDot::ALL` order. Rows of each
+/// tile are formatted in parallel and joined in order; each row counts as one unit of `progress`.
+pub(crate) fn dots_by_direction(
+    tiles: impl IntoIterator<Item = PatternTile>,
+    layout: &DotLayout,
+    progress: &JobProgress,
+    write_dot: impl Fn(&mut String, f64, f64) + Sync,
+) -> Result<[String; 4], Cancelled> {
+    let mut paths: [String; 4] = Default::default();
+    for tile in tiles {
+        progress.check()?;
+        let rows = (0..tile.bits.dim().0)
+            .into_par_iter()
+            .map(|r| {
+                progress.check()?;
+                let mut row_paths: [String; 4] = Default::default();
+                for c in 0..tile.bits.dim().1 {
+                    let (dot, x_pos, y_pos) = layout.dot(tile.row + r, tile.col + c, tile.bits[[r, c, 0]], tile.bits[[r, c, 1]]);
+                    write_dot(&mut row_paths[dot as usize], x_pos, y_pos);
+                }
+                progress.advance(1);
+                Ok(row_paths)
+            })
+            .collect::<Result<Vec<_>, Cancelled>>()?;
+        for row_paths in rows {
+            for (path, row_path) in paths.iter_mut().zip(row_paths) {
+                path.push_str(&row_path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+fn stroke_color_operator(color: &Color) -> String {
+    match *color {
+        Color::Rgb(r, g, b) => format!("{r:.3} {g:.3} {b:.3} RG"),
+        Color::Gray(g) => format!("{g:.3} G"),
+        Color::Cmyk(c, m, y, k) => format!("{c:.3} {m:.3} {y:.3} {k:.3} K"),
+    }
 }
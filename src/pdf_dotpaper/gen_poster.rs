@@ -0,0 +1,269 @@
+use oxidize_pdf::text::Font;
+use oxidize_pdf::{Document, Page};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use super::gen_pdf::{DotLayout, PdfConfig, document_bytes, dot_operations, dots_by_direction, output_path};
+use super::metadata::{PatternInfo, PatternMetadata};
+use crate::codec::AnotoCodec;
+use crate::progress::JobProgress;
+use crate::error::AnotoError;
+
+const MM_TO_PT: f64 = 72.0 / 25.4;
+const MARK_GAP: f64 = 3.0;
+const MARK_LENGTH: f64 = 12.0;
+const MARK_WIDTH: f64 = 0.5;
+const LABEL_FONT_SIZE: f64 = 7.0;
+// JetBrains Mono advances every glyph by 0.6 em
+const GLYPH_ADVANCE: f64 = 0.6;
+const LABEL_FONT: &str = "JetBrainsMonoNL";
+
+/// One continuous pattern of `area_mm` split over sheets of `sheet_mm`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PosterSpec {
+    /// (width, height) of the assembled poster in mm
+    pub area_mm: (f64, f64),
+    /// (width, height) of one printed sheet in mm
+    pub sheet_mm: (f64, f64),
+    /// Border kept free of pattern on every sheet edge, in mm; the marks and labels sit in it
+    pub margin_mm: f64,
+    /// Pattern columns and rows each sheet shares with its right and upper neighbour
+    pub overlap: usize,
+    pub config: PdfConfig,
+}
+
+impl Default for PosterSpec {
+    fn default() -> Self {
+        Self {
+            area_mm: (841.0, 594.0),
+            sheet_mm: (210.0, 297.0),
+            margin_mm: 10.0,
+            overlap: 2,
+            config: PdfConfig::default(),
+        }
+    }
+}
+
+/// (width, height) in mm of a named paper size (a3, a4, a5, letter, legal, tabloid) or of `WxH` in mm.
+pub fn paper_size_mm(name: &str) -> Option<(f64, f64)> {
+    match name.to_lowercase().as_str() {
+        "a3" => Some((297.0, 420.0)),
+        "a4" => Some((210.0, 297.0)),
+        "a5" => Some((148.0, 210.0)),
+        "letter" => Some((215.9, 279.4)),
+        "legal" => Some((215.9, 355.6)),
+        "tabloid" => Some((279.4, 431.8)),
+        size => {
+            let (width, height) = size.split_once('x')?;
+            Some((width.trim().parse().ok()?, height.trim().parse().ok()?)).filter(|&(w, h)| w > 0.0 && h > 0.0)
+        }
+    }
+}
+
+/// One sheet of a poster. Columns count from the left, rows from the bottom.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PosterSheet {
+    /// 0-based page of the PDF
+    pub index: usize,
+    pub column: usize,
+    pub row: usize,
+    /// Pattern position (x, y) of the sheet's first dot
+    pub origin: (i64, i64),
+    /// (width, height) in dots
+    pub size: (usize, usize),
+}
+
+impl std::fmt::Display for PosterSheet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sheet {}  column {} row {}  origin ({}, {})  {}x{} dots",
+            self.index + 1,
+            self.column + 1,
+            self.row + 1,
+            self.origin.0,
+            self.origin.1,
+            self.size.0,
+            self.size.1
+        )
+    }
+}
+
+/// How a poster is split into sheets, bottom row first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PosterLayout {
+    /// (width, height) of the whole poster in dots
+    pub size: (usize, usize),
+    pub columns: usize,
+    pub rows: usize,
+    /// Dots a sheet advances over its left or lower neighbour
+    pub step: (usize, usize),
+    pub sheets: Vec<PosterSheet>,
+}
+
+/// Splits the poster of `spec`, whose first dot is pattern position `origin`, into sheets. Each full
+/// sheet holds as many dots as fit inside its margins and repeats `overlap` columns and rows of the
+/// next sheet; the last column and row take what is left.
+pub fn poster_layout(spec: &PosterSpec, origin: (i64, i64)) -> Result<PosterLayout, AnotoError> {
+    let config = &spec.config;
+    let spacing = config.grid_spacing as f64;
+    if spacing <= 0.0 {
+        return Err("The grid spacing needs to be positive".into());
+    }
+    let pitch = config.dot_pitch_mm();
+    let dots_across = |mm: f64| (mm / pitch).floor().max(0.0) as usize + 1;
+    let size = (dots_across(spec.area_mm.0), dots_across(spec.area_mm.1));
+
+    // Dots reach past the outer grid nodes on both sides by their offset and radius
+    let reach = 2.0 * (config.offset_from_origin + config.dot_size) as f64;
+    let per_sheet = |mm: f64| {
+        let extent = (mm - 2.0 * spec.margin_mm) * MM_TO_PT - reach;
+        (extent >= 0.0).then(|| (extent / spacing).floor() as usize + 1)
+    };
+    let sheet = match (per_sheet(spec.sheet_mm.0), per_sheet(spec.sheet_mm.1)) {
+        (Some(width), Some(height)) if width.min(height) > spec.overlap => (width, height),
+        _ => return Err(format!("Sheets of {} x {} mm hold too few dots for an overlap of {}", spec.sheet_mm.0, spec.sheet_mm.1, spec.overlap).into()),
+    };
+    let step = (sheet.0 - spec.overlap, sheet.1 - spec.overlap);
+    let count = |total: usize, per_sheet: usize, step: usize| if total <= per_sheet { 1 } else { (total - per_sheet).div_ceil(step) + 1 };
+    let (columns, rows) = (count(size.0, sheet.0, step.0), count(size.1, sheet.1, step.1));
+
+    let mut sheets = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (column * step.0, row * step.1);
+            sheets.push(PosterSheet {
+                index: sheets.len(),
+                column,
+                row,
+                origin: (origin.0 + x as i64, origin.1 + y as i64),
+                size: (sheet.0.min(size.0 - x), sheet.1.min(size.1 - y)),
+            });
+        }
+    }
+    Ok(PosterLayout { size, columns, rows, step, sheets })
+}
+
+/// Writes the poster of `spec` in `section` to `output/{filename}`, one sheet per page. Every sheet
+/// carries its part of one continuous pattern starting at `origin`, crop marks where it is trimmed,
+/// alignment marks where its neighbours' trimmed edges go and a label naming its place. The sheet
+/// origins are recorded in the pattern metadata, so `verify_pdf_from_metadata` checks every page.
+pub fn gen_poster_pdf(
+    codec: &AnotoCodec,
+    section: (i32, i32),
+    origin: (i64, i64),
+    spec: &PosterSpec,
+    filename: &str,
+) -> Result<PosterLayout, AnotoError> {
+    let poster = poster_layout(spec, origin)?;
+    codec.check_span(origin.0, poster.size.0)?;
+    codec.check_span(origin.1, poster.size.1)?;
+    let config = &spec.config;
+    let (page_width, page_height) = (spec.sheet_mm.0 * MM_TO_PT, spec.sheet_mm.1 * MM_TO_PT);
+
+    let mut doc = Document::new();
+    doc.set_title("Anoto poster");
+    doc.set_author("Rust");
+    doc.set_compress(true);
+    doc.add_font_from_bytes(LABEL_FONT, crate::fonts::JB_MONO_TTF.to_vec())?;
+
+    for sheet in &poster.sheets {
+        let tiles = codec.tiles(section, sheet.origin, sheet.size, sheet.size)?;
+        let layout = DotLayout::centred_at(sheet.size, config, (page_width / 2.0, page_height / 2.0));
+        let paths = dots_by_direction(tiles, &layout, &JobProgress::new(), |out, x, y| {
+            let _ = writeln!(out, "{x:.2} {y:.2} m {x:.2} {y:.2} l");
+        })?;
+
+        let mut page = Page::new(page_width, page_height);
+        let operations = dot_operations(&paths, config) + &mark_operations(sheet, &poster, &layout);
+        page.graphics().add_command(operations.trim_end_matches('\n'));
+
+        let label = format!(
+            "Sheet {} of {}  column {}/{}  row {}/{} from bottom  origin ({}, {})  section ({}, {})",
+            sheet.index + 1,
+            poster.sheets.len(),
+            sheet.column + 1,
+            poster.columns,
+            sheet.row + 1,
+            poster.rows,
+            sheet.origin.0,
+            sheet.origin.1,
+            section.0,
+            section.1
+        );
+        let hint = "Trim at the plain marks, then lay the trimmed edges on the + marks of the sheets left and below";
+        let margin = spec.margin_mm * MM_TO_PT;
+        for (text, baseline) in [(label.as_str(), page_height - margin / 2.0), (hint, margin / 2.0)] {
+            // Shrink the line to the sheet on narrow paper
+            let size = LABEL_FONT_SIZE.min((page_width - 2.0 * margin) / (text.chars().count() as f64 * GLYPH_ADVANCE));
+            let width = text.chars().count() as f64 * GLYPH_ADVANCE * size;
+            page.text()
+                .set_font(Font::Custom(LABEL_FONT.to_string()), size)
+                .at((page_width - width) / 2.0, baseline - size / 3.0)
+                .write(text)?;
+        }
+        doc.add_page(page);
+    }
+
+    let metadata = PatternMetadata {
+        pattern: PatternInfo {
+            codec: Some(codec.name().to_string()).filter(|name| !name.is_empty()),
+            section: Some(section),
+            origins: poster.sheets.iter().map(|sheet| sheet.origin).collect(),
+            address: None,
+        },
+        page_sizes: poster.sheets.iter().map(|sheet| sheet.size).collect(),
+        config: config.clone(),
+        swatches: Vec::new(),
+    };
+    std::fs::write(output_path(filename)?, document_bytes(doc, &metadata)?)?;
+    Ok(poster)
+}
+
+// Crop marks continue the left and bottom edges a sheet is trimmed along, half a spacing outside its
+// first column and row. Alignment marks (crossed) sit where the right and upper neighbours' trimmed
+// edges go, so the shared columns and rows land on top of each other. Both stay outside the pattern
+// and each is one subpath longer than a dot, so readers do not take them for dots.
+fn mark_operations(sheet: &PosterSheet, poster: &PosterLayout, layout: &DotLayout) -> String {
+    let (width, height) = (sheet.size.0 as f64, sheet.size.1 as f64);
+    let (left, bottom) = layout.position(-0.5, -0.5);
+    let (right, top) = layout.position(height - 0.5, width - 0.5);
+
+    // Ticks as (along, across) pairs, away from the pattern edge at `edge`; `flip` swaps the axes
+    let mut marks = String::new();
+    let mut tick = |position: f64, edge: f64, direction: f64, crossed: bool, flip: bool| {
+        let start = edge + direction * MARK_GAP;
+        let middle = start + direction * MARK_LENGTH / 2.0;
+        let mut points = vec![(position, start), (position, middle)];
+        if crossed {
+            points.extend([(position - MARK_LENGTH / 4.0, middle), (position + MARK_LENGTH / 4.0, middle), (position, middle)]);
+        }
+        points.push((position, start + direction * MARK_LENGTH));
+        for (i, (a, b)) in points.into_iter().enumerate() {
+            let (x, y) = if flip { (b, a) } else { (a, b) };
+            let _ = writeln!(marks, "{x:.2} {y:.2} {}", if i == 0 { "m" } else { "l" });
+        }
+    };
+    let vertical = [(bottom, -1.0), (top, 1.0)];
+    let horizontal = [(left, -1.0), (right, 1.0)];
+    if sheet.column > 0 {
+        vertical.iter().for_each(|&(edge, direction)| tick(left, edge, direction, false, false));
+    }
+    if sheet.column + 1 < poster.columns {
+        let x = layout.position(0.0, poster.step.0 as f64 - 0.5).0;
+        vertical.iter().for_each(|&(edge, direction)| tick(x, edge, direction, true, false));
+    }
+    if sheet.row > 0 {
+        horizontal.iter().for_each(|&(edge, direction)| tick(bottom, edge, direction, false, true));
+    }
+    if sheet.row + 1 < poster.rows {
+        let y = layout.position(poster.step.1 as f64 - 0.5, 0.0).1;
+        horizontal.iter().for_each(|&(edge, direction)| tick(y, edge, direction, true, true));
+    }
+
+    if marks.is_empty() {
+        return marks;
+    }
+    format!("q\n0 G\n0 J\n{:.2} w\n{}S\nQ\n", MARK_WIDTH, marks)
+}
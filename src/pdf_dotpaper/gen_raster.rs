@@ -0,0 +1,283 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use tiff::encoder::compression::{CompressionAlgorithm, Lzw};
+use tiff::encoder::{Rational, TiffEncoder};
+use tiff::tags::{CompressionMethod, PhotometricInterpretation, ResolutionUnit, Tag};
+
+use super::gen_pdf::{DotLayout, PdfConfig, matrix_tile, output_path, page_size};
+use crate::codec::PatternTile;
+use crate::progress::JobProgress;
+use crate::error::AnotoError;
+
+// Rows rendered per band and written per TIFF strip; each worker holds one band of coverage
+const RASTER_BAND_ROWS: usize = 64;
+/// Highest DPI rendered; an A4 page at 4800 DPI is already about 40000x56000 pixels
+pub const MAX_RASTER_DPI: u32 = 4800;
+
+/// Print raster settings. Dots are black on white at the PDF's page position and size.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RasterConfig {
+    pub dpi: u32,
+    /// 1 bit per pixel instead of 8-bit grayscale
+    pub bilevel: bool,
+    /// Samples per pixel edge on dot outlines; 1 turns anti-aliasing off
+    pub antialias: u32,
+}
+
+impl Default for RasterConfig {
+    fn default() -> Self {
+        Self { dpi: 1200, bilevel: false, antialias: 4 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RasterFormat {
+    Tiff,
+    Png,
+}
+
+impl RasterFormat {
+    /// From the file extension: .tif, .tiff or .png
+    pub fn from_filename(filename: &str) -> Result<Self, AnotoError> {
+        let extension = std::path::Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        match extension.as_str() {
+            "tif" | "tiff" => Ok(RasterFormat::Tiff),
+            "png" => Ok(RasterFormat::Png),
+            _ => Err(AnotoError::Invalid(format!("Unknown raster format '{}', expected tiff or png", extension))),
+        }
+    }
+}
+
+pub fn gen_raster_from_matrix_data(bitmatrix: &ndarray::Array3<i32>, filename: &str, config: &PdfConfig, raster: &RasterConfig) -> std::result::Result<(), AnotoError> {
+    let (height, width, _) = bitmatrix.dim();
+    gen_raster_from_tiles(std::iter::once(matrix_tile(bitmatrix)), (width, height), filename, config, raster)
+}
+
+/// Writes the A4 page as a TIFF or PNG (by extension) from tiles covering a `(width, height)` area,
+/// rendered in bands so only a few bands of pixels are held at a time. The DPI is embedded.
+pub fn gen_raster_from_tiles(
+    tiles: impl IntoIterator<Item = PatternTile>,
+    size: (usize, usize),
+    filename: &str,
+    config: &PdfConfig,
+    raster: &RasterConfig,
+) -> std::result::Result<(), AnotoError> {
+    gen_raster_from_tiles_with_progress(tiles, size, filename, config, raster, &JobProgress::new())
+}
+
+/// `gen_raster_from_tiles` reporting pattern rows and bands to `progress`, stopping with `Cancelled` when asked to.
+pub fn gen_raster_from_tiles_with_progress(
+    tiles: impl IntoIterator<Item = PatternTile>,
+    size: (usize, usize),
+    filename: &str,
+    config: &PdfConfig,
+    raster: &RasterConfig,
+    progress: &JobProgress,
+) -> std::result::Result<(), AnotoError> {
+    let format = RasterFormat::from_filename(filename)?;
+    if raster.dpi == 0 {
+        return Err("Raster DPI must be positive".into());
+    }
+    if raster.dpi > MAX_RASTER_DPI {
+        return Err(AnotoError::Invalid(format!("Raster DPI {} is above the maximum of {}", raster.dpi, MAX_RASTER_DPI)));
+    }
+    let page = RasterPage::new(tiles, size, config, raster, progress)?;
+    let file = BufWriter::new(File::create(output_path(filename)?)?);
+    match format {
+        RasterFormat::Tiff => write_tiff(&page, file, progress),
+        RasterFormat::Png => write_png(&page, file, progress),
+    }
+}
+
+// Dot centres in pixels (y downwards), bucketed by the bands their discs touch
+struct RasterPage {
+    width: usize,
+    height: usize,
+    dpi: u32,
+    bilevel: bool,
+    samples: u32,
+    radius: f64,
+    bands: Vec<Vec<(f64, f64)>>,
+}
+
+impl RasterPage {
+    fn new(tiles: impl IntoIterator<Item = PatternTile>, size: (usize, usize), config: &PdfConfig, raster: &RasterConfig, progress: &JobProgress) -> std::result::Result<Self, AnotoError> {
+        let scale = raster.dpi as f64 / 72.0;
+        let (page_width, page_height) = page_size();
+        let width = (page_width * scale).round() as usize;
+        let height = (page_height * scale).round() as usize;
+        let radius = config.dot_size as f64 * scale;
+        let layout = DotLayout::new(size, config);
+
+        let mut bands = vec![Vec::new(); height.div_ceil(RASTER_BAND_ROWS)];
+        progress.start("Placing dots", size.1);
+        for tile in tiles {
+            progress.check()?;
+            for r in 0..tile.bits.dim().0 {
+                for c in 0..tile.bits.dim().1 {
+                    let (_, x, y) = layout.dot(tile.row + r, tile.col + c, tile.bits[[r, c, 0]], tile.bits[[r, c, 1]]);
+                    let (x, y) = (x * scale, (page_height - y) * scale);
+                    let first = ((y - radius).max(0.0) as usize) / RASTER_BAND_ROWS;
+                    let last = ((y + radius).max(0.0) as usize / RASTER_BAND_ROWS).min(bands.len().saturating_sub(1));
+                    for band in bands.iter_mut().take(last + 1).skip(first) {
+                        band.push((x, y));
+                    }
+                }
+                progress.advance(1);
+            }
+        }
+
+        Ok(RasterPage { width, height, dpi: raster.dpi, bilevel: raster.bilevel, samples: raster.antialias.max(1), radius, bands })
+    }
+
+    fn band_rows(&self, band: usize) -> std::ops::Range<usize> {
+        band * RASTER_BAND_ROWS..((band + 1) * RASTER_BAND_ROWS).min(self.height)
+    }
+
+    // Ink coverage 0.0..=1.0 of each pixel in the band. Pixels wholly inside or outside a disc are
+    // decided from their corners; only those on the outline are sampled.
+    fn coverage(&self, band: usize) -> Vec<f32> {
+        let rows = self.band_rows(band);
+        let mut coverage = vec![0.0f32; rows.len() * self.width];
+        let r2 = self.radius * self.radius;
+        let n = self.samples as usize;
+        let step = 1.0 / n as f64;
+
+        for &(cx, cy) in &self.bands[band] {
+            let x0 = (cx - self.radius).floor().max(0.0) as usize;
+            let x1 = ((cx + self.radius).ceil().max(0.0) as usize).min(self.width);
+            let y0 = ((cy - self.radius).floor().max(0.0) as usize).max(rows.start);
+            let y1 = ((cy + self.radius).ceil().max(0.0) as usize).min(rows.end);
+            for py in y0..y1 {
+                let (top, bottom) = (py as f64 - cy, py as f64 + 1.0 - cy);
+                let near_y = if top > 0.0 { top } else if bottom < 0.0 { -bottom } else { 0.0 };
+                let far_y = top.abs().max(bottom.abs());
+                for px in x0..x1 {
+                    let (left, right) = (px as f64 - cx, px as f64 + 1.0 - cx);
+                    let near_x = if left > 0.0 { left } else if right < 0.0 { -right } else { 0.0 };
+                    let far_x = left.abs().max(right.abs());
+                    if near_x * near_x + near_y * near_y >= r2 {
+                        continue;
+                    }
+                    let covered = if far_x * far_x + far_y * far_y <= r2 {
+                        1.0
+                    } else {
+                        let mut inside = 0;
+                        for sy in 0..n {
+                            let dy = top + (sy as f64 + 0.5) * step;
+                            for sx in 0..n {
+                                let dx = left + (sx as f64 + 0.5) * step;
+                                if dx * dx + dy * dy <= r2 {
+                                    inside += 1;
+                                }
+                            }
+                        }
+                        inside as f32 / (n * n) as f32
+                    };
+                    let pixel = &mut coverage[(py - rows.start) * self.width + px];
+                    *pixel = (*pixel + covered).min(1.0);
+                }
+            }
+        }
+        coverage
+    }
+
+    // Rows of the band as stored in TIFF and PNG: 0 is black, bilevel rows are packed MSB first
+    fn pixels(&self, band: usize) -> Vec<u8> {
+        let coverage = self.coverage(band);
+        if !self.bilevel {
+            return coverage.iter().map(|c| ((1.0 - c) * 255.0).round() as u8).collect();
+        }
+        let row_bytes = self.width.div_ceil(8);
+        let mut packed = vec![0xffu8; row_bytes * self.band_rows(band).len()];
+        for (row, line) in coverage.chunks(self.width).enumerate() {
+            for (x, c) in line.iter().enumerate() {
+                if *c >= 0.5 {
+                    packed[row * row_bytes + x / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+        }
+        packed
+    }
+
+    /// Hands the bands to `write` in order, top to bottom. Each batch of bands is rendered and
+    /// run through `encode` in parallel.
+    fn write_bands(
+        &self,
+        progress: &JobProgress,
+        encode: impl Fn(Vec<u8>) -> std::io::Result<Vec<u8>> + Sync,
+        mut write: impl FnMut(Vec<u8>) -> std::result::Result<(), AnotoError>,
+    ) -> std::result::Result<(), AnotoError> {
+        progress.start("Rendering raster", self.bands.len());
+        let bands: Vec<usize> = (0..self.bands.len()).collect();
+        for batch in bands.chunks(rayon::current_num_threads().max(1)) {
+            progress.check()?;
+            let encoded = batch
+                .par_iter()
+                .map(|&band| {
+                    let bytes = encode(self.pixels(band))?;
+                    progress.advance(1);
+                    Ok(bytes)
+                })
+                .collect::<std::io::Result<Vec<_>>>()?;
+            for bytes in encoded {
+                write(bytes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Baseline TIFF, one LZW strip per band
+fn write_tiff(page: &RasterPage, file: BufWriter<File>, progress: &JobProgress) -> std::result::Result<(), AnotoError> {
+    let mut encoder = TiffEncoder::new(file)?;
+    let mut directory = encoder.image_directory()?;
+    let mut offsets = Vec::new();
+    let mut byte_counts = Vec::new();
+    page.write_bands(
+        progress,
+        |pixels| {
+            let mut strip = Vec::new();
+            Lzw.write_to(&mut strip, &pixels)?;
+            Ok(strip)
+        },
+        |strip| {
+            offsets.push(u32::try_from(directory.write_data(strip.as_slice())?)?);
+            byte_counts.push(u32::try_from(strip.len())?);
+            Ok(())
+        },
+    )?;
+
+    let resolution = Rational { n: page.dpi, d: 1 };
+    directory.write_tag(Tag::ImageWidth, u32::try_from(page.width)?)?;
+    directory.write_tag(Tag::ImageLength, u32::try_from(page.height)?)?;
+    directory.write_tag(Tag::BitsPerSample, if page.bilevel { 1u16 } else { 8u16 })?;
+    directory.write_tag(Tag::Compression, CompressionMethod::LZW)?;
+    directory.write_tag(Tag::PhotometricInterpretation, PhotometricInterpretation::BlackIsZero)?;
+    directory.write_tag(Tag::StripOffsets, offsets.as_slice())?;
+    directory.write_tag(Tag::SamplesPerPixel, 1u16)?;
+    directory.write_tag(Tag::RowsPerStrip, RASTER_BAND_ROWS as u32)?;
+    directory.write_tag(Tag::StripByteCounts, byte_counts.as_slice())?;
+    directory.write_tag(Tag::XResolution, resolution.clone())?;
+    directory.write_tag(Tag::YResolution, resolution)?;
+    directory.write_tag(Tag::ResolutionUnit, ResolutionUnit::Inch)?;
+    directory.write_tag(Tag::Software, "anoto_pdf")?;
+    directory.finish()?;
+    Ok(())
+}
+
+// Grayscale PNG with the DPI in pHYs (pixels per metre)
+fn write_png(page: &RasterPage, file: BufWriter<File>, progress: &JobProgress) -> std::result::Result<(), AnotoError> {
+    let mut encoder = png::Encoder::new(file, u32::try_from(page.width)?, u32::try_from(page.height)?);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(if page.bilevel { png::BitDepth::One } else { png::BitDepth::Eight });
+    let pixels_per_metre = (page.dpi as f64 / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions { xppu: pixels_per_metre, yppu: pixels_per_metre, unit: png::Unit::Meter }));
+    let mut stream = encoder.write_header()?.into_stream_writer()?;
+    page.write_bands(progress, Ok, |rows| Ok(stream.write_all(&rows)?))?;
+    stream.finish()?;
+    Ok(())
+}
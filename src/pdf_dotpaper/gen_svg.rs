@@ -0,0 +1,72 @@
+use oxidize_pdf::Color;
+use std::fmt::Write;
+
+use super::gen_pdf::{AnotoDot, DotLayout, PdfConfig, dots_by_direction, matrix_tile, output_path, page_size, parse_hex_color};
+use crate::codec::PatternTile;
+use crate::progress::JobProgress;
+use crate::error::AnotoError;
+
+/// SVG with the same page and dot geometry as `gen_pdf_from_matrix_data`, saved to `output/`.
+pub fn gen_svg_from_matrix_data(bitmatrix: &ndarray::Array3<i32>, filename: &str, config: &PdfConfig) -> std::result::Result<(), AnotoError> {
+    let (height, width, _) = bitmatrix.dim();
+    gen_svg_from_tiles(std::iter::once(matrix_tile(bitmatrix)), (width, height), filename, config)
+}
+
+/// Writes the SVG from tiles (`AnotoCodec::tiles`) covering a `(width, height)` area.
+pub fn gen_svg_from_tiles(tiles: impl IntoIterator<Item = PatternTile>, size: (usize, usize), filename: &str, config: &PdfConfig) -> std::result::Result<(), AnotoError> {
+    std::fs::write(output_path(filename)?, svg_document(tiles, size, config)?)?;
+    Ok(())
+}
+
+/// Same document as `gen_svg_from_matrix_data`, returned as bytes instead of written to `output/`.
+pub fn gen_svg_bytes(bitmatrix: &ndarray::Array3<i32>, config: &PdfConfig) -> std::result::Result<Vec<u8>, AnotoError> {
+    let (height, width, _) = bitmatrix.dim();
+    Ok(svg_document(std::iter::once(matrix_tile(bitmatrix)), (width, height), config)?.into_bytes())
+}
+
+// One Inkscape layer per direction, so the dots of a direction can be restyled or hidden together.
+// SVG runs y downwards, so PDF y becomes page height - y.
+fn svg_document(tiles: impl IntoIterator<Item = PatternTile>, size: (usize, usize), config: &PdfConfig) -> std::result::Result<String, AnotoError> {
+    let (page_width, page_height) = page_size();
+    let radius = config.dot_size as f64;
+    let groups = dots_by_direction(tiles, &DotLayout::new(size, config), &JobProgress::new(), |out, x, y| {
+        let _ = writeln!(out, r#"    <circle cx="{:.2}" cy="{:.2}" r="{:.2}"/>"#, x, page_height - y, radius);
+    })?;
+
+    let mut svg = String::new();
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{w}pt" height="{h}pt" viewBox="0 0 {w} {h}">"#,
+        w = page_width,
+        h = page_height
+    )?;
+    writeln!(svg, "  <title>Anoto PDF</title>")?;
+    for (dot, circles) in AnotoDot::ALL.into_iter().zip(&groups) {
+        writeln!(
+            svg,
+            r#"  <g id="dots-{name}" inkscape:groupmode="layer" inkscape:label="{name} dots" fill="{fill}">"#,
+            name = dot.name(),
+            fill = svg_fill(&parse_hex_color(dot.color(config)))
+        )?;
+        svg.push_str(circles);
+        writeln!(svg, "  </g>")?;
+    }
+    writeln!(svg, "</svg>")?;
+    Ok(svg)
+}
+
+// The colour the PDF writer ends up using, so invalid hex strings fall back to black in both
+fn svg_fill(color: &Color) -> String {
+    let byte = |v: f64| (v * 255.0).round() as u8;
+    match *color {
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", byte(r), byte(g), byte(b)),
+        Color::Gray(g) => format!("#{0:02x}{0:02x}{0:02x}", byte(g)),
+        Color::Cmyk(c, m, y, k) => format!(
+            "#{:02x}{:02x}{:02x}",
+            byte((1.0 - c) * (1.0 - k)),
+            byte((1.0 - m) * (1.0 - k)),
+            byte((1.0 - y) * (1.0 - k))
+        ),
+    }
+}
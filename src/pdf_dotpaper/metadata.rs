@@ -0,0 +1,168 @@
+use oxidize_pdf::Document;
+use oxidize_pdf::metadata::XmpNamespace;
+use oxidize_pdf::metadata::xmp::XmpValue;
+use oxidize_pdf::parser::{ParseOptions, PdfObject, PdfReader};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use super::gen_calibration::CalibrationSwatch;
+use super::gen_pdf::PdfConfig;
+use crate::error::AnotoError;
+
+/// XMP namespace of the pattern properties, prefix `anoto`
+pub const PATTERN_XMP_NAMESPACE: &str = "urn:anoto-pdf:pattern:1.0";
+
+/// Where the pattern of a generated PDF comes from.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PatternInfo {
+    /// Codec name; `None` when the matrix did not come from a codec here (e.g. loaded from JSON)
+    pub codec: Option<String>,
+    /// (sect_u, sect_v)
+    pub section: Option<(i32, i32)>,
+    /// Pattern position (x, y) of the first dot of each page; empty when unknown
+    pub origins: Vec<(i64, i64)>,
+    /// Page address the pattern was generated for, `segment.shelf.book.page`
+    #[serde(default)]
+    pub address: Option<crate::codec::PageAddress>,
+}
+
+impl PatternInfo {
+    /// A single page generated with `codec` from `origin` in `section`.
+    pub fn generated(codec: &crate::codec::AnotoCodec, section: (i32, i32), origin: (i64, i64)) -> Self {
+        PatternInfo {
+            codec: Some(codec.name().to_string()).filter(|name| !name.is_empty()),
+            section: Some(section),
+            origins: vec![origin],
+            address: None,
+        }
+    }
+}
+
+/// Everything a generated PDF records about its pattern, read back with `read_pattern_metadata`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PatternMetadata {
+    pub pattern: PatternInfo,
+    /// (width, height) of the pattern on each page, in dots
+    pub page_sizes: Vec<(usize, usize)>,
+    pub config: PdfConfig,
+    /// Swatches of a calibration sheet, each with its own section and settings; empty otherwise
+    #[serde(default)]
+    pub swatches: Vec<CalibrationSwatch>,
+}
+
+impl PatternMetadata {
+    /// Pattern origin of page `page`, falling back to the first page's.
+    pub fn origin(&self, page: usize) -> Option<(i64, i64)> {
+        self.pattern.origins.get(page).or(self.pattern.origins.first()).copied()
+    }
+}
+
+// The document's own XMP (title, dates, producer) plus the `anoto:` properties. Tools get
+// readable properties; `anoto:metadata` holds the whole record as JSON for reading it back.
+fn xmp_packet(document: &Document, metadata: &PatternMetadata) -> Result<String, serde_json::Error> {
+    let namespace = XmpNamespace::Custom("anoto".to_string(), PATTERN_XMP_NAMESPACE.to_string());
+    let text = |value: String| XmpValue::Text(value);
+    let mut xmp = document.create_xmp_metadata();
+
+    if let Some(codec) = &metadata.pattern.codec {
+        xmp.set_text(namespace.clone(), "codec", codec.as_str());
+    }
+    if let Some((sect_u, sect_v)) = metadata.pattern.section {
+        xmp.set_text(namespace.clone(), "sectU", sect_u.to_string());
+        xmp.set_text(namespace.clone(), "sectV", sect_v.to_string());
+    }
+    if let Some(address) = &metadata.pattern.address {
+        xmp.set_text(namespace.clone(), "pageAddress", address.to_string());
+    }
+    let pages = metadata
+        .page_sizes
+        .iter()
+        .enumerate()
+        .map(|(page, &(width, height))| {
+            let mut fields = HashMap::from([("anoto:width".to_string(), text(width.to_string())), ("anoto:height".to_string(), text(height.to_string()))]);
+            if let Some((x, y)) = metadata.origin(page) {
+                fields.insert("anoto:x".to_string(), text(x.to_string()));
+                fields.insert("anoto:y".to_string(), text(y.to_string()));
+            }
+            fields
+        })
+        .collect();
+    xmp.set_array_struct(namespace.clone(), "pages", pages);
+    let config = &metadata.config;
+    xmp.set_struct(
+        namespace.clone(),
+        "config",
+        HashMap::from([
+            ("anoto:dotSize".to_string(), text(config.dot_size.to_string())),
+            ("anoto:offsetFromOrigin".to_string(), text(config.offset_from_origin.to_string())),
+            ("anoto:gridSpacing".to_string(), text(config.grid_spacing.to_string())),
+            ("anoto:dpi".to_string(), text(config.dpi.to_string())),
+            ("anoto:colorUp".to_string(), text(config.color_up.clone())),
+            ("anoto:colorDown".to_string(), text(config.color_down.clone())),
+            ("anoto:colorLeft".to_string(), text(config.color_left.clone())),
+            ("anoto:colorRight".to_string(), text(config.color_right.clone())),
+        ]),
+    );
+    xmp.set_text(namespace, "metadata", serde_json::to_string(metadata)?);
+    Ok(xmp.to_xmp_packet())
+}
+
+/// Appends an incremental update to a PDF written from `document`, replacing its XMP stream with
+/// one that also carries `metadata`. The original bytes stay untouched.
+pub(crate) fn embed_pattern_metadata(mut pdf: Vec<u8>, document: &Document, metadata: &PatternMetadata) -> Result<Vec<u8>, AnotoError> {
+    let mut reader = PdfReader::new_with_options(Cursor::new(pdf.as_slice()), ParseOptions::lenient())?;
+    let Some(PdfObject::Reference(number, generation)) = reader.catalog()?.get("Metadata").cloned() else {
+        return Err("PDF has no metadata stream to update".into());
+    };
+
+    // The update's trailer repeats the last one (Root, Info, Size, ID) and points back to it
+    let text = String::from_utf8_lossy(&pdf);
+    let startxref = text.rfind("startxref").ok_or("PDF has no startxref")?;
+    let trailer = text.rfind("trailer").filter(|&t| t < startxref).ok_or("PDF has no trailer")?;
+    let previous_xref: u64 = text[startxref + "startxref".len()..].split_whitespace().next().ok_or("PDF has no xref offset")?.parse()?;
+    let trailer = text[trailer + "trailer".len()..startxref].trim();
+    let trailer = trailer.strip_suffix(">>").ok_or("PDF trailer is not a dictionary")?.trim_end().to_string();
+
+    let packet = xmp_packet(document, metadata)?;
+    if !pdf.ends_with(b"\n") {
+        pdf.push(b'\n');
+    }
+    let offset = pdf.len();
+    pdf.extend_from_slice(format!("{} {} obj\n<< /Type /Metadata /Subtype /XML /Length {} >>\nstream\n", number, generation, packet.len()).as_bytes());
+    pdf.extend_from_slice(packet.as_bytes());
+    pdf.extend_from_slice(b"\nendstream\nendobj\n");
+    let xref = pdf.len();
+    pdf.extend_from_slice(
+        format!(
+            "xref\n{} 1\n{:010} {:05} n \ntrailer\n{}\n/Prev {}\n>>\nstartxref\n{}\n%%EOF\n",
+            number, offset, generation, trailer, previous_xref, xref
+        )
+        .as_bytes(),
+    );
+    Ok(pdf)
+}
+
+/// The pattern record of a PDF written by this crate, `None` for PDFs without one.
+pub fn read_pattern_metadata(path: &str) -> Result<Option<PatternMetadata>, AnotoError> {
+    let mut reader = PdfReader::new_with_options(std::fs::File::open(path)?, ParseOptions::lenient())?;
+    let Some(PdfObject::Reference(number, generation)) = reader.catalog()?.get("Metadata").cloned() else {
+        return Ok(None);
+    };
+    let options = reader.options().clone();
+    let Some(stream) = reader.get_object(number, generation)?.as_stream().cloned() else {
+        return Ok(None);
+    };
+    let packet = String::from_utf8(stream.decode(&options)?)?;
+
+    let (open, close) = ("<anoto:metadata>", "</anoto:metadata>");
+    let Some(start) = packet.find(open) else {
+        return Ok(None);
+    };
+    let end = packet[start..].find(close).ok_or("Unterminated anoto:metadata in XMP")? + start;
+    Ok(Some(serde_json::from_str(&unescape_xml(&packet[start + open.len()..end]))?))
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
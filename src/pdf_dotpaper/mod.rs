@@ -1 +1,8 @@
-pub mod gen_pdf;
\ No newline at end of file
+pub mod gen_pdf;
+pub mod gen_svg;
+pub mod gen_eps;
+pub mod gen_raster;
+pub mod gen_calibration;
+pub mod gen_poster;
+pub mod read_pdf;
+pub mod metadata;
@@ -0,0 +1,340 @@
+use ndarray::Array3;
+use oxidize_pdf::parser::{ContentOperation, ContentParser, ParseOptions, PdfDictionary, PdfDocument, PdfObject, PdfReader};
+use std::fmt;
+use std::fs::File;
+
+use super::gen_pdf::{AnotoDot, DotLayout, PdfConfig};
+use crate::error::AnotoError;
+
+// Form XObjects nested deeper than this are not followed
+const MAX_XOBJECT_DEPTH: usize = 8;
+
+/// Dot centres of one page, in PDF points
+pub type PageDots = Vec<(f64, f64)>;
+
+/// Bit matrix read back from one PDF page. Cells without a dot hold -1 in both bit planes.
+pub struct PdfReadback {
+    pub bits: Array3<i32>,
+    /// Dot centres that belong to no cell of the grid, in PDF points
+    pub stray_dots: Vec<(f64, f64)>,
+    /// Cells that more than one dot mapped to; the last dot wins
+    pub crowded_cells: Vec<(usize, usize)>,
+}
+
+/// A cell whose read-back dot differs from the expected pattern.
+pub struct CellMismatch {
+    pub row: usize,
+    pub col: usize,
+    pub expected: [i32; 2],
+    /// `None` when the page has no dot for the cell
+    pub found: Option<[i32; 2]>,
+}
+
+impl fmt::Display for CellMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |bits: [i32; 2]| AnotoDot::from_bits(bits[0] as i8, bits[1] as i8).name();
+        match self.found {
+            Some(found) => write!(f, "cell ({}, {}): expected {}, found {}", self.row, self.col, name(self.expected), name(found)),
+            None => write!(f, "cell ({}, {}): expected {}, found no dot", self.row, self.col, name(self.expected)),
+        }
+    }
+}
+
+/// Outcome of checking one PDF page against the pattern it should carry.
+pub struct PdfVerification {
+    /// 0-based page index
+    pub page: usize,
+    pub dots: usize,
+    pub readback: PdfReadback,
+    pub mismatches: Vec<CellMismatch>,
+}
+
+impl PdfVerification {
+    /// True when every cell matches and no dot was left over.
+    pub fn is_match(&self) -> bool {
+        self.mismatches.is_empty() && self.readback.stray_dots.is_empty() && self.readback.crowded_cells.is_empty()
+    }
+}
+
+impl fmt::Display for PdfVerification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (height, width, _) = self.readback.bits.dim();
+        write!(f, "Page {}: {} dots, {} of {} cells match", self.page + 1, self.dots, height * width - self.mismatches.len(), height * width)?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {}", mismatch)?;
+        }
+        for (row, col) in &self.readback.crowded_cells {
+            write!(f, "\n  cell ({}, {}): more than one dot", row, col)?;
+        }
+        for (x, y) in &self.readback.stray_dots {
+            write!(f, "\n  stray dot at ({:.2}, {:.2}) pt", x, y)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads every page of a PDF back and compares page `i` with `expected[i]`, the pattern written with
+/// `config` centred on the page. Pages past the end of `expected` are compared with its last matrix.
+pub fn verify_pdf(path: &str, expected: &[Array3<i32>], config: &PdfConfig) -> std::result::Result<Vec<PdfVerification>, AnotoError> {
+    let mut pages = Vec::new();
+    for (page, (dots, centre)) in read_pages(path, config)?.into_iter().enumerate() {
+        let expected = expected.get(page).or(expected.last()).ok_or("No expected pattern to compare with")?;
+        let (height, width, _) = expected.dim();
+        let readback = readback(&dots, (width, height), DotLayout::centred_at((width, height), config, centre));
+        let mismatches = compare_bitmatrix(&readback.bits, expected)?;
+        pages.push(PdfVerification { page, dots: dots.len(), readback, mismatches });
+    }
+    Ok(pages)
+}
+
+/// A pattern drawn on a page with its own geometry, like each swatch of a calibration sheet.
+pub struct PlacedPattern {
+    /// 0-based page index
+    pub page: usize,
+    pub expected: Array3<i32>,
+    pub config: PdfConfig,
+    /// Centre of the dot grid, in PDF points
+    pub centre: (f64, f64),
+}
+
+/// `verify_pdf` for pages that carry several patterns. Each pattern is read back from the dots
+/// within half a grid spacing of its own grid, so the dots of its neighbours are not stray.
+pub fn verify_placed_patterns(path: &str, patterns: &[PlacedPattern]) -> std::result::Result<Vec<PdfVerification>, AnotoError> {
+    let widest = patterns.iter().map(|p| p.config.grid_spacing).fold(0.0, f32::max);
+    let pages = read_pages(path, &PdfConfig { grid_spacing: widest, ..PdfConfig::default() })?;
+    let mut verifications = Vec::with_capacity(patterns.len());
+    for pattern in patterns {
+        let (dots, _) = pages.get(pattern.page).ok_or_else(|| format!("{} has {} page(s), page {} requested", path, pages.len(), pattern.page + 1))?;
+        let (height, width, _) = pattern.expected.dim();
+        let layout = DotLayout::centred_at((width, height), &pattern.config, pattern.centre);
+        let (left, bottom) = layout.position(-0.5, -0.5);
+        let (right, top) = layout.position(height as f64 - 0.5, width as f64 - 0.5);
+        let dots: PageDots = dots.iter().copied().filter(|&(x, y)| (left..right).contains(&x) && (bottom..top).contains(&y)).collect();
+        let readback = readback(&dots, (width, height), layout);
+        let mismatches = compare_bitmatrix(&readback.bits, &pattern.expected)?;
+        verifications.push(PdfVerification { page: pattern.page, dots: dots.len(), readback, mismatches });
+    }
+    Ok(verifications)
+}
+
+/// Dot centres of every page in PDF points, in page order.
+///
+/// A dot is any painted subpath smaller than half the grid spacing, so the zero-length round-cap
+/// strokes of `gen_pdf_from_matrix_data`, filled Bézier circles and small rectangles all count.
+/// Dots drawn inside Form XObjects are found as well; `cm` and XObject matrices are applied.
+pub fn read_pdf_dots(path: &str, config: &PdfConfig) -> std::result::Result<Vec<PageDots>, AnotoError> {
+    Ok(read_pages(path, config)?.into_iter().map(|(dots, _)| dots).collect())
+}
+
+// Dots of a page with the centre of its media box, which the writers centre the pattern on
+type PageScan = (PageDots, (f64, f64));
+
+fn read_pages(path: &str, config: &PdfConfig) -> std::result::Result<Vec<PageScan>, AnotoError> {
+    let document = open_pdf(path)?;
+    let mut pages = Vec::new();
+    for index in 0..document.page_count()? {
+        let page = document.get_page(index)?;
+        let mut scanner = DotScanner::new(&document, config.grid_spacing as f64 / 2.0);
+        for content in document.get_page_content_streams(&page)? {
+            scanner.scan(&content, page.get_resources(), 0)?;
+        }
+        let [left, bottom, right, top] = page.media_box;
+        pages.push((scanner.dots, ((left + right) / 2.0, (bottom + top) / 2.0)));
+    }
+    Ok(pages)
+}
+
+/// Reads page `page` (0-based) of a PDF written with `config` for a `(width, height)` pattern back into
+/// a bit matrix, assigning each dot to its nearest grid node.
+pub fn read_bitmatrix_from_pdf(path: &str, page: usize, size: (usize, usize), config: &PdfConfig) -> std::result::Result<PdfReadback, AnotoError> {
+    let pages = read_pages(path, config)?;
+    let (dots, centre) = pages.get(page).ok_or_else(|| format!("{} has {} page(s), page {} requested", path, pages.len(), page + 1))?;
+    Ok(readback(dots, size, DotLayout::centred_at(size, config, *centre)))
+}
+
+/// Places dot centres (PDF points) on the grid of a `(width, height)` pattern laid out with `config`
+/// on an A4 page.
+pub fn bitmatrix_from_dots(dots: &[(f64, f64)], size: (usize, usize), config: &PdfConfig) -> PdfReadback {
+    readback(dots, size, DotLayout::new(size, config))
+}
+
+fn readback(dots: &[(f64, f64)], size: (usize, usize), layout: DotLayout) -> PdfReadback {
+    let (width, height) = size;
+    let mut bits = Array3::from_elem((height, width, 2), -1);
+    let mut stray_dots = Vec::new();
+    let mut crowded_cells = Vec::new();
+
+    for &(x, y) in dots {
+        match layout.node(x, y) {
+            Some((row, col, dot)) if row < height && col < width => {
+                if bits[[row, col, 0]] != -1 {
+                    crowded_cells.push((row, col));
+                }
+                let (x_bit, y_bit) = dot.bits();
+                bits[[row, col, 0]] = x_bit as i32;
+                bits[[row, col, 1]] = y_bit as i32;
+            }
+            _ => stray_dots.push((x, y)),
+        }
+    }
+    PdfReadback { bits, stray_dots, crowded_cells }
+}
+
+/// Every cell where `found` differs from `expected`, row by row. The matrices must have the same shape.
+pub fn compare_bitmatrix(found: &Array3<i32>, expected: &Array3<i32>) -> std::result::Result<Vec<CellMismatch>, AnotoError> {
+    if found.dim() != expected.dim() {
+        return Err(AnotoError::shape(format!("a read back matrix of {:?}", expected.dim()), format!("{:?}", found.dim())));
+    }
+    let (height, width, _) = expected.dim();
+    let mut mismatches = Vec::new();
+    for row in 0..height {
+        for col in 0..width {
+            let expected = [expected[[row, col, 0]], expected[[row, col, 1]]];
+            let found = [found[[row, col, 0]], found[[row, col, 1]]];
+            if found != expected {
+                let found = if found[0] == -1 { None } else { Some(found) };
+                mismatches.push(CellMismatch { row, col, expected, found });
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+// Lenient parsing, as PDFs coming back from other tools are not always tidy
+fn open_pdf(path: &str) -> std::result::Result<PdfDocument<File>, AnotoError> {
+    let reader = PdfReader::new_with_options(File::open(path)?, ParseOptions::lenient())?;
+    Ok(PdfDocument::new(reader))
+}
+
+// Affine matrix [a b c d e f] as in the PDF `cm` operator
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// `first` applied, then `second`
+fn concat(first: &Matrix, second: &Matrix) -> Matrix {
+    [
+        first[0] * second[0] + first[1] * second[2],
+        first[0] * second[1] + first[1] * second[3],
+        first[2] * second[0] + first[3] * second[2],
+        first[2] * second[1] + first[3] * second[3],
+        first[4] * second[0] + first[5] * second[2] + second[4],
+        first[4] * second[1] + first[5] * second[3] + second[5],
+    ]
+}
+
+fn transform(m: &Matrix, x: f32, y: f32) -> (f64, f64) {
+    let (x, y) = (x as f64, y as f64);
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+// Walks content streams keeping the graphics state stack and the current path, in page space
+struct DotScanner<'a> {
+    document: &'a PdfDocument<File>,
+    max_extent: f64,
+    ctm: Matrix,
+    saved: Vec<Matrix>,
+    subpaths: Vec<Vec<(f64, f64)>>,
+    dots: PageDots,
+}
+
+impl<'a> DotScanner<'a> {
+    fn new(document: &'a PdfDocument<File>, max_extent: f64) -> Self {
+        DotScanner { document, max_extent, ctm: IDENTITY, saved: Vec::new(), subpaths: Vec::new(), dots: Vec::new() }
+    }
+
+    fn scan(&mut self, content: &[u8], resources: Option<&PdfDictionary>, depth: usize) -> std::result::Result<(), AnotoError> {
+        for operation in ContentParser::parse_content(content)? {
+            match operation {
+                ContentOperation::SaveGraphicsState => self.saved.push(self.ctm),
+                ContentOperation::RestoreGraphicsState => self.ctm = self.saved.pop().unwrap_or(IDENTITY),
+                ContentOperation::SetTransformMatrix(a, b, c, d, e, f) => {
+                    self.ctm = concat(&[a as f64, b as f64, c as f64, d as f64, e as f64, f as f64], &self.ctm);
+                }
+                ContentOperation::MoveTo(x, y) => self.subpaths.push(vec![transform(&self.ctm, x, y)]),
+                ContentOperation::LineTo(x, y) => self.extend(&[(x, y)]),
+                ContentOperation::CurveTo(x1, y1, x2, y2, x3, y3) => self.extend(&[(x1, y1), (x2, y2), (x3, y3)]),
+                ContentOperation::CurveToV(x2, y2, x3, y3) | ContentOperation::CurveToY(x2, y2, x3, y3) => self.extend(&[(x2, y2), (x3, y3)]),
+                ContentOperation::Rectangle(x, y, w, h) => {
+                    let corners = [(x, y), (x + w, y), (x + w, y + h), (x, y + h)];
+                    self.subpaths.push(corners.iter().map(|&(x, y)| transform(&self.ctm, x, y)).collect());
+                }
+                ContentOperation::Stroke
+                | ContentOperation::CloseStroke
+                | ContentOperation::Fill
+                | ContentOperation::FillEvenOdd
+                | ContentOperation::FillStroke
+                | ContentOperation::FillStrokeEvenOdd
+                | ContentOperation::CloseFillStroke
+                | ContentOperation::CloseFillStrokeEvenOdd => self.paint(),
+                ContentOperation::EndPath => self.subpaths.clear(),
+                ContentOperation::PaintXObject(name) if depth < MAX_XOBJECT_DEPTH => self.paint_xobject(&name, resources, depth)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn extend(&mut self, points: &[(f32, f32)]) {
+        let points = points.iter().map(|&(x, y)| transform(&self.ctm, x, y));
+        match self.subpaths.last_mut() {
+            Some(subpath) => subpath.extend(points),
+            None => self.subpaths.push(points.collect()),
+        }
+    }
+
+    // Each small subpath is one dot at the centre of its bounding box
+    fn paint(&mut self) {
+        for subpath in self.subpaths.drain(..) {
+            let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+            for &(x, y) in &subpath {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+            if !subpath.is_empty() && max_x - min_x <= self.max_extent && max_y - min_y <= self.max_extent {
+                self.dots.push(((min_x + max_x) / 2.0, (min_y + max_y) / 2.0));
+            }
+        }
+    }
+
+    fn paint_xobject(&mut self, name: &str, resources: Option<&PdfDictionary>, depth: usize) -> std::result::Result<(), AnotoError> {
+        let Some(entry) = resources.and_then(|r| r.get("XObject")).map(|x| self.document.resolve(x)).transpose()? else {
+            return Ok(());
+        };
+        let Some(object) = entry.as_dict().and_then(|x| x.get(name)).map(|o| self.document.resolve(o)).transpose()? else {
+            return Ok(());
+        };
+        let Some(stream) = object.as_stream() else {
+            return Ok(());
+        };
+        if stream.dict.get("Subtype").and_then(|s| s.as_name()).map(|n| n.0.as_str()) != Some("Form") {
+            return Ok(());
+        }
+
+        let mut matrix = IDENTITY;
+        if let Some(values) = stream.dict.get("Matrix").and_then(|m| m.as_array()) {
+            for (i, value) in values.0.iter().take(6).enumerate() {
+                matrix[i] = number(value).unwrap_or(matrix[i]);
+            }
+        }
+        let content = stream.decode(&self.document.options())?;
+        let form_resources = match stream.dict.get("Resources") {
+            Some(r) => Some(self.document.resolve(r)?),
+            None => None,
+        };
+        let form_resources = form_resources.as_ref().and_then(|r| r.as_dict()).or(resources);
+
+        // The form runs in its own graphics state and may not leave a path behind
+        let (ctm, saved, subpaths) = (self.ctm, std::mem::take(&mut self.saved), std::mem::take(&mut self.subpaths));
+        self.ctm = concat(&matrix, &ctm);
+        let result = self.scan(&content, form_resources, depth + 1);
+        (self.ctm, self.saved, self.subpaths) = (ctm, saved, subpaths);
+        result
+    }
+}
+
+fn number(object: &PdfObject) -> Option<f64> {
+    object.as_real().or_else(|| object.as_integer().map(|i| i as f64))
+}
@@ -0,0 +1,328 @@
+use ndarray::Array3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::codec::AnotoCodec;
+use crate::error::AnotoError;
+
+/// Camera and print imperfections applied by `simulate_capture`.
+///
+/// Lengths without a unit are in grid spacings (one dot pitch), so the defaults
+/// match `PdfConfig::default()`: 1pt dots offset 3pt on a 10pt grid.
+#[derive(Clone, Debug, Serialize)]
+pub struct CameraConfig {
+    pub image_width: u32,
+    pub image_height: u32,
+    /// Image pixels per grid spacing at the image centre
+    pub pixels_per_dot: f64,
+    /// Pen rotation around the optical axis, in degrees
+    pub rotation_deg: f64,
+    /// Perspective tilt along the image x and y axes; 0.0 is a pen held upright and the
+    /// magnitudes must add up to less than 1
+    pub tilt: (f64, f64),
+    pub dot_radius: f64,
+    pub dot_offset: f64,
+    /// Gaussian blur sigma in pixels
+    pub blur_sigma: f64,
+    /// Probability that a printed dot is missing from the capture
+    pub dropout: f64,
+    /// Number of dirt/noise dots scattered over the image
+    pub spurious_dots: usize,
+    /// Gaussian sensor noise sigma in grey levels
+    pub noise_sigma: f64,
+    pub seed: u64,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            image_width: 108,
+            image_height: 108,
+            pixels_per_dot: 12.0,
+            rotation_deg: 0.0,
+            tilt: (0.0, 0.0),
+            dot_radius: 0.1,
+            dot_offset: 0.3,
+            blur_sigma: 0.8,
+            dropout: 0.0,
+            spurious_dots: 0,
+            noise_sigma: 4.0,
+            seed: 0,
+        }
+    }
+}
+
+/// A rendered capture with its ground truth.
+pub struct SimCapture {
+    pub image: image::GrayImage,
+    /// Window of the codec's order that covers the image centre
+    pub bits: Array3<i8>,
+    /// What `decode_position` should return for `bits`
+    pub position: (i64, i64),
+    /// Exact pattern point under the image centre
+    pub nib: (f64, f64),
+    pub section: (i32, i32),
+    /// Image coordinates of every printed dot that made it into the capture
+    pub dots: Vec<(f64, f64)>,
+    /// Image coordinates of the spurious dots
+    pub spurious: Vec<(f64, f64)>,
+}
+
+// Grey levels of the paper and of a fully covered dot
+const PAPER_LEVEL: f64 = 220.0;
+const INK_LEVEL: f64 = 40.0;
+
+struct Projection {
+    centre: (f64, f64),
+    cos: f64,
+    sin: f64,
+    scale: f64,
+    tilt: (f64, f64),
+    half: (f64, f64),
+}
+
+impl Projection {
+    fn new(nib: (f64, f64), config: &CameraConfig) -> Self {
+        let angle = config.rotation_deg.to_radians();
+        Projection {
+            centre: nib,
+            cos: angle.cos(),
+            sin: angle.sin(),
+            scale: config.pixels_per_dot,
+            tilt: config.tilt,
+            half: (config.image_width as f64 / 2.0, config.image_height as f64 / 2.0),
+        }
+    }
+
+    /// Pattern point (x right, y up the page as in `gen_pdf`) to image pixel (u right, v down).
+    fn project(&self, p: (f64, f64)) -> (f64, f64) {
+        let dx = p.0 - self.centre.0;
+        let dy = p.1 - self.centre.1;
+        let rx = (dx * self.cos - dy * self.sin) * self.scale;
+        let ry = (dx * self.sin + dy * self.cos) * self.scale;
+        let (u, v) = (rx, -ry);
+        let w = 1.0 + self.tilt.0 * u / self.half.0 + self.tilt.1 * v / self.half.1;
+        (u / w + self.half.0, v / w + self.half.1)
+    }
+}
+
+/// Renders what a pen camera sees with its image centre over `nib` (pattern units,
+/// x along columns and y along rows of the bitmatrix) in the given section.
+pub fn simulate_capture(
+    codec: &AnotoCodec,
+    section: (i32, i32),
+    nib: (f64, f64),
+    config: &CameraConfig,
+) -> Result<SimCapture, AnotoError> {
+    if config.image_width == 0 || config.image_height == 0 || config.pixels_per_dot <= 0.0 {
+        return Err("Camera image size and pixels_per_dot must be positive".into());
+    }
+    // The perspective divisor 1 + tilt.0 * u + tilt.1 * v (u, v in -1..1) has to stay positive
+    // over the whole image, or dots behind the camera would be drawn mirrored
+    let tilt = config.tilt.0.abs() + config.tilt.1.abs();
+    if tilt.is_nan() || tilt >= 1.0 {
+        return Err(AnotoError::Invalid(format!(
+            "Camera tilt {:?} is out of range; |x| + |y| must stay below 1",
+            config.tilt
+        )));
+    }
+
+    let order = codec.mns_order();
+    let rolls = (section.0 % codec.mns_length as i32, section.1 % codec.mns_length as i32);
+
+    // Every node whose dot could land in the image, with room for tilt and rotation
+    let max_tilt = tilt.min(0.9);
+    let reach = (config.image_width.max(config.image_height) as f64 / config.pixels_per_dot)
+        / (1.0 - max_tilt)
+        + 2.0;
+    let node_x0 = (nib.0 - reach).floor() as i64;
+    let node_y0 = (nib.1 - reach).floor() as i64;
+    let span = (2.0 * reach).ceil() as usize + 1;
+    let field = codec.encode_patch((node_x0, node_y0), (span, span), rolls)?;
+
+    let projection = Projection::new(nib, config);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let (w, h) = (config.image_width as usize, config.image_height as usize);
+    let mut coverage = vec![0f64; w * h];
+    let mut dots = Vec::new();
+
+    for r in 0..span {
+        for c in 0..span {
+            let dot_type = field[[r, c, 0]] + (field[[r, c, 1]] << 1);
+            let (dx, dy) = match dot_type {
+                0 => (0.0, config.dot_offset),  // Up
+                1 => (-config.dot_offset, 0.0), // Left
+                2 => (config.dot_offset, 0.0),  // Right
+                _ => (0.0, -config.dot_offset), // Down
+            };
+            let centre = (node_x0 as f64 + c as f64 + dx, node_y0 as f64 + r as f64 + dy);
+            let (u, v) = projection.project(centre);
+            if u < -2.0 || v < -2.0 || u > w as f64 + 2.0 || v > h as f64 + 2.0 {
+                continue;
+            }
+            if rng.random::<f64>() < config.dropout {
+                continue;
+            }
+            let edge = projection.project((centre.0 + config.dot_radius, centre.1));
+            let radius = ((edge.0 - u).powi(2) + (edge.1 - v).powi(2)).sqrt();
+            draw_disc(&mut coverage, w, h, (u, v), radius);
+            dots.push((u, v));
+        }
+    }
+
+    let mut spurious = Vec::new();
+    let nominal_radius = config.dot_radius * config.pixels_per_dot;
+    for _ in 0..config.spurious_dots {
+        let u = rng.random::<f64>() * w as f64;
+        let v = rng.random::<f64>() * h as f64;
+        let radius = nominal_radius * (0.5 + rng.random::<f64>());
+        draw_disc(&mut coverage, w, h, (u, v), radius);
+        spurious.push((u, v));
+    }
+
+    if config.blur_sigma > 0.0 {
+        coverage = gaussian_blur(&coverage, w, h, config.blur_sigma);
+    }
+
+    let mut image = image::GrayImage::new(config.image_width, config.image_height);
+    for (i, pixel) in image.pixels_mut().enumerate() {
+        let level = PAPER_LEVEL - coverage[i] * (PAPER_LEVEL - INK_LEVEL)
+            + config.noise_sigma * standard_normal(&mut rng);
+        pixel.0[0] = level.round().clamp(0.0, 255.0) as u8;
+    }
+
+    // The window the decoder would read around the image centre
+    let half_window = (order as f64 - 1.0) / 2.0;
+    let position = (
+        codec.wrap_position((nib.0 - half_window).round() as i64),
+        codec.wrap_position((nib.1 - half_window).round() as i64),
+    );
+    let bits = codec.encode_patch(position, (order, order), rolls)?;
+
+    Ok(SimCapture {
+        image,
+        bits,
+        position,
+        nib,
+        section,
+        dots,
+        spurious,
+    })
+}
+
+/// Writes `output/{base_filename}.png` and the ground truth as `output/{base_filename}.json`.
+pub fn save_capture(capture: &SimCapture, config: &CameraConfig, base_filename: &str) -> Result<(), AnotoError> {
+    #[derive(Serialize)]
+    struct GroundTruth<'a> {
+        position: (i64, i64),
+        nib: (f64, f64),
+        section: (i32, i32),
+        bits: Vec<Vec<[i8; 2]>>,
+        dots: &'a [(f64, f64)],
+        spurious: &'a [(f64, f64)],
+        camera: &'a CameraConfig,
+    }
+
+    std::fs::create_dir_all("output")?;
+    capture.image.save(format!("output/{}.png", base_filename))?;
+
+    let truth = GroundTruth {
+        position: capture.position,
+        nib: capture.nib,
+        section: capture.section,
+        bits: capture.bits.outer_iter()
+            .map(|row| row.outer_iter().map(|cell| [cell[0], cell[1]]).collect())
+            .collect(),
+        dots: &capture.dots,
+        spurious: &capture.spurious,
+        camera: config,
+    };
+    let file = std::fs::File::create(format!("output/{}.json", base_filename))?;
+    serde_json::to_writer_pretty(file, &truth)?;
+    Ok(())
+}
+
+fn draw_disc(coverage: &mut [f64], w: usize, h: usize, centre: (f64, f64), radius: f64) {
+    let (u, v) = centre;
+    let u0 = (u - radius - 1.0).floor().max(0.0) as usize;
+    let v0 = (v - radius - 1.0).floor().max(0.0) as usize;
+    let u1 = ((u + radius + 1.0).ceil().max(0.0) as usize).min(w);
+    let v1 = ((v + radius + 1.0).ceil().max(0.0) as usize).min(h);
+    for py in v0..v1 {
+        for px in u0..u1 {
+            // Pixel centre distance, with a one pixel anti-aliased edge
+            let d = ((px as f64 + 0.5 - u).powi(2) + (py as f64 + 0.5 - v).powi(2)).sqrt();
+            let c = (radius + 0.5 - d).clamp(0.0, 1.0);
+            let idx = py * w + px;
+            coverage[idx] = coverage[idx].max(c);
+        }
+    }
+}
+
+fn gaussian_blur(src: &[f64], w: usize, h: usize, sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let norm: f64 = kernel.iter().sum();
+
+    let mut tmp = vec![0f64; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sx = (x as isize + k as isize - radius).clamp(0, w as isize - 1) as usize;
+                acc += src[y * w + sx] * weight;
+            }
+            tmp[y * w + x] = acc / norm;
+        }
+    }
+
+    let mut out = vec![0f64; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sy = (y as isize + k as isize - radius).clamp(0, h as isize - 1) as usize;
+                acc += tmp[sy * w + x] * weight;
+            }
+            out[y * w + x] = acc / norm;
+        }
+    }
+    out
+}
+
+// Box-Muller
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = rng.random::<f64>().max(f64::MIN_POSITIVE);
+    let u2: f64 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::anoto_6x6_a4_fixed;
+
+    #[test]
+    fn ground_truth_decodes_to_its_position() {
+        let codec = anoto_6x6_a4_fixed();
+        let config = CameraConfig { rotation_deg: 40.0, tilt: (0.1, 0.05), ..Default::default() };
+        let capture = simulate_capture(&codec, (10, 2), (500.5, 700.25), &config).unwrap();
+        assert_eq!(codec.decode_position(&capture.bits).unwrap(), capture.position);
+        assert_eq!(capture.position, (498, 698));
+        assert!(!capture.dots.is_empty());
+    }
+
+    #[test]
+    fn rejects_tilt_that_folds_the_image() {
+        let codec = anoto_6x6_a4_fixed();
+        for tilt in [(1.0, 0.0), (0.0, -1.5), (0.6, -0.6), (f64::NAN, 0.0)] {
+            let config = CameraConfig { tilt, ..Default::default() };
+            assert!(matches!(simulate_capture(&codec, (10, 2), (100.0, 100.0), &config), Err(AnotoError::Invalid(_))), "{:?}", tilt);
+        }
+        let config = CameraConfig { tilt: (0.5, -0.4), ..Default::default() };
+        assert!(simulate_capture(&codec, (10, 2), (100.0, 100.0), &config).is_ok());
+    }
+}
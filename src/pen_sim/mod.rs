@@ -0,0 +1,2 @@
+pub mod camera;
+pub use camera::{CameraConfig, SimCapture, simulate_capture, save_capture};
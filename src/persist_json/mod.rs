@@ -1,3 +1,3 @@
 pub mod persist;
 
-pub use persist::{save_bitmatrix_text, save_bitmatrix_json, save_as_json, save_as_txt, load_from_json, load_array3_from_json, load_6x6_section};
+pub use persist::{save_bitmatrix_text, save_bitmatrix_json, save_as_json, save_as_txt, save_tiles_as_json, save_tiles_as_txt, load_from_json, load_array3_from_json, load_6x6_section, load_section};
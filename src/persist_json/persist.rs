@@ -1,9 +1,11 @@
 use ndarray::{Array2, Array3};
-use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 
-pub fn save_bitmatrix_text(bitmatrix: &Array3<i8>, filename: &str) -> Result<(), Box<dyn Error>> {
+use crate::codec::PatternTile;
+use crate::error::AnotoError;
+
+pub fn save_bitmatrix_text(bitmatrix: &Array3<i8>, filename: &str) -> Result<(), AnotoError> {
     let mut file = File::create(filename)?;
     for row in 0..bitmatrix.dim().0 {
         for col in 0..bitmatrix.dim().1 {
@@ -19,7 +21,7 @@ pub fn save_bitmatrix_text(bitmatrix: &Array3<i8>, filename: &str) -> Result<(),
     Ok(())
 }
 
-pub fn save_bitmatrix_json(bitmatrix: &Array3<i8>, filename: &str) -> Result<(), Box<dyn Error>> {
+pub fn save_bitmatrix_json(bitmatrix: &Array3<i8>, filename: &str) -> Result<(), AnotoError> {
     let mut data = Vec::new();
     for row in 0..bitmatrix.dim().0 {
         let mut row_data = Vec::new();
@@ -34,7 +36,7 @@ pub fn save_bitmatrix_json(bitmatrix: &Array3<i8>, filename: &str) -> Result<(),
     Ok(())
 }
 
-pub fn save_as_json(bitmatrix: &Array3<i32>, base_filename: &str) -> Result<(), Box<dyn Error>> {
+pub fn save_as_json(bitmatrix: &Array3<i32>, base_filename: &str) -> Result<(), AnotoError> {
     let filename = format!("output/{}.json", base_filename);
     let data: Vec<Vec<Vec<i32>>> = bitmatrix.outer_iter().map(|row| row.outer_iter().map(|col| col.to_vec()).collect()).collect();
     let file = File::create(filename)?;
@@ -42,7 +44,7 @@ pub fn save_as_json(bitmatrix: &Array3<i32>, base_filename: &str) -> Result<(),
     Ok(())
 }
 
-pub fn save_as_txt(bitmatrix: &Array3<i32>, base_filename: &str) -> Result<(), Box<dyn Error>> {
+pub fn save_as_txt(bitmatrix: &Array3<i32>, base_filename: &str) -> Result<(), AnotoError> {
     let filename = format!("output/{}.txt", base_filename);
     let mut file = File::create(filename)?;
     for row in bitmatrix.outer_iter() {
@@ -54,7 +56,59 @@ pub fn save_as_txt(bitmatrix: &Array3<i32>, base_filename: &str) -> Result<(), B
     Ok(())
 }
 
-pub fn load_from_json(json_path: &str) -> Result<Array2<i32>, Box<dyn Error>> {
+/// Streams full-width row bands (`AnotoCodec::row_bands`) to `output/{base_filename}.json`
+/// in the `save_as_json` format, one band in memory at a time.
+pub fn save_tiles_as_json(bands: impl IntoIterator<Item = PatternTile>, base_filename: &str) -> Result<(), AnotoError> {
+    let mut file = BufWriter::new(File::create(format!("output/{}.json", base_filename))?);
+    write!(file, "[")?;
+    let mut first_row = true;
+    for band in bands {
+        check_band(&band)?;
+        for row in band.bits.outer_iter() {
+            if !first_row {
+                write!(file, ",")?;
+            }
+            first_row = false;
+            write!(file, "[")?;
+            for (c, cell) in row.outer_iter().enumerate() {
+                if c > 0 {
+                    write!(file, ",")?;
+                }
+                write!(file, "[{},{}]", cell[0], cell[1])?;
+            }
+            write!(file, "]")?;
+        }
+    }
+    write!(file, "]")?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Streams full-width row bands to `output/{base_filename}.txt` in the `save_as_txt` format.
+pub fn save_tiles_as_txt(bands: impl IntoIterator<Item = PatternTile>, base_filename: &str) -> Result<(), AnotoError> {
+    let mut file = BufWriter::new(File::create(format!("output/{}.txt", base_filename))?);
+    for band in bands {
+        check_band(&band)?;
+        for row in band.bits.outer_iter() {
+            for cell in row.outer_iter() {
+                write!(file, "[{} {}] ", cell[0], cell[1])?;
+            }
+            writeln!(file)?;
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+// Row-oriented formats can only append whole rows
+fn check_band(band: &PatternTile) -> Result<(), AnotoError> {
+    if band.col != 0 {
+        return Err(format!("Expected full-width row bands, got a tile at column {}", band.col).into());
+    }
+    Ok(())
+}
+
+pub fn load_from_json(json_path: &str) -> Result<Array2<i32>, AnotoError> {
     let file = File::open(json_path)?;
     let data: Vec<Vec<i32>> = serde_json::from_reader(file)?;
     let height = data.len();
@@ -68,7 +122,7 @@ pub fn load_from_json(json_path: &str) -> Result<Array2<i32>, Box<dyn Error>> {
     Ok(bitmatrix)
 }
 
-pub fn load_array3_from_json(json_path: &str) -> Result<Array3<i32>, Box<dyn Error>> {
+pub fn load_array3_from_json(json_path: &str) -> Result<Array3<i32>, AnotoError> {
     let file = File::open(json_path)?;
     let data: Vec<Vec<Vec<i32>>> = serde_json::from_reader(file)?;
     let height = data.len();
@@ -85,43 +139,55 @@ pub fn load_array3_from_json(json_path: &str) -> Result<Array3<i32>, Box<dyn Err
     Ok(bitmatrix)
 }
 
-pub fn load_6x6_section(file_path: &str) -> Result<Array3<i8>, Box<dyn Error>> {
+pub fn load_6x6_section(file_path: &str) -> Result<Array3<i8>, AnotoError> {
+    load_section(file_path, 6)
+}
+
+/// Loads an `order` x `order` section (the codec's window order) from JSON or TXT.
+pub fn load_section(file_path: &str, order: usize) -> Result<Array3<i8>, AnotoError> {
     // Try to determine file format by extension or content
     if file_path.ends_with(".json") {
         let array3_i32 = load_array3_from_json(file_path)?;
-        if array3_i32.dim() != (6, 6, 2) {
-            return Err("Section file must contain a 6x6x2 array".into());
+        if array3_i32.dim() != (order, order, 2) {
+            return Err(AnotoError::shape(format!("a {}x{}x2 section", order, order), format!("{:?}", array3_i32.dim())));
         }
         let array3_i8 = array3_i32.mapv(|x| x as i8);
         Ok(array3_i8)
     } else if file_path.ends_with(".txt") {
-        load_6x6_from_text(file_path)
+        load_section_from_text(file_path, order)
     } else {
         // Try JSON first, then TXT
         match load_array3_from_json(file_path) {
             Ok(array3_i32) => {
-                if array3_i32.dim() != (6, 6, 2) {
-                    return Err("Section file must contain a 6x6x2 array".into());
+                if array3_i32.dim() != (order, order, 2) {
+                    return Err(AnotoError::shape(format!("a {}x{}x2 section", order, order), format!("{:?}", array3_i32.dim())));
                 }
                 let array3_i8 = array3_i32.mapv(|x| x as i8);
                 Ok(array3_i8)
             }
-            Err(_) => load_6x6_from_text(file_path),
+            Err(_) => load_section_from_text(file_path, order),
         }
     }
 }
 
-pub fn load_6x6_from_text(file_path: &str) -> Result<Array3<i8>, Box<dyn Error>> {
+pub fn load_6x6_from_text(file_path: &str) -> Result<Array3<i8>, AnotoError> {
+    load_section_from_text(file_path, 6)
+}
+
+pub fn load_section_from_text(file_path: &str, order: usize) -> Result<Array3<i8>, AnotoError> {
     let file = File::open(file_path)?;
     let reader = BufReader::new(file);
     let mut data = Vec::new();
     
-    for line_result in reader.lines() {
-        let line = line_result?;
-        let line = line.trim();
+    for (line_index, line_result) in reader.lines().enumerate() {
+        let raw = line_result?;
+        let line = raw.trim();
         if line.is_empty() {
             continue;
         }
+        let line_number = line_index + 1;
+        // 1-based column of the current pair, for error messages
+        let mut column = raw.len() - raw.trim_start().len() + 1;
         
         let mut row_data = Vec::new();
         // Parse pairs like "[1 1] [1 0] ..."
@@ -141,25 +207,26 @@ pub fn load_6x6_from_text(file_path: &str) -> Result<Array3<i8>, Box<dyn Error>>
                 .collect();
             
             if nums.len() != 2 {
-                return Err(format!("Invalid pair format in line: {}", line).into());
+                return Err(AnotoError::parse(format!("Invalid pair '{}' in {}", pair, file_path), line_number, column));
             }
             
             row_data.push(nums);
+            column += pair.len() + "] [".len();
         }
         
-        if row_data.len() != 6 {
-            return Err(format!("Expected 6 pairs per row, got {} in line: {}", row_data.len(), line).into());
+        if row_data.len() != order {
+            return Err(AnotoError::shape(format!("{} pairs per row", order), format!("{} on line {} of {}", row_data.len(), line_number, file_path)));
         }
         
         data.push(row_data);
     }
     
-    if data.len() != 6 {
-        return Err(format!("Expected 6 rows, got {}", data.len()).into());
+    if data.len() != order {
+        return Err(AnotoError::shape(format!("{} rows", order), format!("{} in {}", data.len(), file_path)));
     }
     
     // Convert to Array3<i8>
-    let mut array3 = Array3::zeros((6, 6, 2));
+    let mut array3 = Array3::zeros((order, order, 2));
     for (i, row) in data.into_iter().enumerate() {
         for (j, pair) in row.into_iter().enumerate() {
             array3[[i, j, 0]] = pair[0];
@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Returned by long-running work when `JobProgress::cancel` was called.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+type Observer = Box<dyn Fn(&str, f32) + Send + Sync>;
+
+/// Progress and cancellation shared between a worker and whoever started it.
+/// Workers report units of work per stage; the observer hears about every 0.1% step.
+#[derive(Default)]
+pub struct JobProgress {
+    stage: Mutex<String>,
+    done: AtomicUsize,
+    total: AtomicUsize,
+    // Last reported fraction in permille, so the observer is not flooded
+    reported: AtomicUsize,
+    cancelled: AtomicBool,
+    observer: Option<Observer>,
+}
+
+impl JobProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `observer(stage, fraction)` as the work advances, from the worker threads.
+    pub fn with_observer(observer: impl Fn(&str, f32) + Send + Sync + 'static) -> Self {
+        JobProgress { observer: Some(Box::new(observer)), ..Self::default() }
+    }
+
+    /// Starts a new stage of `total` units.
+    pub fn start(&self, stage: &str, total: usize) {
+        *self.stage.lock().unwrap_or_else(|e| e.into_inner()) = stage.to_string();
+        self.done.store(0, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+        self.reported.store(0, Ordering::Relaxed);
+        self.notify(stage, 0.0);
+    }
+
+    pub fn advance(&self, units: usize) {
+        let done = self.done.fetch_add(units, Ordering::Relaxed) + units;
+        let fraction = self.fraction_of(done);
+        let permille = (fraction * 1000.0) as usize;
+        if self.reported.fetch_max(permille, Ordering::Relaxed) < permille {
+            self.notify(&self.stage(), fraction);
+        }
+    }
+
+    pub fn stage(&self) -> String {
+        self.stage.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Completed share of the current stage, 0.0..=1.0
+    pub fn fraction(&self) -> f32 {
+        self.fraction_of(self.done.load(Ordering::Relaxed))
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `Err(Cancelled)` once `cancel` was called; workers call this between units.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() { Err(Cancelled) } else { Ok(()) }
+    }
+
+    fn fraction_of(&self, done: usize) -> f32 {
+        match self.total.load(Ordering::Relaxed) {
+            0 => 1.0,
+            total => (done as f32 / total as f32).min(1.0),
+        }
+    }
+
+    fn notify(&self, stage: &str, fraction: f32) {
+        if let Some(observer) = &self.observer {
+            observer(stage, fraction);
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod job;
+pub use job::{Cancelled, JobProgress};
@@ -0,0 +1,268 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::codec::{AddressSpace, AnotoCodec, PageAddress};
+use crate::error::AnotoError;
+
+/// Registry file kept in the project directory
+pub const REGISTRY_FILE: &str = "anoto_registry.json";
+
+/// A pattern region handed to one document.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Allocation {
+    pub document: String,
+    /// Codec name; regions of different codecs never collide
+    pub codec: String,
+    /// (sect_u, sect_v), reduced modulo the MNS length
+    pub section: (i32, i32),
+    /// Pattern position (x, y) of the first dot, reduced modulo the period
+    pub origin: (i64, i64),
+    /// (width, height) in dots
+    pub size: (usize, usize),
+    /// Codec period; regions wrap around it. 0 in registries written before it was recorded
+    #[serde(default)]
+    pub period: i64,
+    #[serde(default)]
+    pub address: Option<PageAddress>,
+    /// Output the region was written to
+    pub file: String,
+    pub allocated_at: DateTime<Utc>,
+}
+
+impl Allocation {
+    /// The `size` region at `origin` in `section`, allocated now.
+    pub fn new(codec: &AnotoCodec, document: &str, section: (i32, i32), origin: (i64, i64), size: (usize, usize), file: &str) -> Self {
+        let mns_length = codec.mns_length as i32;
+        Allocation {
+            document: document.to_string(),
+            codec: codec.name().to_string(),
+            section: (section.0.rem_euclid(mns_length), section.1.rem_euclid(mns_length)),
+            origin: (codec.wrap_position(origin.0), codec.wrap_position(origin.1)),
+            size,
+            period: codec.period(),
+            address: None,
+            file: file.to_string(),
+            allocated_at: Utc::now(),
+        }
+    }
+
+    /// True when both regions share a dot of the same codec and section, counting the dots
+    /// past the end of the period from the start again.
+    pub fn overlaps(&self, other: &Allocation) -> bool {
+        // One codec has one period, older entries just don't know it
+        let period = self.period.max(other.period);
+        let intersect = |a: i64, a_size: usize, b: i64, b_size: usize| {
+            if period > 0 {
+                // Two runs on a circle meet when either one starts inside the other
+                let (a, b) = (a.rem_euclid(period), b.rem_euclid(period));
+                (b - a).rem_euclid(period) < a_size as i64 || (a - b).rem_euclid(period) < b_size as i64
+            } else {
+                a < b.saturating_add(b_size as i64) && b < a.saturating_add(a_size as i64)
+            }
+        };
+        self.codec == other.codec
+            && self.section == other.section
+            && intersect(self.origin.0, self.size.0, other.origin.0, other.size.0)
+            && intersect(self.origin.1, self.size.1, other.origin.1, other.size.1)
+    }
+
+    // Same document printing the same region again
+    fn same_region(&self, other: &Allocation) -> bool {
+        self.document == other.document && self.codec == other.codec && self.section == other.section && self.origin == other.origin && self.size == other.size
+    }
+}
+
+impl fmt::Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: section ({}, {}), origin ({}, {}), {}x{} dots",
+            self.document, self.section.0, self.section.1, self.origin.0, self.origin.1, self.size.0, self.size.1
+        )?;
+        if let Some(address) = &self.address {
+            write!(f, ", page {}", address)?;
+        }
+        if !self.codec.is_empty() {
+            write!(f, ", codec {}", self.codec)?;
+        }
+        write!(f, ", {} at {}", self.file, self.allocated_at.format("%Y-%m-%d %H:%M:%S UTC"))
+    }
+}
+
+/// File-backed record of the pattern regions allocated to documents, so that no two documents
+/// are printed with the same dots.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    pub allocations: Vec<Allocation>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Registry {
+    /// Reads the registry at `path`; a missing file is an empty registry.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AnotoError> {
+        let path = path.as_ref().to_path_buf();
+        let mut registry = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str::<Registry>(&json).map_err(|e| AnotoError::from(e).in_file(path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Registry::default(),
+            Err(e) => return Err(e.into()),
+        };
+        registry.path = path;
+        Ok(registry)
+    }
+
+    /// Writes the registry back, replacing the file only once the new one is complete.
+    pub fn save(&self) -> Result<(), AnotoError> {
+        let partial = self.path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+
+    /// Allocations of other documents that share dots with `allocation`.
+    pub fn collisions(&self, allocation: &Allocation) -> Vec<&Allocation> {
+        self.allocations.iter().filter(|a| a.document != allocation.document && a.overlaps(allocation)).collect()
+    }
+
+    /// Errors listing the collisions of `allocation` unless there are none or `reuse` is set.
+    pub fn check(&self, allocation: &Allocation, reuse: bool) -> Result<(), AnotoError> {
+        let collisions = self.collisions(allocation);
+        if collisions.is_empty() || reuse {
+            return Ok(());
+        }
+        let holders = collisions.iter().map(|a| a.to_string()).collect::<Vec<_>>().join("; ");
+        Err(format!("The pattern for {} is already allocated to {}. Pick a free region or allow reuse", allocation.document, holders).into())
+    }
+
+    /// Adds `allocation` and saves the registry. Printing the same region for the same document
+    /// again only refreshes its entry.
+    pub fn record(&mut self, allocation: Allocation) -> Result<(), AnotoError> {
+        match self.allocations.iter_mut().find(|a| a.same_region(&allocation)) {
+            Some(existing) => *existing = allocation,
+            None => self.allocations.push(allocation),
+        }
+        self.save()
+    }
+
+    /// First section from `start`, counting up in sect_v and then sect_u, whose `size` region at
+    /// the origin no other document holds yet. `document` names the document a section would be
+    /// recorded for, so that a document finds the section it already holds again.
+    pub fn fresh_section(&self, codec: &AnotoCodec, document: impl Fn((i32, i32)) -> String, start: (i32, i32), size: (usize, usize)) -> Option<(i32, i32)> {
+        let mns_length = codec.mns_length as i32;
+        let first = start.0.rem_euclid(mns_length) * mns_length + start.1.rem_euclid(mns_length);
+        (0..mns_length * mns_length)
+            .map(|i| (first + i) % (mns_length * mns_length))
+            .map(|segment| (segment / mns_length, segment % mns_length))
+            .find(|&section| self.collisions(&Allocation::new(codec, &document(section), section, (0, 0), size, "")).is_empty())
+    }
+
+    /// First page of `space`, from the first page of `segment` on, that no document holds yet.
+    pub fn fresh_page(&self, codec: &AnotoCodec, document: &str, space: &AddressSpace, segment: u64) -> Option<PageAddress> {
+        let (columns, rows) = space.pages_per_section();
+        let pages_per_shelf = space.pages_per_book.saturating_mul(space.books_per_shelf);
+        (0..columns.saturating_mul(rows)).find_map(|index| {
+            let address = PageAddress {
+                segment,
+                shelf: index / pages_per_shelf,
+                book: index % pages_per_shelf / space.pages_per_book,
+                page: index % space.pages_per_book,
+            };
+            let (section, origin) = space.page_origin(&address).ok()?;
+            self.collisions(&Allocation::new(codec, document, section, origin, space.page_size, "")).is_empty().then_some(address)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::anoto_6x6_a4_fixed;
+
+    fn allocation(document: &str, section: (i32, i32), origin: (i64, i64), size: (usize, usize)) -> Allocation {
+        Allocation::new(&anoto_6x6_a4_fixed(), document, section, origin, size, "")
+    }
+
+    fn holding(allocations: Vec<Allocation>) -> Registry {
+        Registry { allocations, ..Default::default() }
+    }
+
+    #[test]
+    fn overlaps_needs_a_shared_dot() {
+        let a = allocation("a", (10, 2), (0, 0), (100, 50));
+        assert!(a.overlaps(&allocation("b", (10, 2), (99, 49), (10, 10))));
+        assert!(!a.overlaps(&allocation("b", (10, 2), (100, 0), (10, 10))));
+        assert!(!a.overlaps(&allocation("b", (10, 2), (0, 50), (10, 10))));
+        assert!(!a.overlaps(&allocation("b", (10, 3), (0, 0), (100, 50))));
+        // Sections are compared modulo the MNS length
+        let mns_length = anoto_6x6_a4_fixed().mns_length as i32;
+        assert!(a.overlaps(&allocation("b", (10 + mns_length, 2 - mns_length), (0, 0), (1, 1))));
+
+        let mut other_codec = allocation("b", (10, 2), (0, 0), (100, 50));
+        other_codec.codec = "other".to_string();
+        assert!(!a.overlaps(&other_codec));
+    }
+
+    #[test]
+    fn overlaps_wraps_around_the_period() {
+        let period = anoto_6x6_a4_fixed().period();
+        // Origins name the same dots whichever way they are written
+        let a = allocation("a", (10, 2), (-10, 0), (20, 20));
+        assert_eq!(a.origin, (period - 10, 0));
+        assert!(a.overlaps(&allocation("b", (10, 2), (5, 5), (1, 1))));
+        assert!(a.overlaps(&allocation("b", (10, 2), (period - 1, period), (1, 1))));
+        assert!(!a.overlaps(&allocation("b", (10, 2), (10, 0), (period as usize - 20, 20))));
+
+        // Entries written before the period was recorded take it from the other side
+        let mut old = allocation("b", (10, 2), (period - 5, 0), (1, 1));
+        old.period = 0;
+        assert!(old.overlaps(&a));
+    }
+
+    #[test]
+    fn check_refuses_other_documents_unless_reused() {
+        let registry = holding(vec![allocation("a", (10, 2), (0, 0), (100, 100))]);
+        let clash = allocation("b", (10, 2), (50, 50), (100, 100));
+        assert!(matches!(registry.check(&clash, false), Err(AnotoError::Invalid(message)) if message.contains("a: section (10, 2)")));
+        assert!(registry.check(&clash, true).is_ok());
+        // A document never collides with itself
+        assert!(registry.check(&allocation("a", (10, 2), (50, 50), (100, 100)), false).is_ok());
+        assert!(registry.check(&allocation("b", (10, 2), (100, 0), (100, 100)), false).is_ok());
+    }
+
+    #[test]
+    fn fresh_section_skips_held_sections() {
+        let codec = anoto_6x6_a4_fixed();
+        let mns_length = codec.mns_length as i32;
+        let registry = holding(vec![
+            allocation("a", (10, 2), (0, 0), (50, 50)),
+            allocation("b", (10, 3), (40, 40), (50, 50)),
+            allocation("c", (10, 4), (50, 50), (50, 50)),
+        ]);
+        let named = |name: &'static str| move |_: (i32, i32)| name.to_string();
+        assert_eq!(registry.fresh_section(&codec, named("d"), (10, 2), (50, 50)), Some((10, 4)));
+        assert_eq!(registry.fresh_section(&codec, named("b"), (10, 2), (50, 50)), Some((10, 3)));
+        assert_eq!(registry.fresh_section(&codec, named("d"), (10, 2), (60, 60)), Some((10, 5)));
+        // The search carries on into the next sect_u and wraps around the last section
+        let last = holding(vec![allocation("a", (mns_length - 1, mns_length - 1), (0, 0), (1, 1))]);
+        assert_eq!(last.fresh_section(&codec, named("d"), (-1, -1), (1, 1)), Some((0, 0)));
+    }
+
+    #[test]
+    fn fresh_page_skips_held_pages() {
+        let codec = anoto_6x6_a4_fixed();
+        let space = AddressSpace::new(&codec, AddressSpace::DEFAULT_PAGE_SIZE).unwrap();
+        let page = |page| PageAddress { segment: 5, shelf: 0, book: 0, page };
+        let held = |page| {
+            let (section, origin) = space.page_origin(&page).unwrap();
+            allocation("a", section, origin, space.page_size)
+        };
+        assert_eq!(holding(Vec::new()).fresh_page(&codec, "b", &space, 5), Some(page(0)));
+
+        let registry = holding(vec![held(page(0)), held(page(1))]);
+        assert_eq!(registry.fresh_page(&codec, "b", &space, 5), Some(page(2)));
+        assert_eq!(registry.fresh_page(&codec, "a", &space, 5), Some(page(0)));
+        assert_eq!(registry.fresh_page(&codec, "b", &space, 6), Some(PageAddress { segment: 6, ..page(0) }));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod allocations;
+pub use allocations::{Allocation, REGISTRY_FILE, Registry};